@@ -20,7 +20,7 @@ use godot::{
 use netman::NetmanVariant;
 use ron::ser::PrettyConfig;
 use tokio::runtime::{EnterGuard, Runtime};
-use tracing::{info, Level};
+use tracing::{info, warn, Level};
 use tracing_subscriber::FmtSubscriber;
 use ui::{resources::InputStateRes, Ui};
 use universe::{
@@ -97,6 +97,7 @@ struct GameClass {
 
 const TEMP_SAVE: &'static str = "tmp.universe";
 const TEMP_SAVE_2: &'static str = "tmp2.universe";
+const TEMP_SAVE_2_BACKUP: &'static str = "tmp2.universe.bak";
 
 #[godot_api]
 impl Node3DVirtual for GameClass {
@@ -124,9 +125,17 @@ impl Node3DVirtual for GameClass {
         //     info!("Trying to deserialize universe");
         //     Some(bincode::deserialize_from(file).expect("can deserialize"))
         // });
-        let maybe_universe = fs::read_to_string(TEMP_SAVE_2)
-            .map(|x| ron::from_str(&x).expect("can deserialize"))
-            .ok();
+        let maybe_universe = fs::read_to_string(TEMP_SAVE_2).ok().and_then(|x| {
+            ron::from_str(&x)
+                .map_err(|err| {
+                    // Keep the unreadable save around, as it's going to be overwritten on exit.
+                    warn!("Can't load {TEMP_SAVE_2}, moving it to {TEMP_SAVE_2_BACKUP} and starting a new universe: {err}");
+                    if let Err(err) = fs::rename(TEMP_SAVE_2, TEMP_SAVE_2_BACKUP) {
+                        panic!("Can't move {TEMP_SAVE_2} to {TEMP_SAVE_2_BACKUP}: {err}");
+                    }
+                })
+                .ok()
+        });
 
         let universe = maybe_universe.unwrap_or_else(|| {
             let mut universe = Universe::new();
@@ -158,7 +167,7 @@ impl Node3DVirtual for GameClass {
         //             for ent in query.iter() {
         //                 if ent != default.0 .0 {
         //                     commands.submit(move |world| {
-        //                         world.despawn(ent);
+        //                         world.despawn_recursive(ent);
         //                     });
         //                 }
         //             }
@@ -3,6 +3,7 @@ use std::{
     time::{Duration, Instant},
 };
 
+use engine_ecs::Schedule;
 use tokio::{
     net::{TcpListener, TcpStream, ToSocketAddrs},
     sync::mpsc,
@@ -14,8 +15,9 @@ use crate::{
     enter_runtime, get_runtime,
     netman::net::EndpointId,
     universe::{
-        mcs::PlayerID, ui_events::UiEventCtx, OwnedUniverseEvent, Universe, UniverseEvent,
-        TICK_TIME,
+        mcs::{ComponentStorage, PlayerID},
+        ui_events::UiEventCtx,
+        OwnedUniverseEvent, Universe, UniverseEvent, TICK_TIME,
     },
 };
 
@@ -41,6 +43,7 @@ type CRemoteEndpoint = RemoteEndpoint<SentByServer, SentByClient>;
 /// Implementation of client-side netmanager.
 pub struct Client {
     endpoint: CRemoteEndpoint,
+    schedule: Schedule<ComponentStorage>,
     my_id: Option<PlayerID>,
 
     event_queue: VecDeque<PartialEvent>,
@@ -58,6 +61,7 @@ enum PartialEvent {
 /// Implementation of server-side netmanager.
 pub struct Server {
     new_connections: mpsc::Receiver<SRemoteEndpoint>,
+    schedule: Schedule<ComponentStorage>,
     endpoints: Vec<SRemoteEndpoint>,
     event_queue: VecDeque<QueuedEvent>,
     listener_task: AbortHandle,
@@ -74,6 +78,8 @@ impl Client {
                     self.last_step = Instant::now();
                     info!("Setting new universe...");
                     *universe = new_universe;
+                    // Locals of systems belong to the old universe.
+                    self.schedule = Universe::schedule();
                     info!("Clearing queues...");
                     self.event_queue.clear();
                     self.pending_steps = 0;
@@ -94,7 +100,7 @@ impl Client {
                 }
             }
         }
-        let mut update_ctx = universe.update_ctx();
+        let mut update_ctx = universe.update_ctx(&mut self.schedule);
         while !self.event_queue.is_empty() && self.last_step.elapsed() > Duration::ZERO {
             match self.event_queue.pop_front().unwrap() {
                 PartialEvent::Step => {
@@ -166,7 +172,7 @@ impl Server {
             warn!("Lag detected - skipping 10+ ticks");
         }
 
-        let mut update_ctx = universe.update_ctx();
+        let mut update_ctx = universe.update_ctx(&mut self.schedule);
         while !self.event_queue.is_empty() {
             let has_space = self.endpoints.iter().all(|x| x.has_space());
             if !has_space {
@@ -237,6 +243,7 @@ impl NetmanVariant {
 
         Ok(Self::Server(Server {
             new_connections,
+            schedule: Universe::schedule(),
             listener_task,
             endpoints: Vec::new(),
             event_queue,
@@ -255,6 +262,7 @@ impl NetmanVariant {
         })?;
         Ok(Self::Client(Client {
             endpoint,
+            schedule: Universe::schedule(),
             my_id: None,
             event_queue: Default::default(),
             pending_steps: 0,
@@ -1,6 +1,6 @@
 use std::{mem, sync::Arc};
 
-use engine_ecs::{World, WorldRun};
+use engine_ecs::{system, Schedule, World};
 use godot::prelude::{Gd, Node3D};
 use universe::mcs::PlayerID;
 
@@ -18,8 +18,9 @@ use self::{
             build_mode_switch, building_facing, building_placer, building_remover,
             building_selector,
         },
-        player_controls, update_current_vessel, update_player_positions, update_players_on_vessel,
-        upload_current_vessel_tiles, vessel_upload_condition,
+        player_controls, update_current_vessel, update_current_vessel_buildings,
+        update_player_positions, update_players_on_vessel, upload_current_vessel_tiles,
+        vessel_upload_condition,
     },
 };
 
@@ -28,21 +29,36 @@ mod systems;
 
 pub struct Ui {
     world: World<uecs::ComponentStorage>,
+    update_schedule: Schedule<uecs::ComponentStorage>,
+    render_schedule: Schedule<uecs::ComponentStorage>,
     first_update: bool,
 }
 
-#[derive(Hash, PartialEq, Eq, Debug, Clone)]
-struct UpdateSchedule;
-
-#[derive(Hash, PartialEq, Eq, Debug, Clone)]
-struct RenderSchedule;
-
 impl Ui {
     pub fn new() -> Self {
         let world = World::new();
 
+        let update_schedule = Schedule::new()
+            .with_system(system!(update_current_vessel))
+            .with_system(system!(update_players_on_vessel))
+            .with_system(
+                system!(upload_current_vessel_tiles)
+                    .label("vessel_upload")
+                    .run_if(system!(vessel_upload_condition)),
+            )
+            .with_system(system!(update_current_vessel_buildings).after("vessel_upload"))
+            .with_system(system!(player_controls))
+            .with_system(system!(building_facing).label("building"))
+            .with_system(system!(building_selector).label("building"))
+            .with_system(system!(building_placer).label("building"))
+            .with_system(system!(building_remover).label("building"))
+            .with_system(system!(build_mode_switch).after("building"));
+        let render_schedule = Schedule::new().with_system(system!(update_player_positions));
+
         Self {
             world,
+            update_schedule,
+            render_schedule,
             first_update: true,
         }
     }
@@ -54,17 +70,18 @@ impl Ui {
         root: Gd<Node3D>,
         my_id: PlayerID,
     ) {
-        *self.world.resource_mut() = UniverseRes(Some(universe));
+        self.world.insert_resource(UniverseRes(universe));
         *self.world.resource_mut() = input;
-        *self.world.resource_mut() = SceneTreeRes(Some(root.get_tree().unwrap()));
-        *self.world.resource_mut() = RootNodeRes(Some(root));
+        self.world
+            .insert_resource(SceneTreeRes(root.get_tree().unwrap()));
+        self.world.insert_resource(RootNodeRes(root));
         *self.world.resource_mut() = CurrentPlayerRes(Some(my_id));
         *self.world.resource_mut() = UniverseEventStorageRes(Vec::new());
     }
     pub fn remove_temporal_resources(&mut self) -> Vec<universe::UniverseEvent> {
-        *self.world.resource_mut() = UniverseRes(None);
-        *self.world.resource_mut() = SceneTreeRes(None);
-        *self.world.resource_mut() = RootNodeRes(None);
+        self.world.remove_resource::<UniverseRes>();
+        self.world.remove_resource::<SceneTreeRes>();
+        self.world.remove_resource::<RootNodeRes>();
         *self.world.resource_mut() = CurrentPlayerRes(None);
 
         mem::take(self.world.resource_mut::<UniverseEventStorageRes>()).0
@@ -74,24 +91,11 @@ impl Ui {
         self.world.next_cycle();
 
         *self.world.resource_mut() = EvCtxRes(evctx);
-        let query_world = self.world.query_world();
-        query_world.run(update_current_vessel);
-        query_world.run(update_players_on_vessel);
-        let upload_cond = query_world.run(vessel_upload_condition);
-        if upload_cond {
-            query_world.run(upload_current_vessel_tiles);
-        }
-        query_world.run(player_controls);
-        query_world.run(building_facing);
-        query_world.run(building_selector);
-        query_world.run(building_placer);
-        query_world.run(building_remover);
-        query_world.run(build_mode_switch);
+        self.update_schedule.run(&mut self.world);
 
         self.first_update = false;
     }
     pub fn on_render(&mut self) {
-        let query_world = self.world.query_world();
-        query_world.run(update_player_positions);
+        self.render_schedule.run(&mut self.world);
     }
 }
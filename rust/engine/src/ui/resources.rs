@@ -12,14 +12,13 @@ use godot::{
 
 use crate::universe::{rotations::BuildingFacing, ui_events::UiEventCtx, Universe, UniverseEvent};
 
-#[derive(Default)]
-pub struct UniverseRes(pub Option<Arc<Universe>>);
+pub struct UniverseRes(pub Arc<Universe>);
 
 impl ops::Deref for UniverseRes {
     type Target = Universe;
 
     fn deref(&self) -> &Self::Target {
-        self.0.as_ref().unwrap()
+        &self.0
     }
 }
 
@@ -34,39 +33,11 @@ pub struct CurrentPlayerRes(pub Option<PlayerID>);
 #[derive(Deref, DerefMut, Default)]
 pub struct CurrentVesselRes(pub VesselID);
 
-#[derive(Default)]
-pub struct RootNodeRes(pub Option<Gd<Node3D>>);
-
-impl ops::Deref for RootNodeRes {
-    type Target = Gd<Node3D>;
-
-    fn deref(&self) -> &Self::Target {
-        self.0.as_ref().unwrap()
-    }
-}
-
-impl ops::DerefMut for RootNodeRes {
-    fn deref_mut(&mut self) -> &mut Gd<Node3D> {
-        self.0.as_mut().unwrap()
-    }
-}
-
-#[derive(Default)]
-pub struct SceneTreeRes(pub Option<Gd<SceneTree>>);
-
-impl ops::Deref for SceneTreeRes {
-    type Target = Gd<SceneTree>;
-
-    fn deref(&self) -> &Self::Target {
-        self.0.as_ref().unwrap()
-    }
-}
+#[derive(Deref, DerefMut)]
+pub struct RootNodeRes(pub Gd<Node3D>);
 
-impl ops::DerefMut for SceneTreeRes {
-    fn deref_mut(&mut self) -> &mut Gd<SceneTree> {
-        self.0.as_mut().unwrap()
-    }
-}
+#[derive(Deref, DerefMut)]
+pub struct SceneTreeRes(pub Gd<SceneTree>);
 
 #[derive(Default)]
 pub struct PlayerNodeRes {
@@ -117,11 +88,6 @@ pub struct CurrentTileIndexRes(pub usize);
 #[derive(Deref, DerefMut, Default)]
 pub struct CurrentPlayerRotationRes(pub f32);
 
-#[derive(Default)]
-pub struct PlacerRes {
-    pub temp_build_node: Option<Gd<Node3D>>,
-}
-
 #[derive(Default, Clone, Copy, PartialEq, Eq)]
 pub enum BuildingMode {
     #[default]
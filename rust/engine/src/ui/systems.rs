@@ -87,18 +87,60 @@ pub fn upload_current_vessel_tiles(
         node.add_to_group("static".into());
         root_node.add_child(node.upcast());
     }
+}
+
+fn add_building_node(root_node: &mut RootNodeRes, entity: EntityID, building: &Building) {
+    let device = Registry::instance().scene_by_building_kind(building.kind);
+    let mut node = device.instantiate().unwrap().cast::<BaseStaticBody>();
+    node.bind_mut().kind = Some(crate::BodyKind::Building { entity });
+    node.set_position(building.position.to_godot());
+    node.set_basis(building.orientation.to_basis().for_buildings().to_godot());
+    node.add_to_group("buildings".into());
+
+    root_node.add_child(node.upcast());
+}
 
+/// Creates and frees nodes only for buildings that were spawned or despawned, unless current vessel changes.
+pub fn update_current_vessel_buildings(
+    universe: &UniverseRes,
+    current_vessel: &CurrentVesselRes,
+    evctx: &EvCtxRes,
+    scene_tree: &mut SceneTreeRes,
+    root_node: &mut RootNodeRes,
+    changes: Changes,
+) {
     let qword = universe.world.query_world_shared();
     let mut buildings = qword.parameter::<mcs::Query<(EntityID, &Building)>>();
-    for (entity, building) in buildings.iter() {
-        let device = registry.scene_by_building_kind(building.kind);
-        let mut node = device.instantiate().unwrap().cast::<BaseStaticBody>();
-        node.bind_mut().kind = Some(crate::BodyKind::Building { entity });
-        node.set_position(building.position.to_godot());
-        node.set_basis(building.orientation.to_basis().for_buildings().to_godot());
-        node.add_to_group("static".into());
 
-        root_node.add_child(node.upcast());
+    if changes.resource_changed::<CurrentVesselRes>() {
+        for mut shown in &mut scene_tree.iter_group::<Node>("buildings") {
+            shown.queue_free()
+        }
+        for (entity, building) in buildings.iter_children_of(current_vessel.0 .0) {
+            add_building_node(root_node, entity, building);
+        }
+        return;
+    }
+
+    let despawned = &evctx.buildings_despawned;
+    if !despawned.is_empty() {
+        for mut shown in &mut scene_tree.iter_group::<BaseStaticBody>("buildings") {
+            let kind = shown.bind().kind;
+            if let Some(crate::BodyKind::Building { entity }) = kind {
+                if despawned.contains(&entity) {
+                    shown.queue_free()
+                }
+            }
+        }
+    }
+
+    for &entity in &evctx.buildings_spawned {
+        let Some((_, building)) = buildings.get(entity) else {
+            continue;
+        };
+        if building.vessel() == current_vessel.0 {
+            add_building_node(root_node, entity, building);
+        }
     }
 }
 
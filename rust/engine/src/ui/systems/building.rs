@@ -1,7 +1,7 @@
 use crate::{
     ui::{
         resources::{CurrentBuildingIndexRes, CurrentBuildingRotationRes, CurrentTileIndexRes},
-        uecs::Changes,
+        uecs::{Changes, Local},
     },
     universe,
     util::RegistryExt,
@@ -10,14 +10,17 @@ use std::f32::consts::PI;
 
 use engine_registry::Registry;
 use engine_universe::{rotations::BuildingOrientation, tilemap::TilePos};
-use godot::engine::{load, Input, Node3D, PackedScene, RayCast3D};
+use godot::{
+    engine::{load, Input, Node3D, PackedScene, RayCast3D},
+    prelude::{Gd, InstanceId},
+};
 use tracing::info;
 
 use crate::{
     ui::{
         resources::{
-            BuildingMode, CurrentFacingRes, CurrentPlayerRotationRes, PlacerRes, PlayerNodeRes,
-            RootNodeRes, UniverseEventStorageRes,
+            BuildingMode, CurrentFacingRes, CurrentPlayerRotationRes, PlayerNodeRes, RootNodeRes,
+            UniverseEventStorageRes,
         },
         uecs::Commands,
     },
@@ -105,11 +108,25 @@ pub fn building_selector(commands: Commands, mode: &BuildingMode) {
     }
 }
 
+/// Preview of the building that is about to be placed.
+///
+/// Keeps instance id rather than `Gd`, as local state has to be `Send`.
+#[derive(Default)]
+pub struct Placer {
+    temp_build_node: Option<InstanceId>,
+}
+
+impl Placer {
+    fn temp_build_node(&self) -> Option<Gd<Node3D>> {
+        self.temp_build_node.map(Gd::from_instance_id)
+    }
+}
+
 pub fn building_placer(
     player_node: &mut PlayerNodeRes,
     events: &mut UniverseEventStorageRes,
     root_node: &mut RootNodeRes,
-    local: &mut PlacerRes,
+    mut local: Local<Placer>,
     current_facing: &CurrentFacingRes,
     current_rotation: &CurrentBuildingRotationRes,
     current_building: &CurrentBuildingIndexRes,
@@ -123,7 +140,7 @@ pub fn building_placer(
     if changes.resource_changed::<BuildingMode>()
         || changes.resource_changed::<CurrentBuildingIndexRes>()
     {
-        if let Some(temp_node) = &mut local.temp_build_node {
+        if let Some(mut temp_node) = local.temp_build_node() {
             temp_node.queue_free();
             local.temp_build_node = None;
         }
@@ -153,10 +170,10 @@ pub fn building_placer(
         let node = scene.instantiate().unwrap();
         root_node.add_child(node.clone());
         let node = node.cast::<Node3D>();
-        local.temp_build_node = Some(node);
+        local.temp_build_node = Some(node.instance_id());
     }
 
-    if let Some(b_node) = &mut local.temp_build_node {
+    if let Some(mut b_node) = local.temp_build_node() {
         b_node.set_position(place_pos_q);
         let rotated_basis = current_facing.to_basis().rotate_by(current_rotation.0);
         let final_basis = match *mode {
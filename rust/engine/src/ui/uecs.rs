@@ -3,8 +3,8 @@ use engine_ecs::gen_storage_for_world;
 use super::resources::{
     BuildingMode, CurrentBuildingIndexRes, CurrentBuildingRotationRes, CurrentFacingRes,
     CurrentPlayerRes, CurrentPlayerRotationRes, CurrentTileIndexRes, CurrentVesselRes, DtRes,
-    EvCtxRes, InputStateRes, PlacerRes, PlayerNodeRes, RootNodeRes, SceneTreeRes,
-    UniverseEventStorageRes, UniverseRes,
+    EvCtxRes, InputStateRes, PlayerNodeRes, RootNodeRes, SceneTreeRes, UniverseEventStorageRes,
+    UniverseRes,
 };
 
 gen_storage_for_world!(
@@ -14,7 +14,8 @@ gen_storage_for_world!(
 
     : resources
         CurrentFacingRes CurrentPlayerRes CurrentPlayerRotationRes CurrentVesselRes DtRes
-        EvCtxRes InputStateRes PlayerNodeRes RootNodeRes SceneTreeRes UniverseEventStorageRes
-        UniverseRes PlacerRes BuildingMode CurrentBuildingRotationRes CurrentBuildingIndexRes
-        CurrentTileIndexRes
+        EvCtxRes InputStateRes PlayerNodeRes UniverseEventStorageRes BuildingMode
+        CurrentBuildingRotationRes CurrentBuildingIndexRes CurrentTileIndexRes
+    : optional_resources
+        RootNodeRes SceneTreeRes UniverseRes
 );
@@ -1,5 +1,6 @@
-use super::internal::ComponentStorageProvider;
+use super::internal::{ComponentStorageProvider, DynDispath};
 use super::ArchetypeID;
+use super::EntityID;
 use super::LocalTypeIndex;
 use crate::TypeIndex;
 use crate::World;
@@ -15,11 +16,32 @@ where
 {
 }
 
+/// Component that is stored in archetype columns rather than in a sparse set, so it can be accessed in chunks.
+pub trait TableComponent<Storage>: Component<Storage> {}
+
 pub type TypeIndexStorage = SmallVec<[TypeIndex; 8]>;
 
 pub trait Bundle<Storage> {
     fn type_ids() -> TypeIndexStorage;
-    fn add_to_archetype_in_storage(self, world: &mut World<Storage>, archetype: ArchetypeID);
+    /// Adds components to the archetype `entity` was just moved to, or to sparse sets.
+    fn add_to_archetype_in_storage(
+        self,
+        world: &mut World<Storage>,
+        archetype: ArchetypeID,
+        entity: EntityID,
+    );
+    /// Same as `add_to_archetype_in_storage` for many bundles at once, `entities` are their IDs in the same order.
+    fn add_many_to_archetype_in_storage(
+        bundles: Vec<Self>,
+        world: &mut World<Storage>,
+        archetype: ArchetypeID,
+        entities: &[EntityID],
+    ) where
+        Self: Sized;
+    /// Splits bundle into separate components, so that it can be stored in a `CommandG`.
+    fn into_components(self, components: &mut Vec<Storage::AnyComponent>)
+    where
+        Storage: DynDispath;
 }
 
 impl<Storage> Bundle<Storage> for () {
@@ -27,7 +49,27 @@ impl<Storage> Bundle<Storage> for () {
         TypeIndexStorage::new()
     }
 
-    fn add_to_archetype_in_storage(self, _world: &mut World<Storage>, _archetype: ArchetypeID) {}
+    fn add_to_archetype_in_storage(
+        self,
+        _world: &mut World<Storage>,
+        _archetype: ArchetypeID,
+        _entity: EntityID,
+    ) {
+    }
+
+    fn add_many_to_archetype_in_storage(
+        _bundles: Vec<Self>,
+        _world: &mut World<Storage>,
+        _archetype: ArchetypeID,
+        _entities: &[EntityID],
+    ) {
+    }
+
+    fn into_components(self, _components: &mut Vec<Storage::AnyComponent>)
+    where
+        Storage: DynDispath,
+    {
+    }
 }
 
 gen_bundle_tuple_impls!(1);
@@ -36,3 +78,13 @@ gen_bundle_tuple_impls!(3);
 gen_bundle_tuple_impls!(4);
 gen_bundle_tuple_impls!(5);
 gen_bundle_tuple_impls!(6);
+gen_bundle_tuple_impls!(7);
+gen_bundle_tuple_impls!(8);
+gen_bundle_tuple_impls!(9);
+gen_bundle_tuple_impls!(10);
+gen_bundle_tuple_impls!(11);
+gen_bundle_tuple_impls!(12);
+gen_bundle_tuple_impls!(13);
+gen_bundle_tuple_impls!(14);
+gen_bundle_tuple_impls!(15);
+gen_bundle_tuple_impls!(16);
@@ -29,6 +29,9 @@ impl<T> EcsCell<T> {
     pub(crate) unsafe fn get_mut_unsafe(&self) -> &mut T {
         unsafe { &mut *self.inner.get() }
     }
+    pub(crate) fn as_ptr(&self) -> *mut T {
+        self.inner.get()
+    }
 }
 
 unsafe impl<T: ?Sized + Send> Send for EcsCell<T> {}
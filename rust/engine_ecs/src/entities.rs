@@ -0,0 +1,244 @@
+use serde::{de, ser::SerializeSeq, Deserialize, Serialize};
+
+use crate::{ArchetypeID, EntityID, EntityInfo};
+
+/// Location of every entity, along with IDs that were reserved but not spawned yet.
+///
+/// Laid out like a slot map: an ID is an index of a slot and its version, which is odd while the slot is in use.
+/// Vacant slots are reused lowest index first, so that which IDs come next only depends on the slots,
+/// which is all that is saved. The first slot is never used, like in the slot map saves were made with.
+///
+/// IDs are handed out ahead of time without changing anything, see `candidate`, and `claim`ed once they are used.
+/// Claimed IDs are stored with `RESERVED` archetype, and are invisible until spawned.
+#[derive(Clone)]
+pub(crate) struct Entities {
+    slots: Vec<Slot>,
+    /// Indices of vacant slots in ascending order, not including the first slot.
+    free: Vec<u32>,
+    /// Number of spawned entities.
+    len: usize,
+}
+
+#[derive(Clone, Copy)]
+struct Slot {
+    version: u32,
+    info: Option<EntityInfo>,
+}
+
+const RESERVED: EntityInfo = EntityInfo {
+    archetype_id: ArchetypeID(u32::MAX),
+    in_archetype_id: 0,
+};
+
+impl EntityInfo {
+    fn is_reserved(&self) -> bool {
+        self.archetype_id == RESERVED.archetype_id
+    }
+}
+
+fn split(entity: EntityID) -> (usize, u32) {
+    let raw = entity.to_raw();
+    ((raw & 0xffff_ffff) as usize, (raw >> 32) as u32)
+}
+
+fn join(index: usize, version: u32) -> EntityID {
+    EntityID::from_raw((version as u64) << 32 | index as u64)
+}
+
+impl Default for Entities {
+    fn default() -> Self {
+        Self {
+            slots: vec![Slot {
+                version: 0,
+                info: None,
+            }],
+            free: Vec::new(),
+            len: 0,
+        }
+    }
+}
+
+impl Entities {
+    fn slot(&self, entity: EntityID) -> Option<&Slot> {
+        let (index, version) = split(entity);
+        self.slots
+            .get(index)
+            .filter(|slot| slot.version == version && slot.info.is_some())
+    }
+
+    pub(crate) fn get(&self, entity: EntityID) -> Option<EntityInfo> {
+        self.slot(entity)
+            .and_then(|slot| slot.info)
+            .filter(|info| !info.is_reserved())
+    }
+    pub(crate) fn get_mut(&mut self, entity: EntityID) -> Option<&mut EntityInfo> {
+        let (index, version) = split(entity);
+        self.slots
+            .get_mut(index)
+            .filter(|slot| slot.version == version)
+            .and_then(|slot| slot.info.as_mut())
+            .filter(|info| !info.is_reserved())
+    }
+    pub(crate) fn contains(&self, entity: EntityID) -> bool {
+        self.get(entity).is_some()
+    }
+    pub(crate) fn is_reserved(&self, entity: EntityID) -> bool {
+        self.slot(entity)
+            .and_then(|slot| slot.info)
+            .is_some_and(|info| info.is_reserved())
+    }
+    /// Number of spawned entities.
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// ID that the `n`-th next reservation would get, if nothing changes in the meantime.
+    ///
+    /// Takes vacant slots first, then new ones after the last slot.
+    pub(crate) fn candidate(&self, n: usize) -> EntityID {
+        match self.free.get(n) {
+            Some(&index) => join(index as usize, self.slots[index as usize].version + 1),
+            None => join(self.slots.len() + n - self.free.len(), 1),
+        }
+    }
+    /// Reserves an ID returned by `candidate`. Returns false if it's no longer vacant.
+    pub(crate) fn claim(&mut self, entity: EntityID) -> bool {
+        let (index, version) = split(entity);
+        if index == 0 || index >= u32::MAX as usize {
+            return false;
+        }
+        if index < self.slots.len() {
+            let slot = &mut self.slots[index];
+            if slot.info.is_some() || slot.version + 1 != version {
+                return false;
+            }
+            let position = self
+                .free
+                .binary_search(&(index as u32))
+                .expect("vacant slots are free");
+            self.free.remove(position);
+        } else {
+            if version != 1 {
+                return false;
+            }
+            // Slots that were skipped over stay vacant, and have higher indices than all of the free ones.
+            self.free.extend(self.slots.len() as u32..index as u32);
+            self.slots.resize(
+                index + 1,
+                Slot {
+                    version: 0,
+                    info: None,
+                },
+            );
+        }
+        self.slots[index] = Slot {
+            version,
+            info: Some(RESERVED),
+        };
+        true
+    }
+    /// Reserves the next ID, which stays unused until it's passed to `spawn_reserved`.
+    pub(crate) fn reserve(&mut self) -> EntityID {
+        let entity = self.candidate(0);
+        assert!(self.claim(entity), "candidate is vacant");
+        entity
+    }
+    /// Places an entity right away, without reserving its ID first.
+    pub(crate) fn spawn(&mut self, info: EntityInfo) -> EntityID {
+        let entity = self.reserve();
+        self.spawn_reserved(entity, info);
+        entity
+    }
+    /// Makes room for `additional` more entities.
+    pub(crate) fn reserve_capacity(&mut self, additional: usize) {
+        self.slots
+            .reserve(additional.saturating_sub(self.free.len()));
+    }
+    /// Places a reserved entity. Returns false if the ID is not reserved.
+    pub(crate) fn spawn_reserved(&mut self, entity: EntityID, info: EntityInfo) -> bool {
+        if !self.is_reserved(entity) {
+            return false;
+        }
+        let (index, _version) = split(entity);
+        self.slots[index].info = Some(info);
+        self.len += 1;
+        true
+    }
+    /// Removes an entity or an unused reservation.
+    pub(crate) fn remove(&mut self, entity: EntityID) -> Option<EntityInfo> {
+        self.slot(entity)?;
+        let (index, _version) = split(entity);
+        let slot = &mut self.slots[index];
+        let info = slot.info.take().expect("slot is in use");
+        slot.version += 1;
+        let position = self
+            .free
+            .binary_search(&(index as u32))
+            .expect_err("slot in use is not free");
+        self.free.insert(position, index as u32);
+        if info.is_reserved() {
+            return None;
+        }
+        self.len -= 1;
+        Some(info)
+    }
+}
+
+/// Same as a slot of a slot map, so that saves made with one still load.
+#[derive(Serialize, Deserialize)]
+struct SerdeSlot {
+    value: Option<EntityInfo>,
+    version: u32,
+}
+
+impl Serialize for Entities {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut seq = serializer.serialize_seq(Some(self.slots.len()))?;
+        for slot in &self.slots {
+            seq.serialize_element(&SerdeSlot {
+                value: slot.info,
+                version: slot.version,
+            })?;
+        }
+        seq.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Entities {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let saved = Vec::<SerdeSlot>::deserialize(deserializer)?;
+        if saved.len() >= u32::MAX as usize {
+            return Err(de::Error::custom("too many slots"));
+        }
+        if saved.first().is_none_or(|slot| slot.value.is_some()) {
+            return Err(de::Error::custom("first slot not empty"));
+        }
+        let mut entities = Self {
+            slots: Vec::with_capacity(saved.len()),
+            free: Vec::new(),
+            len: 0,
+        };
+        for (index, slot) in saved.into_iter().enumerate() {
+            if (slot.version % 2 == 1) != slot.value.is_some() {
+                return Err(de::Error::custom("inconsistent occupation in slot"));
+            }
+            match slot.value {
+                Some(info) if !info.is_reserved() => entities.len += 1,
+                Some(_) => {}
+                None if index != 0 => entities.free.push(index as u32),
+                None => {}
+            }
+            entities.slots.push(Slot {
+                version: slot.version,
+                info: slot.value,
+            });
+        }
+        Ok(entities)
+    }
+}
@@ -0,0 +1,135 @@
+use crate::{
+    component_traits::{Bundle, Component},
+    internal::{ComponentStorageProvider, DynDispath},
+    EntityID, EntityInfo, TypeIndex, World,
+};
+
+/// Shared access to components of a single entity, see `World::entity`.
+///
+/// Location of the entity is resolved once, when the handle is created.
+pub struct EntityRef<'wrld, Storage> {
+    world: &'wrld World<Storage>,
+    entity: EntityID,
+    info: EntityInfo,
+}
+
+/// Exclusive access to a single entity, see `World::entity_mut`.
+///
+/// Hooks triggered by `insert` or `remove` might despawn the entity,
+/// after which the handle acts as if the entity had no components.
+pub struct EntityMut<'wrld, Storage> {
+    world: &'wrld mut World<Storage>,
+    entity: EntityID,
+    /// None once the entity is despawned.
+    info: Option<EntityInfo>,
+}
+
+impl<'wrld, Storage: DynDispath> EntityRef<'wrld, Storage> {
+    pub fn id(&self) -> EntityID {
+        self.entity
+    }
+
+    pub fn get<C>(&self) -> Option<&'wrld C>
+    where
+        Storage: ComponentStorageProvider<C>,
+        C: Component<Storage>,
+    {
+        self.world.get_at(self.entity, self.info)
+    }
+
+    pub fn contains<C: Component<Storage>>(&self) -> bool {
+        self.world
+            .has_component(self.entity, self.info, C::TYPE_INDEX)
+    }
+
+    /// Type indices of components this entity has, in ascending order.
+    pub fn component_types(&self) -> impl Iterator<Item = TypeIndex> + 'wrld {
+        self.world
+            .component_types(self.entity, self.info)
+            .into_iter()
+    }
+}
+
+impl<'wrld, Storage: DynDispath> EntityMut<'wrld, Storage> {
+    pub fn id(&self) -> EntityID {
+        self.entity
+    }
+
+    /// True if the entity was despawned by a hook.
+    pub fn is_despawned(&self) -> bool {
+        self.info.is_none()
+    }
+
+    pub fn get<C>(&self) -> Option<&C>
+    where
+        Storage: ComponentStorageProvider<C>,
+        C: Component<Storage>,
+    {
+        self.world.get_at(self.entity, self.info?)
+    }
+
+    pub fn get_mut<C>(&mut self) -> Option<&mut C>
+    where
+        Storage: ComponentStorageProvider<C>,
+        C: Component<Storage>,
+    {
+        self.world.get_mut_at(self.entity, self.info?)
+    }
+
+    pub fn contains<C: Component<Storage>>(&self) -> bool {
+        self.info
+            .is_some_and(|info| self.world.has_component(self.entity, info, C::TYPE_INDEX))
+    }
+
+    /// Type indices of components this entity has, in ascending order.
+    pub fn component_types(&self) -> impl Iterator<Item = TypeIndex> + '_ {
+        self.info
+            .map(|info| self.world.component_types(self.entity, info))
+            .unwrap_or_default()
+            .into_iter()
+    }
+
+    /// Same as `World::insert`.
+    pub fn insert<B: Bundle<Storage>>(&mut self, bundle: B) -> &mut Self {
+        self.world.insert(self.entity, bundle);
+        self.refresh();
+        self
+    }
+
+    /// Same as `World::remove`.
+    pub fn remove<B: Bundle<Storage>>(&mut self) -> &mut Self {
+        self.world.remove::<B>(self.entity);
+        self.refresh();
+        self
+    }
+
+    /// Same as `World::despawn`.
+    pub fn despawn(self) {
+        self.world.despawn(self.entity);
+    }
+
+    /// Entity might have moved to another archetype, or have been despawned by a hook.
+    fn refresh(&mut self) {
+        self.info = self.world.entities.get(self.entity);
+    }
+}
+
+impl<Storage: DynDispath> World<Storage> {
+    pub fn entity(&self, entity: EntityID) -> Option<EntityRef<'_, Storage>> {
+        let info = self.entities.get(entity)?;
+        Some(EntityRef {
+            world: self,
+            entity,
+            info,
+        })
+    }
+
+    pub fn entity_mut(&mut self, entity: EntityID) -> Option<EntityMut<'_, Storage>> {
+        let info = self.entities.get(entity)?;
+        Some(EntityMut {
+            world: self,
+            entity,
+            info: Some(info),
+        })
+    }
+}
@@ -0,0 +1,54 @@
+use std::fmt;
+
+/// Reason a system parameter can't be created.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EcsError {
+    /// Both parameters access the same data, and at least one of them needs exclusive access.
+    ///
+    /// Accesses of both parameters are listed, with names of the types they access.
+    IncompatibleRequests {
+        first: String,
+        second: String,
+    },
+    /// Parameter needs exclusive access, while QueryWorld only has a shared reference to the world.
+    ExclusiveOnShared {
+        parameter: String,
+    },
+    MissingResource {
+        resource: &'static str,
+    },
+    /// System takes `&mut World`, so it can't run on a QueryWorld.
+    ExclusiveSystem {
+        system: &'static str,
+    },
+    /// Local state only exists in systems, not in plain QueryWorld runs.
+    LocalOutsideSystem {
+        parameter: &'static str,
+    },
+}
+
+impl fmt::Display for EcsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EcsError::IncompatibleRequests { first, second } => write!(
+                f,
+                "parameters accessing {first} and {second} are incompatible, and thus cannot be used at the same time"
+            ),
+            EcsError::ExclusiveOnShared { parameter } => write!(
+                f,
+                "parameter `{parameter}` needs exclusive access, which is not supported in non-exclusive QueryWorld"
+            ),
+            EcsError::MissingResource { resource } => {
+                write!(f, "resource `{resource}` does not exist")
+            }
+            EcsError::ExclusiveSystem { system } => {
+                write!(f, "system `{system}` needs exclusive access to the world, which QueryWorld can't provide")
+            }
+            EcsError::LocalOutsideSystem { parameter } => {
+                write!(f, "local `{parameter}` can only be used in a system")
+            }
+        }
+    }
+}
+
+impl std::error::Error for EcsError {}
@@ -0,0 +1,209 @@
+use rayon::prelude::*;
+
+use crate::{
+    internal::DynDispath, system_parameter::commands::CommandList, EcsError, System, World,
+};
+
+/// Runs systems on a thread pool, running systems that don't conflict with each other concurrently.
+///
+/// Commands are applied after all systems, in the same order as if systems were run one by one on a single QueryWorld.
+/// Exclusive systems are the exception: commands of systems that ran before them are applied first.
+pub(crate) struct ParallelExecutor {
+    /// Order in which systems would run one by one.
+    order: Vec<usize>,
+    /// Systems are split into stages, so that systems in the same stage don't depend on or conflict with each other.
+    stages: Vec<Vec<usize>>,
+}
+
+impl ParallelExecutor {
+    /// Splits systems into stages, reordering them stage by stage.
+    ///
+    /// `order` has to be a topological order of `predecessors`,
+    /// which are systems that have to run before each system.
+    pub(crate) fn new<Storage: DynDispath>(
+        systems: &[System<Storage>],
+        order: Vec<usize>,
+        predecessors: &[Vec<usize>],
+    ) -> Self {
+        // Each system goes to the stage after the last one it depends on or conflicts with.
+        let mut stage_of = vec![0; systems.len()];
+        let mut stages: Vec<Vec<usize>> = Vec::new();
+        for (position, &index) in order.iter().enumerate() {
+            let stage = order[..position]
+                .iter()
+                .filter(|&&other| {
+                    predecessors[index].contains(&other)
+                        || systems[other].conflicts_with(&systems[index])
+                })
+                .map(|&other| stage_of[other] + 1)
+                .max()
+                .unwrap_or(0);
+            stage_of[index] = stage;
+            if stage == stages.len() {
+                stages.push(Vec::new());
+            }
+            stages[stage].push(index);
+        }
+        // Systems run one by one stage by stage as well, so that they reserve the same IDs either way.
+        let order = stages.concat();
+        Self { order, stages }
+    }
+
+    pub(crate) fn order(&self) -> &[usize] {
+        &self.order
+    }
+
+    pub(crate) fn stage_count(&self) -> usize {
+        self.stages.len()
+    }
+
+    /// Runs `systems`, which have to be the same ones this executor was created for.
+    ///
+    /// Systems whose parameters can't be acquired are skipped, and returned in the order systems would run.
+    pub(crate) fn run<Storage>(
+        &self,
+        systems: &mut [System<Storage>],
+        world: &mut World<Storage>,
+    ) -> Vec<(&'static str, EcsError)>
+    where
+        Storage: DynDispath + Send + Sync,
+        Storage::AnyComponent: Send,
+        Storage::AnyResource: Send,
+    {
+        self.run_stages(systems, world, |world, stage_systems| {
+            stage_systems
+                .into_par_iter()
+                .map(|stage_system| run_in_stage(world, stage_system))
+                .collect()
+        })
+    }
+
+    /// Same as `run`, but runs systems of a stage one by one on the current thread.
+    ///
+    /// Entities get the same IDs either way.
+    pub(crate) fn run_serial<Storage: DynDispath>(
+        &self,
+        systems: &mut [System<Storage>],
+        world: &mut World<Storage>,
+    ) -> Vec<(&'static str, EcsError)> {
+        self.run_stages(systems, world, |world, stage_systems| {
+            stage_systems
+                .into_iter()
+                .map(|stage_system| run_in_stage(world, stage_system))
+                .collect()
+        })
+    }
+
+    fn run_stages<Storage: DynDispath>(
+        &self,
+        systems: &mut [System<Storage>],
+        world: &mut World<Storage>,
+        run_stage: impl Fn(&World<Storage>, Vec<StageSystem<Storage>>) -> Vec<StageResult<Storage>>,
+    ) -> Vec<(&'static str, EcsError)> {
+        let position_of = |index| {
+            self.order
+                .iter()
+                .position(|&x| x == index)
+                .expect("every system is ordered")
+        };
+        let mut commands = Vec::with_capacity(systems.len());
+        let mut errors = Vec::new();
+        // Candidate IDs used up by stages since commands were last applied.
+        let mut reserved = 0;
+        for stage in &self.stages {
+            // Exclusive systems conflict with everything, so they are alone in their stages.
+            if let [index] = stage[..] {
+                if systems[index].is_exclusive() {
+                    apply_commands(world, &mut commands);
+                    reserved = 0;
+                    let system = &mut systems[index];
+                    if let Err(err) = system.try_run_exclusive(world) {
+                        errors.push((position_of(index), system.name(), err));
+                    }
+                    continue;
+                }
+            }
+            // Only systems that can spawn entities get lanes, so that candidates aren't skipped for nothing.
+            let lanes = stage
+                .iter()
+                .filter(|&&index| systems[index].reserves_entities())
+                .copied()
+                .collect::<Vec<_>>();
+            let stage_systems = systems
+                .iter_mut()
+                .enumerate()
+                .filter(|(index, _)| stage.contains(index))
+                .map(|(index, system)| {
+                    let lane = lanes.iter().position(|&x| x == index).unwrap_or(0);
+                    StageSystem {
+                        position: position_of(index),
+                        first: reserved + lane,
+                        lanes: lanes.len().max(1),
+                        system,
+                    }
+                })
+                .collect::<Vec<_>>();
+            for result in run_stage(&*world, stage_systems) {
+                commands.push((result.position, result.commands));
+                errors.extend(result.err);
+                reserved = reserved.max(result.reserved);
+            }
+        }
+        apply_commands(world, &mut commands);
+        errors.sort_by_key(|(position, _, _)| *position);
+        errors
+            .into_iter()
+            .map(|(_position, system, err)| (system, err))
+            .collect()
+    }
+}
+
+/// System of a stage, along with its position in the order and its reservation lane.
+struct StageSystem<'a, Storage: DynDispath> {
+    position: usize,
+    first: usize,
+    lanes: usize,
+    system: &'a mut System<Storage>,
+}
+
+struct StageResult<Storage: DynDispath> {
+    position: usize,
+    err: Option<(usize, &'static str, EcsError)>,
+    commands: CommandList<Storage>,
+    /// Number of candidate IDs used up by the system, counting from the start of its stage.
+    reserved: usize,
+}
+
+fn run_in_stage<Storage: DynDispath>(
+    world: &World<Storage>,
+    stage_system: StageSystem<Storage>,
+) -> StageResult<Storage> {
+    let StageSystem {
+        position,
+        first,
+        lanes,
+        system,
+    } = stage_system;
+    // Safety: systems in the same stage don't conflict with each other.
+    let (ret, commands, reserved) = unsafe { system.run_scoped(world, first, lanes) };
+    StageResult {
+        position,
+        err: ret.err().map(|err| (position, system.name(), err)),
+        commands,
+        reserved,
+    }
+}
+
+/// Applies commands collected from systems, in the order those systems would run one by one.
+fn apply_commands<Storage: DynDispath>(
+    world: &mut World<Storage>,
+    commands: &mut Vec<(usize, CommandList<Storage>)>,
+) {
+    commands.sort_by_key(|(position, _)| *position);
+    world.apply_commands(
+        commands
+            .drain(..)
+            .flat_map(|(_position, system_commands)| system_commands)
+            .map(|(_param_index, cmd)| cmd),
+    );
+}
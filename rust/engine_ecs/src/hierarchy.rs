@@ -0,0 +1,164 @@
+use std::mem;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    component_traits::{Component, TypeIndexStorage},
+    internal::{ComponentStorageProvider, DynDispath},
+    EntityID, LocalTypeIndex, TypeIndex, World,
+};
+
+/// Entity this entity belongs to.
+///
+/// Set with `World::set_parent`, which keeps `Children` of the parent up to date.
+/// Not a `Bundle`, so it can't be inserted or removed any other way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Parent(EntityID);
+
+impl Parent {
+    pub fn get(self) -> EntityID {
+        self.0
+    }
+}
+
+/// Entities which have this entity as their `Parent`, in the order they were added.
+///
+/// Only exists while there is at least one child. Like `Parent`, it is not a `Bundle`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Children(Vec<EntityID>);
+
+impl Children {
+    pub fn as_slice(&self) -> &[EntityID] {
+        &self.0
+    }
+    pub fn iter(&self) -> impl Iterator<Item = EntityID> + '_ {
+        self.0.iter().copied()
+    }
+    pub fn contains(&self, entity: EntityID) -> bool {
+        self.0.contains(&entity)
+    }
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<Storage: DynDispath> LocalTypeIndex<Storage> for Parent {
+    const TYPE_INDEX: TypeIndex = Storage::COMPONENT_TYPES - 2;
+}
+
+impl<Storage: DynDispath> LocalTypeIndex<Storage> for Children {
+    const TYPE_INDEX: TypeIndex = Storage::COMPONENT_TYPES - 1;
+}
+
+impl<Storage: DynDispath> World<Storage> {
+    pub fn parent(&self, entity: EntityID) -> Option<EntityID> {
+        self.get::<Parent>(entity).map(|parent| parent.0)
+    }
+
+    /// Children of this entity, empty if it has none or does not exist.
+    pub fn children(&self, entity: EntityID) -> &[EntityID] {
+        self.get::<Children>(entity)
+            .map(Children::as_slice)
+            .unwrap_or(&[])
+    }
+
+    /// Makes `parent` the parent of `child`, detaching it from its previous parent.
+    ///
+    /// Returns false if either entity does not exist, or if `child` is `parent` itself or one of its ancestors.
+    pub fn set_parent(&mut self, child: EntityID, parent: EntityID) -> bool {
+        if !self.entities.contains(child) || !self.entities.contains(parent) {
+            return false;
+        }
+        let mut ancestor = Some(parent);
+        while let Some(current) = ancestor {
+            if current == child {
+                return false;
+            }
+            ancestor = self.parent(current);
+        }
+
+        self.remove_parent(child);
+        self.insert_link(child, Parent(parent));
+        match self.get_mut::<Children>(parent) {
+            Some(children) => children.0.push(child),
+            None => {
+                self.insert_link(parent, Children(vec![child]));
+            }
+        }
+        true
+    }
+
+    /// Detaches entity from its parent. Returns false if it had no parent.
+    pub fn remove_parent(&mut self, child: EntityID) -> bool {
+        let Some(parent) = self.parent(child) else {
+            return false;
+        };
+        self.remove_link::<Parent>(child);
+        self.forget_child(parent, child);
+        true
+    }
+
+    /// Despawns entity along with all of its descendants. Returns false if entity does not exist.
+    pub fn despawn_recursive(&mut self, entity: EntityID) -> bool {
+        if !self.entities.contains(entity) {
+            return false;
+        }
+        if let Some(parent) = self.parent(entity) {
+            self.forget_child(parent, entity);
+        }
+        // Whole subtree goes away, so there are no links left to maintain inside of it.
+        let mut subtree = vec![entity];
+        let mut next = 0;
+        while let Some(&current) = subtree.get(next) {
+            subtree.extend_from_slice(self.children(current));
+            next += 1;
+        }
+        for entity in subtree {
+            self._despawn(entity);
+        }
+        true
+    }
+
+    /// Removes entity from children of its parent, and detaches its own children from it.
+    pub(crate) fn unlink_from_hierarchy(&mut self, entity: EntityID) {
+        if let Some(parent) = self.parent(entity) {
+            self.forget_child(parent, entity);
+        }
+        let children = match self.get_mut::<Children>(entity) {
+            Some(children) => mem::take(&mut children.0),
+            None => return,
+        };
+        self.remove_link::<Children>(entity);
+        for child in children {
+            self.remove_link::<Parent>(child);
+        }
+    }
+
+    fn forget_child(&mut self, parent: EntityID, child: EntityID) {
+        let Some(children) = self.get_mut::<Children>(parent) else {
+            return;
+        };
+        children.0.retain(|&entity| entity != child);
+        if children.0.is_empty() {
+            self.remove_link::<Children>(parent);
+        }
+    }
+
+    fn insert_link<C>(&mut self, entity: EntityID, component: C)
+    where
+        Storage: ComponentStorageProvider<C>,
+        C: Component<Storage>,
+    {
+        let added = TypeIndexStorage::from_elem(C::TYPE_INDEX, 1);
+        self.insert_components(entity, added, |world, archetype| {
+            world.add_bundle_to_archetype(archetype, entity, component)
+        });
+    }
+
+    fn remove_link<C: Component<Storage>>(&mut self, entity: EntityID) {
+        self.remove_components(entity, &[C::TYPE_INDEX]);
+    }
+}
@@ -0,0 +1,149 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    component_traits::Component,
+    internal::DynDispath,
+    query_world::{QueryWorld, WorldRef},
+    system_parameter::commands::{CommandG, CommandsG},
+    EntityID, EntityRef, TypeIndex, World,
+};
+
+/// Callback that runs when a component is added to or removed from an entity.
+///
+/// Gets the entity, which still has the component in case of removal, and commands,
+/// which are applied right after the change that triggered the hook.
+/// Implemented for closures `Fn(EntityRef<Storage>, &CommandsG<Storage>)`.
+pub(crate) trait Hook<Storage>: Send + Sync {
+    fn run(&self, entity: EntityRef<Storage>, commands: &CommandsG<Storage>)
+    where
+        Storage: DynDispath;
+}
+
+impl<Storage, F> Hook<Storage> for F
+where
+    Storage: DynDispath,
+    F: Fn(EntityRef<Storage>, &CommandsG<Storage>) + Send + Sync,
+{
+    fn run(&self, entity: EntityRef<Storage>, commands: &CommandsG<Storage>) {
+        self(entity, commands)
+    }
+}
+
+type HookMap<Storage> = HashMap<TypeIndex, Vec<Arc<dyn Hook<Storage>>>>;
+
+/// Hooks of a world, see `World::on_add` and `World::on_remove`.
+///
+/// Worlds start with hooks returned by a function named in `: hooks` section of gen_storage_for_world!,
+/// so that loaded worlds have them as well.
+pub struct Hooks<Storage> {
+    on_add: HookMap<Storage>,
+    on_remove: HookMap<Storage>,
+}
+
+impl<Storage> Default for Hooks<Storage> {
+    fn default() -> Self {
+        Self {
+            on_add: HashMap::new(),
+            on_remove: HashMap::new(),
+        }
+    }
+}
+
+impl<Storage> Clone for Hooks<Storage> {
+    fn clone(&self) -> Self {
+        Self {
+            on_add: self.on_add.clone(),
+            on_remove: self.on_remove.clone(),
+        }
+    }
+}
+
+impl<Storage: DynDispath> Hooks<Storage> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a hook that runs whenever an entity gets component `C` it didn't have,
+    /// including spawns. Replacing a component doesn't trigger it.
+    pub fn on_add<C: Component<Storage>>(
+        mut self,
+        hook: impl Fn(EntityRef<Storage>, &CommandsG<Storage>) + Send + Sync + 'static,
+    ) -> Self {
+        push_hook(&mut self.on_add, C::TYPE_INDEX, Arc::new(hook));
+        self
+    }
+
+    /// Adds a hook that runs whenever component `C` is removed from an entity, including despawns.
+    pub fn on_remove<C: Component<Storage>>(
+        mut self,
+        hook: impl Fn(EntityRef<Storage>, &CommandsG<Storage>) + Send + Sync + 'static,
+    ) -> Self {
+        push_hook(&mut self.on_remove, C::TYPE_INDEX, Arc::new(hook));
+        self
+    }
+}
+
+fn push_hook<Storage>(
+    hooks: &mut HookMap<Storage>,
+    type_index: TypeIndex,
+    hook: Arc<dyn Hook<Storage>>,
+) {
+    hooks.entry(type_index).or_default().push(hook);
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum HookKind {
+    Add,
+    Remove,
+}
+
+impl<Storage: DynDispath> World<Storage> {
+    /// Runs `hook` whenever an entity gets component `C` it didn't have, see `Hooks::on_add`.
+    pub fn on_add<C: Component<Storage>>(
+        &mut self,
+        hook: impl Fn(EntityRef<Storage>, &CommandsG<Storage>) + Send + Sync + 'static,
+    ) {
+        push_hook(&mut self.hooks.on_add, C::TYPE_INDEX, Arc::new(hook));
+    }
+
+    /// Runs `hook` whenever component `C` is removed from an entity, see `Hooks::on_remove`.
+    pub fn on_remove<C: Component<Storage>>(
+        &mut self,
+        hook: impl Fn(EntityRef<Storage>, &CommandsG<Storage>) + Send + Sync + 'static,
+    ) {
+        push_hook(&mut self.hooks.on_remove, C::TYPE_INDEX, Arc::new(hook));
+    }
+
+    /// Runs hooks of `kind` registered for any of `components` for each of `entities`, returning commands they submitted.
+    ///
+    /// Entities have to exist.
+    pub(crate) fn run_hooks(
+        &self,
+        kind: HookKind,
+        entities: &[EntityID],
+        components: &[TypeIndex],
+    ) -> Vec<CommandG<Storage>> {
+        let hooks = match kind {
+            HookKind::Add => &self.hooks.on_add,
+            HookKind::Remove => &self.hooks.on_remove,
+        };
+        if hooks.is_empty() {
+            return Vec::new();
+        }
+        let query_world = QueryWorld::new(WorldRef::Scoped(self));
+        let commands = CommandsG::new(&query_world, 0);
+        for &entity in entities {
+            for type_index in components {
+                for hook in hooks.get(type_index).into_iter().flatten() {
+                    hook.run(self.entity(entity).expect("entity exists"), &commands);
+                }
+            }
+        }
+        query_world.drain_commands()
+    }
+
+    /// Applies commands returned by `run_hooks`, once the change that triggered hooks is done.
+    pub(crate) fn apply_hook_commands(&mut self, commands: Vec<CommandG<Storage>>) {
+        self.apply_commands(commands);
+    }
+}
@@ -1,9 +1,23 @@
-use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt, ptr};
 
-use crate::{ecs_cell::EcsCell, StorageID, TypeIndex};
+use serde::{
+    de::{IgnoredAny, MapAccess},
+    ser::SerializeMap,
+    Deserialize, Serialize,
+};
 
-pub use crate::component_traits::TypeIndexStorage;
-pub use crate::system_parameter::{query::QueryParameter, ComponentRequests, SystemParameter};
+use crate::{
+    ecs_cell::EcsCell, ArchetypeID, Children, ComponentIndex, EntityID, Events, Hooks, Migrations,
+    Parent, StorageID, Tick, TypeIndex, World,
+};
+
+pub use crate::component_traits::{TableComponent, TypeIndexStorage};
+pub use crate::system_parameter::{
+    chunks::{ChunkMut, ChunkParameter},
+    index::index_hooks,
+    query::{shortest_entity_list, QueryParameter},
+    ComponentRequests, SystemParameter,
+};
 pub use crate::InArchetypeID;
 pub use smallvec::SmallVec;
 
@@ -12,61 +26,318 @@ pub trait ComponentStorageProvider<T> {
     fn storage_mut(&mut self) -> &mut ComponentList<T>;
 }
 
-pub trait DynDispath {
+/// Implemented by gen_storage_for_world! macro.
+///
+/// Built-in components come after the declared ones, `Parent` and `Children` take the last two indices.
+pub trait DynDispath:
+    Sized + ComponentStorageProvider<Parent> + ComponentStorageProvider<Children>
+{
+    const COMPONENT_TYPES: TypeIndex;
     const RESOURCE_TYPES: TypeIndex;
+    /// Names of component types, by their type index.
+    const COMPONENT_NAMES: &'static [&'static str];
+    /// Names of resource types, including event queues, by their type index.
+    const RESOURCE_NAMES: &'static [&'static str];
+    /// Whether component types, by their type index, are declared `sparse`.
+    ///
+    /// Sparse components are kept in a `SparseSet` instead of archetype columns,
+    /// so they are not part of archetypes and adding or removing them doesn't move entities.
+    const SPARSE: &'static [bool];
+    /// Type indices of sparse components in ascending order, same as those that are `true` in `SPARSE`.
+    const SPARSE_TYPES: &'static [TypeIndex];
+
+    /// Enum of all component types, used to store components in `CommandG`.
+    type AnyComponent: DynComponent<Self> + From<Parent> + From<Children> + fmt::Debug;
+    /// Enum of all resource types, used to store resources in `CommandG`.
+    type AnyResource: DynResource<Self> + fmt::Debug;
 
+    fn dispath<F, Ret>(&self, type_index: TypeIndex, f: F) -> Ret
+    where
+        F: FnOnce(&dyn DynComponentList) -> Ret;
     fn dispath_mut<F, Ret>(&mut self, type_index: TypeIndex, f: F) -> Ret
     where
         F: FnOnce(&mut dyn DynComponentList) -> Ret;
+    /// Swaps buffers of all event queues.
+    fn update_events(&mut self);
+    /// Hooks returned by the function from `: hooks` section, or none.
+    fn hooks() -> Hooks<Self>;
+
+    fn is_sparse(type_index: TypeIndex) -> bool {
+        Self::SPARSE[type_index as usize]
+    }
+}
+
+/// Implemented by gen_storage_for_world! macro, unless `no_serialize` is set.
+///
+/// Storages are serialized as maps from names of types to their data, so that saves don't depend on type indices.
+pub trait StorageSerde: DynDispath + Default {
+    /// Migrations returned by the function from `: migrations` section, or none.
+    fn migrations() -> Migrations<Self>;
+    fn serialize_components<M: SerializeMap>(&self, map: &mut M) -> Result<(), M::Error>;
+    fn serialize_resources<M: SerializeMap>(&self, map: &mut M) -> Result<(), M::Error>;
+    /// Reads the next value of `map` into storage of the component named `name`.
+    ///
+    /// Returns false without reading anything if there is no such component.
+    fn deserialize_component<'de, A: MapAccess<'de>>(
+        &mut self,
+        name: &str,
+        map: &mut A,
+    ) -> Result<bool, A::Error>;
+    /// Same as `deserialize_component`, for resources and event queues.
+    fn deserialize_resource<'de, A: MapAccess<'de>>(
+        &mut self,
+        name: &str,
+        map: &mut A,
+    ) -> Result<bool, A::Error>;
+    /// Fills a storage of a transient component with `len` default values, does nothing for other components.
+    fn fill_transient(&mut self, type_index: TypeIndex, storage: StorageID, len: InArchetypeID);
+}
+
+/// Placeholder for a transient type in a save, which reads and discards whatever is there instead.
+///
+/// Binary formats can only read a placeholder, while human-readable ones also accept data
+/// saved before the type was marked as transient.
+pub struct Transient;
+
+impl<'de> Deserialize<'de> for Transient {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        if deserializer.is_human_readable() {
+            IgnoredAny::deserialize(deserializer)?;
+        } else {
+            <()>::deserialize(deserializer)?;
+        }
+        Ok(Transient)
+    }
+}
+
+/// Component of any type, see `DynDispath::AnyComponent`.
+pub trait DynComponent<Storage>: Sized {
+    fn type_index(&self) -> TypeIndex;
+    fn add_to_archetype_in_storage(
+        self,
+        world: &mut World<Storage>,
+        archetype: ArchetypeID,
+        entity: EntityID,
+    );
+}
+
+/// Resource of any type, see `DynDispath::AnyResource`.
+pub trait DynResource<Storage>: Sized {
+    fn insert_into(self, world: &mut World<Storage>);
 }
 
 pub trait DynComponentList {
     fn allocate(&mut self) -> StorageID;
     fn swap_remove(&mut self, storage: StorageID, index: InArchetypeID);
+    /// Moves component from one storage to the end of another one, like `swap_remove` does.
+    fn move_to(&mut self, from: StorageID, index: InArchetypeID, to: StorageID);
+    /// Makes room for `additional` more components in a storage.
+    fn reserve(&mut self, storage: StorageID, additional: usize);
+    fn sparse_contains(&self, entity: EntityID) -> bool;
+    fn sparse_remove(&mut self, entity: EntityID);
+    fn sparse_reserve(&mut self, additional: usize);
+}
+
+/// Components of a single archetype, along with ticks of their last mutable access.
+///
+/// Only components are serialized, ticks are reset on load.
+#[derive(Clone)]
+pub(crate) struct Column<T> {
+    values: Vec<T>,
+    ticks: Vec<Tick>,
+    /// Tick of the last mutable access to any of the components, so that unchanged columns can be skipped.
+    changed: Tick,
+}
+
+impl<T> Column<T> {
+    fn new() -> Self {
+        Self {
+            values: Vec::new(),
+            ticks: Vec::new(),
+            changed: 0,
+        }
+    }
+    fn push(&mut self, component: T, tick: Tick) {
+        self.values.push(component);
+        self.ticks.push(tick);
+    }
+    fn extend(&mut self, components: Vec<T>, tick: Tick) {
+        self.values.extend(components);
+        self.ticks.resize(self.values.len(), tick);
+    }
+    fn reserve(&mut self, additional: usize) {
+        self.values.reserve(additional);
+        self.ticks.reserve(additional);
+    }
+    fn swap_remove(&mut self, index: usize) -> (T, Tick) {
+        (
+            self.values.swap_remove(index),
+            self.ticks.swap_remove(index),
+        )
+    }
+    fn get_mut(&mut self, index: usize, tick: Tick) -> Option<&mut T> {
+        let component = self.values.get_mut(index)?;
+        self.ticks[index] = tick;
+        self.changed = tick;
+        Some(component)
+    }
+    /// Same as `get_mut`, without creating a reference to the whole column,
+    /// so that components returned earlier stay valid.
+    ///
+    /// # Safety
+    ///
+    /// `column` has to be valid, and nothing else may access this component or the column at the same time.
+    unsafe fn get_mut_raw<'a>(column: *mut Self, index: usize, tick: Tick) -> Option<&'a mut T> {
+        unsafe {
+            if index >= (*column).values.len() {
+                return None;
+            }
+            *(*ptr::addr_of_mut!((*column).ticks))
+                .as_mut_ptr()
+                .add(index) = tick;
+            *ptr::addr_of_mut!((*column).changed) = tick;
+            Some(
+                &mut *(*ptr::addr_of_mut!((*column).values))
+                    .as_mut_ptr()
+                    .add(index),
+            )
+        }
+    }
+}
+
+impl<T: Serialize> Serialize for Column<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.values.serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Column<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let values = Vec::<T>::deserialize(deserializer)?;
+        let ticks = vec![0; values.len()];
+        Ok(Self {
+            values,
+            ticks,
+            changed: 0,
+        })
+    }
 }
 
+/// Storages of a component type, one per archetype that has it.
+///
+/// Sparse components only use `sparse`, which is saved separately, see `DynDispath::SPARSE`.
 #[derive(Clone, Serialize, Deserialize)]
+#[serde(bound(serialize = "T: Serialize", deserialize = "T: Deserialize<'de>"))]
 pub struct ComponentList<T> {
-    list: Vec<EcsCell<Vec<T>>>,
+    list: Vec<EcsCell<Column<T>>>,
+    #[serde(skip)]
+    sparse: SparseSet<T>,
 }
 
 impl<T> Default for ComponentList<T> {
     fn default() -> Self {
         Self {
             list: Default::default(),
+            sparse: Default::default(),
         }
     }
 }
 
 impl<T> ComponentList<T> {
-    pub(crate) fn add_to_storage(&mut self, storage: StorageID, component: T) {
-        self.list[storage.0 as usize].get_mut().push(component)
+    pub(crate) fn add_to_storage(&mut self, storage: StorageID, component: T, tick: Tick) {
+        self.list[storage.0 as usize]
+            .get_mut()
+            .push(component, tick)
+    }
+    pub(crate) fn extend_storage(&mut self, storage: StorageID, components: Vec<T>, tick: Tick) {
+        self.list[storage.0 as usize]
+            .get_mut()
+            .extend(components, tick)
     }
     pub(crate) fn get(&self, storage: StorageID, index_in_arche: InArchetypeID) -> Option<&T> {
         self.list[storage.0 as usize]
             .get()
+            .values
             .get(index_in_arche as usize)
     }
     pub(crate) fn get_mut(
         &mut self,
         storage: StorageID,
         index_in_arche: InArchetypeID,
+        tick: Tick,
     ) -> Option<&mut T> {
         self.list[storage.0 as usize]
             .get_mut()
-            .get_mut(index_in_arche as usize)
+            .get_mut(index_in_arche as usize, tick)
     }
     pub(crate) unsafe fn get_mut_unsafe(
         &self,
         storage: StorageID,
         index_in_arche: InArchetypeID,
+        tick: Tick,
     ) -> Option<&mut T> {
         unsafe {
             self.list[storage.0 as usize]
                 .get_mut_unsafe()
-                .get_mut(index_in_arche as usize)
+                .get_mut(index_in_arche as usize, tick)
         }
     }
+    /// All components in this storage.
+    pub(crate) fn column(&self, storage: StorageID) -> &[T] {
+        &self.list[storage.0 as usize].get().values
+    }
+    /// # Safety
+    ///
+    /// Same as `get_mut_unsafe`, for all components in this storage.
+    pub(crate) unsafe fn column_mut_unsafe(
+        &self,
+        storage: StorageID,
+        tick: Tick,
+    ) -> ChunkMut<'_, T> {
+        let column = unsafe { self.list[storage.0 as usize].get_mut_unsafe() };
+        column.changed = tick;
+        ChunkMut::new(&mut column.values, &mut column.ticks, tick)
+    }
+    /// Pads a storage with default components, until it has `len` of them.
+    ///
+    /// Used to restore transient components, creating the storage if needed.
+    pub fn fill_default(&mut self, storage: StorageID, len: InArchetypeID)
+    where
+        T: Default,
+    {
+        let index = storage.0 as usize;
+        if self.list.len() <= index {
+            self.list
+                .resize_with(index + 1, || EcsCell::new(Column::new()));
+        }
+        let column = self.list[index].get_mut();
+        while column.values.len() < len as usize {
+            column.push(T::default(), 0);
+        }
+    }
+    /// Tick of the last mutable access to this component.
+    pub(crate) fn changed_tick(&self, storage: StorageID, index_in_arche: InArchetypeID) -> Tick {
+        self.list[storage.0 as usize].get().ticks[index_in_arche as usize]
+    }
+    /// Tick of the last mutable access to any component in this storage.
+    pub(crate) fn column_changed_tick(&self, storage: StorageID) -> Tick {
+        self.list[storage.0 as usize].get().changed
+    }
+    pub fn sparse(&self) -> &SparseSet<T> {
+        &self.sparse
+    }
+    pub fn sparse_mut(&mut self) -> &mut SparseSet<T> {
+        &mut self.sparse
+    }
 }
 
 impl<T> DynComponentList for ComponentList<T> {
@@ -77,7 +348,7 @@ impl<T> DynComponentList for ComponentList<T> {
                 .try_into()
                 .expect("Less archetypes use this component than IDs available"),
         );
-        self.list.push(EcsCell::new(Vec::new()));
+        self.list.push(EcsCell::new(Column::new()));
         ret
     }
     fn swap_remove(&mut self, storage: StorageID, index: InArchetypeID) {
@@ -85,6 +356,169 @@ impl<T> DynComponentList for ComponentList<T> {
             .get_mut()
             .swap_remove(index as usize);
     }
+    fn move_to(&mut self, from: StorageID, index: InArchetypeID, to: StorageID) {
+        let (component, tick) = self.list[from.0 as usize]
+            .get_mut()
+            .swap_remove(index as usize);
+        self.list[to.0 as usize].get_mut().push(component, tick);
+    }
+    fn reserve(&mut self, storage: StorageID, additional: usize) {
+        self.list[storage.0 as usize].get_mut().reserve(additional);
+    }
+    fn sparse_contains(&self, entity: EntityID) -> bool {
+        self.sparse.contains(entity)
+    }
+    fn sparse_remove(&mut self, entity: EntityID) {
+        self.sparse.remove(entity);
+    }
+    fn sparse_reserve(&mut self, additional: usize) {
+        self.sparse.reserve(additional);
+    }
+}
+
+/// Components of a sparse type, looked up by entity.
+///
+/// Zero-sized components only take up space for entities and ticks.
+/// Only entities and components are serialized, ticks are reset on load.
+pub struct SparseSet<T> {
+    indices: HashMap<EntityID, u32>,
+    entities: Vec<EntityID>,
+    column: EcsCell<Column<T>>,
+}
+
+impl<T> Default for SparseSet<T> {
+    fn default() -> Self {
+        Self {
+            indices: HashMap::new(),
+            entities: Vec::new(),
+            column: EcsCell::new(Column::new()),
+        }
+    }
+}
+
+impl<T: Clone> Clone for SparseSet<T> {
+    fn clone(&self) -> Self {
+        Self {
+            indices: self.indices.clone(),
+            entities: self.entities.clone(),
+            column: self.column.clone(),
+        }
+    }
+}
+
+impl<T> SparseSet<T> {
+    pub(crate) fn contains(&self, entity: EntityID) -> bool {
+        self.indices.contains_key(&entity)
+    }
+    /// Inserts a component, replacing the one entity already had.
+    pub(crate) fn insert(&mut self, entity: EntityID, component: T, tick: Tick) {
+        let column = self.column.get_mut();
+        match self.indices.get(&entity) {
+            Some(&index) => {
+                column.values[index as usize] = component;
+                column.ticks[index as usize] = tick;
+                column.changed = tick;
+            }
+            None => {
+                let index = self
+                    .entities
+                    .len()
+                    .try_into()
+                    .expect("less sparse components than indices");
+                self.indices.insert(entity, index);
+                self.entities.push(entity);
+                column.push(component, tick);
+            }
+        }
+    }
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        self.indices.reserve(additional);
+        self.entities.reserve(additional);
+        self.column.get_mut().reserve(additional);
+    }
+    pub(crate) fn remove(&mut self, entity: EntityID) -> Option<T> {
+        let index = self.indices.remove(&entity)? as usize;
+        self.entities.swap_remove(index);
+        if let Some(&moved) = self.entities.get(index) {
+            self.indices.insert(moved, index as u32);
+        }
+        Some(self.column.get_mut().swap_remove(index).0)
+    }
+    pub(crate) fn get(&self, entity: EntityID) -> Option<&T> {
+        let &index = self.indices.get(&entity)?;
+        self.column.get().values.get(index as usize)
+    }
+    pub(crate) fn get_mut(&mut self, entity: EntityID, tick: Tick) -> Option<&mut T> {
+        let &index = self.indices.get(&entity)?;
+        self.column.get_mut().get_mut(index as usize, tick)
+    }
+    /// # Safety
+    ///
+    /// Same as `ComponentList::get_mut_unsafe`, per entity.
+    #[allow(clippy::mut_from_ref)]
+    pub(crate) unsafe fn get_mut_unsafe(&self, entity: EntityID, tick: Tick) -> Option<&mut T> {
+        let &index = self.indices.get(&entity)?;
+        unsafe { Column::get_mut_raw(self.column.as_ptr(), index as usize, tick) }
+    }
+    /// Tick of the last mutable access to entity's component, if it has one.
+    pub(crate) fn changed_tick(&self, entity: EntityID) -> Option<Tick> {
+        let &index = self.indices.get(&entity)?;
+        Some(self.column.get().ticks[index as usize])
+    }
+    pub(crate) fn entities(&self) -> &[EntityID] {
+        &self.entities
+    }
+    /// Tick of the last mutable access to any component in this set.
+    pub(crate) fn column_changed_tick(&self) -> Tick {
+        self.column.get().changed
+    }
+    /// Entities with their components and ticks of the last mutable access to them.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (EntityID, &T, Tick)> {
+        let column = self.column.get();
+        self.entities
+            .iter()
+            .zip(&column.values)
+            .zip(&column.ticks)
+            .map(|((&entity, component), &tick)| (entity, component, tick))
+    }
+}
+
+impl<T: Serialize> Serialize for SparseSet<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        (&self.entities, &self.column.get().values).serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for SparseSet<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let (entities, values) = <(Vec<EntityID>, Vec<T>)>::deserialize(deserializer)?;
+        if entities.len() != values.len() {
+            return Err(serde::de::Error::custom(
+                "sparse set has different amounts of entities and components",
+            ));
+        }
+        let indices = entities
+            .iter()
+            .enumerate()
+            .map(|(index, &entity)| (entity, index as u32))
+            .collect();
+        let ticks = vec![0; values.len()];
+        Ok(Self {
+            indices,
+            entities,
+            column: EcsCell::new(Column {
+                values,
+                ticks,
+                changed: 0,
+            }),
+        })
+    }
 }
 
 pub trait ResourceStorageProvider<T> {
@@ -92,22 +526,70 @@ pub trait ResourceStorageProvider<T> {
     fn storage_mut(&mut self) -> &mut ResourceStorage<T>;
 }
 
-#[derive(Default, Clone, Serialize, Deserialize)]
+/// Storage of a single resource, which might be absent.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct ResourceStorage<T> {
-    inner: EcsCell<T>,
+    inner: EcsCell<Option<T>>,
+}
+
+impl<T: Default> Default for ResourceStorage<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
 }
 
 impl<T> ResourceStorage<T> {
-    pub(crate) fn get(&self) -> &T {
-        self.inner.get()
+    pub fn new(resource: T) -> Self {
+        Self {
+            inner: EcsCell::new(Some(resource)),
+        }
+    }
+    /// Storage of a resource that is absent until inserted.
+    pub fn empty() -> Self {
+        Self {
+            inner: EcsCell::new(None),
+        }
     }
-    pub(crate) fn get_mut(&mut self) -> &mut T {
-        self.inner.get_mut()
+    pub(crate) fn get(&self) -> Option<&T> {
+        self.inner.get().as_ref()
+    }
+    pub(crate) fn get_mut(&mut self) -> Option<&mut T> {
+        self.inner.get_mut().as_mut()
     }
     #[allow(clippy::mut_from_ref)]
-    pub(crate) unsafe fn get_mut_unsafe(&self) -> &mut T {
-        unsafe { self.inner.get_mut_unsafe() }
+    pub(crate) unsafe fn get_mut_unsafe(&self) -> Option<&mut T> {
+        unsafe { self.inner.get_mut_unsafe() }.as_mut()
+    }
+    pub(crate) fn insert(&mut self, resource: T) -> Option<T> {
+        self.inner.get_mut().replace(resource)
+    }
+    pub(crate) fn take(&mut self) -> Option<T> {
+        self.inner.get_mut().take()
     }
 }
 
 pub struct OfResources<T>(T);
+
+/// Event queues are treated as resources, placed after the regular ones.
+pub trait EventStorageProvider<T> {
+    /// Index of the queue among resources.
+    const EVENTS_INDEX: TypeIndex;
+    fn events(&self) -> &ResourceStorage<Events<T>>;
+    fn events_mut(&mut self) -> &mut ResourceStorage<Events<T>>;
+}
+
+/// Indexes are treated as transient resources, placed after event queues.
+pub trait IndexStorageProvider<C, K> {
+    /// Index of the index among resources.
+    const INDEX_INDEX: TypeIndex;
+    fn index(&self) -> &ResourceStorage<ComponentIndex<C, K>>;
+    fn index_mut(&mut self) -> &mut ResourceStorage<ComponentIndex<C, K>>;
+}
+
+pub fn update_events<Storage: EventStorageProvider<T>, T>(storage: &mut Storage) {
+    storage
+        .events_mut()
+        .get_mut()
+        .expect("event queues always exist")
+        .update()
+}
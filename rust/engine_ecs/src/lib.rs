@@ -1,27 +1,57 @@
-use std::{collections::HashMap, mem};
+use std::{any, collections::HashMap, mem, sync::Arc};
 
-use internal::{ComponentStorageProvider, DynDispath, OfResources, ResourceStorageProvider};
+use entities::Entities;
+use internal::{
+    ComponentStorageProvider, DynDispath, EventStorageProvider, OfResources,
+    ResourceStorageProvider,
+};
 use query_world::WorldRef;
 use serde::{Deserialize, Serialize};
 use slotmapd::{new_key_type, KeyData};
 
 mod component_traits;
 mod ecs_cell;
+mod entities;
+mod entity_ref;
+mod error;
+mod executor;
+mod hierarchy;
+mod hooks;
 #[doc(hidden)]
 pub mod internal;
 mod query_world;
+mod save;
+mod schedule;
+mod system;
 mod system_parameter;
 
-pub use engine_macro::gen_storage_for_world;
-use system_parameter::changes::{ChangeManager, ReadOnly, WriteOnly};
+pub use engine_macro::{gen_storage_for_world, Bundle, QueryData, SystemParam};
+use system_parameter::{
+    changes::{ChangeManager, ReadOnly, WriteOnly},
+    query::QueryCache,
+    ComponentRequests,
+};
+
+use component_traits::TypeIndexStorage;
+use hooks::HookKind;
 
 pub use crate::{
     component_traits::{Bundle, Component},
+    entity_ref::{EntityMut, EntityRef},
+    error::EcsError,
+    hierarchy::{Children, Parent},
+    hooks::Hooks,
     query_world::{ParamGuard, QueryWorld, WorldRun},
+    save::Migrations,
+    schedule::{resource_changed, Schedule, ScheduleError},
+    system::System,
     system_parameter::{
-        changes::ChangesG,
-        commands::CommandsG,
-        query::{QueryG, WithG, WithoutG},
+        changes::{ChangesG, DespawnedG, SpawnedG},
+        commands::{CommandG, CommandsG},
+        events::{EventReaderG, EventWriterG, Events},
+        index::{ComponentIndex, IndexG, IndexedBy},
+        local::LocalG,
+        query::{AnyOfG, ChangedG, OrG, QueryG, WithG, WithoutG},
     },
 };
 
@@ -38,6 +68,18 @@ impl EntityID {
 
 pub type TypeIndex = u32;
 
+fn missing_resource<R>() -> ! {
+    panic!(
+        "{}",
+        EcsError::MissingResource {
+            resource: any::type_name::<R>()
+        }
+    )
+}
+
+/// Cycle at which something has happened. Zero means "never".
+pub(crate) type Tick = u32;
+
 pub trait Resource<Storage>: LocalTypeIndex<OfResources<Storage>> {}
 
 impl<Storage, T> Resource<Storage> for T
@@ -49,7 +91,7 @@ where
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct StorageID(u32);
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ArchetypeID(u32);
 
 /// Provides an index that is unique with all other types that implement this trait for the same `T`.
@@ -80,10 +122,13 @@ impl ArchetypeInfo {
 
 type TypeBox = Box<[TypeIndex]>;
 
-#[derive(Default, Clone, Serialize, Deserialize)]
+#[derive(Default, Clone)]
 struct ArchetypeManager {
     archetypes: Vec<ArchetypeInfo>,
     archetype_map: HashMap<TypeBox, ArchetypeID>,
+    /// Incremented every time a new archetype is created.
+    generation: u64,
+    query_cache: QueryCache,
 }
 
 impl ArchetypeManager {
@@ -97,6 +142,11 @@ impl ArchetypeManager {
         archetype_info.entities.push(entity);
         ret
     }
+    /// Archetypes that satisfy these requests, in the order they were created.
+    fn matching_archetypes(&self, req: &ComponentRequests) -> Arc<[ArchetypeID]> {
+        self.query_cache
+            .matching(&self.archetypes, self.generation, req)
+    }
     fn find_archetype(&self, components: &[TypeIndex]) -> Option<ArchetypeID> {
         self.archetype_map.get(components).copied()
     }
@@ -104,7 +154,9 @@ impl ArchetypeManager {
         &self,
         archetype: ArchetypeID,
     ) -> Option<StorageID> {
-        let index = C::TYPE_INDEX;
+        self.find_storage_by_index(archetype, C::TYPE_INDEX)
+    }
+    fn find_storage_by_index(&self, archetype: ArchetypeID, index: TypeIndex) -> Option<StorageID> {
         let arche_info = self.archetypes.get(archetype.0 as usize)?;
         arche_info
             .component_slots
@@ -114,14 +166,22 @@ impl ArchetypeManager {
     }
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+/// Serialized with names of component and resource types, see `Migrations`.
+#[derive(Clone)]
 pub struct World<Storage> {
-    entities: slotmapd::HopSlotMap<EntityID, EntityInfo>,
+    entities: Entities,
     archeman: ArchetypeManager,
     storage: Storage,
 
     changes_prev: ChangeManager<Storage, ReadOnly>,
     changes_new: ChangeManager<Storage, WriteOnly>,
+    change_tick: Tick,
+
+    hooks: Hooks<Storage>,
+}
+
+fn first_tick() -> Tick {
+    1
 }
 
 impl<Storage: DynDispath + Default> Default for World<Storage> {
@@ -132,6 +192,8 @@ impl<Storage: DynDispath + Default> Default for World<Storage> {
             storage: Default::default(),
             changes_prev: Default::default(),
             changes_new: Default::default(),
+            change_tick: first_tick(),
+            hooks: Storage::hooks(),
         }
     }
 }
@@ -168,6 +230,7 @@ impl<Storage: DynDispath> World<Storage> {
             entities: Vec::new(),
             component_slots,
         });
+        self.archeman.generation += 1;
         archetype_id
     }
     fn find_or_create_archetype(&mut self, components: &[TypeIndex]) -> ArchetypeID {
@@ -176,7 +239,8 @@ impl<Storage: DynDispath> World<Storage> {
             .unwrap_or_else(|| self.create_archetype(components))
     }
 
-    fn remove_from_archetype(&mut self, ent_info: EntityInfo) {
+    /// Removes entity from archetype's entity list, without touching component storages.
+    fn unregister_from_archetype(&mut self, ent_info: EntityInfo) {
         let arche_info = &mut self.archeman.archetypes[ent_info.archetype_id.0 as usize];
 
         let last_entity = arche_info.entities.len() - 1;
@@ -189,7 +253,12 @@ impl<Storage: DynDispath> World<Storage> {
         arche_info
             .entities
             .swap_remove(ent_info.in_archetype_id as usize);
+    }
+
+    fn remove_from_archetype(&mut self, ent_info: EntityInfo) {
+        self.unregister_from_archetype(ent_info);
 
+        let arche_info = &self.archeman.archetypes[ent_info.archetype_id.0 as usize];
         for (type_index, storage_id) in arche_info.component_slots.iter() {
             self.storage.dispath_mut(*type_index, |list| {
                 list.swap_remove(*storage_id, ent_info.in_archetype_id)
@@ -197,89 +266,479 @@ impl<Storage: DynDispath> World<Storage> {
         }
     }
 
+    /// Moves entity to another archetype, carrying over components that are present in both of them.
+    ///
+    /// Components listed in `replaced` are dropped instead, as well as the ones new archetype doesn't have.
+    /// Caller has to add every component of the new archetype that wasn't carried over.
+    fn migrate_entity(
+        &mut self,
+        entity: EntityID,
+        ent_info: EntityInfo,
+        new_archetype: ArchetypeID,
+        replaced: &[TypeIndex],
+    ) {
+        self.unregister_from_archetype(ent_info);
+
+        let arche_info = &self.archeman.archetypes[ent_info.archetype_id.0 as usize];
+        for &(type_index, storage_id) in arche_info.component_slots.iter() {
+            let target = if replaced.contains(&type_index) {
+                None
+            } else {
+                self.archeman
+                    .find_storage_by_index(new_archetype, type_index)
+            };
+            self.storage.dispath_mut(type_index, |list| match target {
+                Some(target) => list.move_to(storage_id, ent_info.in_archetype_id, target),
+                None => list.swap_remove(storage_id, ent_info.in_archetype_id),
+            });
+        }
+
+        let in_archetype_id = self.archeman.register_entity(new_archetype, entity);
+        *self.entities.get_mut(entity).expect("should exist") = EntityInfo {
+            archetype_id: new_archetype,
+            in_archetype_id,
+        };
+    }
+
+    /// Type indices of entity's components, both from its archetype and sparse ones, in ascending order.
+    fn component_types(&self, entity: EntityID, info: EntityInfo) -> TypeIndexStorage {
+        let mut components: TypeIndexStorage = self.archeman.archetypes
+            [info.archetype_id.0 as usize]
+            .component_slots
+            .iter()
+            .map(|(type_index, _storage_id)| *type_index)
+            .collect();
+        let sparse = self.sparse_types(entity);
+        if !sparse.is_empty() {
+            components.extend_from_slice(&sparse);
+            components.sort();
+        }
+        components
+    }
+
+    /// Type indices of entity's sparse components.
+    fn sparse_types(&self, entity: EntityID) -> TypeIndexStorage {
+        Storage::SPARSE_TYPES
+            .iter()
+            .copied()
+            .filter(|&type_index| {
+                self.storage
+                    .dispath(type_index, |list| list.sparse_contains(entity))
+            })
+            .collect()
+    }
+
+    fn has_component(&self, entity: EntityID, info: EntityInfo, type_index: TypeIndex) -> bool {
+        if Storage::is_sparse(type_index) {
+            return self
+                .storage
+                .dispath(type_index, |list| list.sparse_contains(entity));
+        }
+        self.archeman
+            .find_storage_by_index(info.archetype_id, type_index)
+            .is_some()
+    }
+
+    /// Components that are kept in archetype columns, out of these.
+    fn table_types(components: &[TypeIndex]) -> TypeIndexStorage {
+        components
+            .iter()
+            .copied()
+            .filter(|&type_index| !Storage::is_sparse(type_index))
+            .collect()
+    }
+
     /// Spawn an entity with this bundle of components.
     pub fn spawn<B: Bundle<Storage>>(&mut self, bundle: B) -> EntityID {
+        let entity = self.entities.reserve();
+        self.spawn_reserved(entity, B::type_ids(), |world, archetype| {
+            bundle.add_to_archetype_in_storage(world, archetype, entity)
+        });
+        entity
+    }
+
+    /// Spawns an entity for every bundle, returning their IDs in the same order.
+    ///
+    /// Faster than calling `spawn` for each of them, as the archetype is looked up once and every storage grows once.
+    /// Bundles are collected first, which preallocates only according to the lower bound of the iterator's size hint.
+    pub fn spawn_batch<B, I>(&mut self, bundles: I) -> Vec<EntityID>
+    where
+        B: Bundle<Storage>,
+        I: IntoIterator<Item = B>,
+    {
+        let bundles = bundles.into_iter().collect::<Vec<_>>();
         let mut components = B::type_ids();
         components.sort();
-        let archetype = self.find_or_create_archetype(components.as_slice());
-        bundle.add_to_archetype_in_storage(self, archetype);
-        self.entities.insert_with_key(|entity| EntityInfo {
-            archetype_id: archetype,
-            in_archetype_id: self.archeman.register_entity(archetype, entity),
-        })
+        let archetype = self.reserve::<B>(bundles.len());
+        let first = self.archeman.archetypes[archetype.0 as usize].len();
+        let spawned = (first..)
+            .take(bundles.len())
+            .map(|in_archetype_id| {
+                self.entities.spawn(EntityInfo {
+                    archetype_id: archetype,
+                    in_archetype_id,
+                })
+            })
+            .collect::<Vec<_>>();
+        self.archeman.archetypes[archetype.0 as usize]
+            .entities
+            .extend_from_slice(&spawned);
+        B::add_many_to_archetype_in_storage(bundles, self, archetype, &spawned);
+        for &type_index in components.iter() {
+            self.changes_new.mark_spawned_many(type_index, &spawned);
+        }
+        let commands = self.run_hooks(HookKind::Add, &spawned, &components);
+        self.apply_hook_commands(commands);
+        spawned
+    }
+
+    /// Makes room for `additional` more entities with bundle `B`, creating its archetype if needed.
+    ///
+    /// Returns the archetype, which entities spawned with exactly this bundle go to.
+    pub fn reserve<B: Bundle<Storage>>(&mut self, additional: usize) -> ArchetypeID {
+        let mut components = B::type_ids();
+        components.sort();
+        let archetype = self.find_or_create_archetype(&Self::table_types(&components));
+        let arche_info = &mut self.archeman.archetypes[archetype.0 as usize];
+        arche_info.entities.reserve(additional);
+        for &(type_index, storage_id) in arche_info.component_slots.iter() {
+            self.storage
+                .dispath_mut(type_index, |list| list.reserve(storage_id, additional));
+        }
+        for &type_index in components.iter() {
+            if Storage::is_sparse(type_index) {
+                self.storage
+                    .dispath_mut(type_index, |list| list.sparse_reserve(additional));
+            }
+        }
+        self.entities.reserve_capacity(additional);
+        archetype
+    }
+
+    /// Spawns an entity with a reserved ID, `add` has to add all of `components` to the archetype.
+    ///
+    /// Returns false if the ID is not reserved.
+    pub(crate) fn spawn_reserved(
+        &mut self,
+        entity: EntityID,
+        mut components: TypeIndexStorage,
+        add: impl FnOnce(&mut Self, ArchetypeID),
+    ) -> bool {
+        if !self.entities.is_reserved(entity) {
+            return false;
+        }
+        components.sort();
+        let archetype = self.find_or_create_archetype(&Self::table_types(&components));
+        add(self, archetype);
+        let in_archetype_id = self.archeman.register_entity(archetype, entity);
+        self.entities.spawn_reserved(
+            entity,
+            EntityInfo {
+                archetype_id: archetype,
+                in_archetype_id,
+            },
+        );
+        for &type_index in components.iter() {
+            self.changes_new.mark_spawned(type_index, entity);
+        }
+        let commands = self.run_hooks(HookKind::Add, &[entity], &components);
+        self.apply_hook_commands(commands);
+        true
     }
 
     pub fn entity_count(&self) -> u32 {
         self.entities.len() as u32
     }
 
+    /// Changes every time a new archetype is created, which happens when an entity gets a new set of components.
+    pub fn archetype_generation(&self) -> u64 {
+        self.archeman.generation
+    }
+
+    /// Despawns entity without updating `Parent` and `Children` of other entities.
     fn _despawn(&mut self, entity: EntityID) -> Option<()> {
         let ent_info = self.entities.get(entity)?;
-        self.remove_from_archetype(*ent_info);
+        let components = self.component_types(entity, ent_info);
+        for &type_index in components.iter() {
+            self.changes_new.mark_despawned(type_index, entity);
+        }
+        let commands = self.run_hooks(HookKind::Remove, &[entity], &components);
+        self.remove_from_archetype(ent_info);
+        self.remove_sparse(entity, &components);
         self.entities.remove(entity);
+        self.apply_hook_commands(commands);
         Some(())
     }
 
+    /// Despawns entity, detaching it from its parent. Its children lose their parent, but stay alive.
+    ///
+    /// See `despawn_recursive` for despawning children as well. Also releases IDs reserved with `CommandsG::spawn`.
     pub fn despawn(&mut self, entity: EntityID) -> bool {
+        if !self.entities.contains(entity) {
+            return self.entities.remove(entity).is_some();
+        }
+        self.unlink_from_hierarchy(entity);
         self._despawn(entity).is_some()
     }
 
+    /// Add components of this bundle to an existing entity, replacing the ones it already has.
+    ///
+    /// Moves the entity to a different archetype if needed. Returns false if entity does not exist.
+    pub fn insert<B: Bundle<Storage>>(&mut self, entity: EntityID, bundle: B) -> bool {
+        self.insert_components(entity, B::type_ids(), |world, archetype| {
+            bundle.add_to_archetype_in_storage(world, archetype, entity)
+        })
+    }
+
+    /// Like `insert`, `add` has to add all of `added` components to the archetype.
+    pub(crate) fn insert_components(
+        &mut self,
+        entity: EntityID,
+        added: TypeIndexStorage,
+        add: impl FnOnce(&mut Self, ArchetypeID),
+    ) -> bool {
+        let Some(ent_info) = self.entities.get(entity) else {
+            return false;
+        };
+        let mut components = self.component_types(entity, ent_info);
+        let new: TypeIndexStorage = added
+            .iter()
+            .copied()
+            .filter(|type_index| !components.contains(type_index))
+            .collect();
+        for &type_index in new.iter() {
+            self.changes_new.mark_spawned(type_index, entity);
+        }
+        components.extend_from_slice(&added);
+        components.sort();
+        components.dedup();
+
+        let archetype = self.find_or_create_archetype(&Self::table_types(&components));
+        let replaced = Self::table_types(&added);
+        // Entities that only get sparse components stay where they are.
+        if archetype != ent_info.archetype_id || !replaced.is_empty() {
+            self.migrate_entity(entity, ent_info, archetype, &replaced);
+        }
+        add(self, archetype);
+        let commands = self.run_hooks(HookKind::Add, &[entity], &new);
+        self.apply_hook_commands(commands);
+        true
+    }
+
+    /// Remove components of this bundle from an entity. Components that entity doesn't have are ignored.
+    ///
+    /// Moves the entity to a different archetype if needed. Returns false if entity does not exist.
+    pub fn remove<B: Bundle<Storage>>(&mut self, entity: EntityID) -> bool {
+        self.remove_components(entity, &B::type_ids())
+    }
+
+    /// Like `remove`, for components with these type indices.
+    pub(crate) fn remove_components(&mut self, entity: EntityID, removed: &[TypeIndex]) -> bool {
+        let Some(ent_info) = self.entities.get(entity) else {
+            return false;
+        };
+        let current = self.component_types(entity, ent_info);
+        let components: TypeIndexStorage = current
+            .iter()
+            .copied()
+            .filter(|type_index| !removed.contains(type_index))
+            .collect();
+        let removed: TypeIndexStorage = current
+            .iter()
+            .copied()
+            .filter(|type_index| removed.contains(type_index))
+            .collect();
+        for &type_index in removed.iter() {
+            self.changes_new.mark_despawned(type_index, entity);
+        }
+        if !removed.is_empty() {
+            let commands = self.run_hooks(HookKind::Remove, &[entity], &removed);
+            self.remove_sparse(entity, &removed);
+            let archetype = self.find_or_create_archetype(&Self::table_types(&components));
+            if archetype != ent_info.archetype_id {
+                self.migrate_entity(entity, ent_info, archetype, &[]);
+            }
+            self.apply_hook_commands(commands);
+        }
+        true
+    }
+
+    /// Removes sparse components out of these from the entity, ignoring other components.
+    fn remove_sparse(&mut self, entity: EntityID, components: &[TypeIndex]) {
+        for &type_index in components {
+            if Storage::is_sparse(type_index) {
+                self.storage
+                    .dispath_mut(type_index, |list| list.sparse_remove(entity));
+            }
+        }
+    }
+
     pub fn get<C>(&self, entity: EntityID) -> Option<&C>
     where
         Storage: ComponentStorageProvider<C>,
         C: Component<Storage>,
     {
         let info = self.entities.get(entity)?;
+        self.get_at(entity, info)
+    }
+
+    pub fn get_mut<C>(&mut self, entity: EntityID) -> Option<&mut C>
+    where
+        Storage: ComponentStorageProvider<C>,
+        C: Component<Storage>,
+    {
+        let info = self.entities.get(entity)?;
+        self.get_mut_at(entity, info)
+    }
+
+    fn get_at<C>(&self, entity: EntityID, info: EntityInfo) -> Option<&C>
+    where
+        Storage: ComponentStorageProvider<C>,
+        C: Component<Storage>,
+    {
+        if Storage::is_sparse(C::TYPE_INDEX) {
+            return self.storage.storage().sparse().get(entity);
+        }
         let storage_id = self
             .archeman
             .find_storage::<Storage, C>(info.archetype_id)?;
         self.storage.storage().get(storage_id, info.in_archetype_id)
     }
 
-    pub fn get_mut<C>(&mut self, entity: EntityID) -> Option<&mut C>
+    fn get_mut_at<C>(&mut self, entity: EntityID, info: EntityInfo) -> Option<&mut C>
     where
         Storage: ComponentStorageProvider<C>,
         C: Component<Storage>,
     {
-        let info = self.entities.get(entity)?;
+        if Storage::is_sparse(C::TYPE_INDEX) {
+            return self
+                .storage
+                .storage_mut()
+                .sparse_mut()
+                .get_mut(entity, self.change_tick);
+        }
         let storage_id = self
             .archeman
             .find_storage::<Storage, C>(info.archetype_id)?;
         self.storage
             .storage_mut()
-            .get_mut(storage_id, info.in_archetype_id)
+            .get_mut(storage_id, info.in_archetype_id, self.change_tick)
     }
 
+    /// # Panics
+    ///
+    /// Panics if the resource does not exist, see `get_resource`.
     pub fn resource<R>(&self) -> &R
     where
         Storage: ResourceStorageProvider<R>,
     {
-        self.storage.storage().get()
+        self.get_resource()
+            .unwrap_or_else(|| missing_resource::<R>())
     }
 
+    /// # Panics
+    ///
+    /// Panics if the resource does not exist.
     pub fn resource_mut<R>(&mut self) -> &mut R
     where
         R: LocalTypeIndex<OfResources<Storage>>,
         Storage: ResourceStorageProvider<R>,
     {
         self.changes_new.mark_resource_as_changed(R::TYPE_INDEX);
-        self.storage.storage_mut().get_mut()
+        ResourceStorageProvider::<R>::storage_mut(&mut self.storage)
+            .get_mut()
+            .unwrap_or_else(|| missing_resource::<R>())
     }
 
-    /// Used by component bundles to add themselves to an archetype
-    #[doc(hidden)]
-    pub fn add_bundle_to_archetype<T>(&mut self, archetype: ArchetypeID, component: T)
+    pub fn get_resource<R>(&self) -> Option<&R>
+    where
+        Storage: ResourceStorageProvider<R>,
+    {
+        ResourceStorageProvider::<R>::storage(&self.storage).get()
+    }
+
+    pub fn contains_resource<R>(&self) -> bool
+    where
+        Storage: ResourceStorageProvider<R>,
+    {
+        self.get_resource::<R>().is_some()
+    }
+
+    /// Inserts a resource, replacing the previous value if there was one.
+    pub fn insert_resource<R>(&mut self, resource: R)
+    where
+        R: LocalTypeIndex<OfResources<Storage>>,
+        Storage: ResourceStorageProvider<R>,
+    {
+        self.changes_new.mark_resource_as_changed(R::TYPE_INDEX);
+        ResourceStorageProvider::<R>::storage_mut(&mut self.storage).insert(resource);
+    }
+
+    /// Removes a resource, so that systems that require it can't run until it's inserted again.
+    pub fn remove_resource<R>(&mut self) -> Option<R>
     where
+        R: LocalTypeIndex<OfResources<Storage>>,
+        Storage: ResourceStorageProvider<R>,
+    {
+        let removed = ResourceStorageProvider::<R>::storage_mut(&mut self.storage).take();
+        if removed.is_some() {
+            self.changes_new.mark_resource_as_changed(R::TYPE_INDEX);
+        }
+        removed
+    }
+
+    /// Used by component bundles to add themselves to an archetype, sparse components are added to `entity` instead.
+    #[doc(hidden)]
+    pub fn add_bundle_to_archetype<T>(
+        &mut self,
+        archetype: ArchetypeID,
+        entity: EntityID,
+        component: T,
+    ) where
         Storage: ComponentStorageProvider<T>,
-        T: Bundle<Storage> + LocalTypeIndex<Storage>,
+        T: Component<Storage>,
     {
+        if Storage::is_sparse(T::TYPE_INDEX) {
+            self.storage
+                .storage_mut()
+                .sparse_mut()
+                .insert(entity, component, self.change_tick);
+            return;
+        }
         let storage = self
             .archeman
             .find_storage::<Storage, T>(archetype)
             .expect("Required archetype exists");
         self.storage
             .storage_mut()
-            .add_to_storage(storage, component)
+            .add_to_storage(storage, component, self.change_tick)
+    }
+
+    /// Same as `add_bundle_to_archetype` for many entities at once, growing each storage once.
+    #[doc(hidden)]
+    pub fn add_many_to_archetype<T>(
+        &mut self,
+        archetype: ArchetypeID,
+        entities: &[EntityID],
+        components: Vec<T>,
+    ) where
+        Storage: ComponentStorageProvider<T>,
+        T: Component<Storage>,
+    {
+        if Storage::is_sparse(T::TYPE_INDEX) {
+            let sparse = self.storage.storage_mut().sparse_mut();
+            for (&entity, component) in entities.iter().zip(components) {
+                sparse.insert(entity, component, self.change_tick);
+            }
+            return;
+        }
+        let storage = self
+            .archeman
+            .find_storage::<Storage, T>(archetype)
+            .expect("Required archetype exists");
+        self.storage
+            .storage_mut()
+            .extend_storage(storage, components, self.change_tick)
     }
 
     pub fn query_world_shared(&self) -> query_world::QueryWorld<Storage> {
@@ -294,7 +753,39 @@ impl<Storage: DynDispath> World<Storage> {
         self.changes_prev = mem::replace(&mut self.changes_new, Default::default()).to_read_only();
     }
 
+    /// Returns true if something that happened at `tick` should be considered a recent change.
+    ///
+    /// Only changes made during the previous cycle are recent, same as with `ChangesG`,
+    /// so every change is seen exactly once by a system that runs every cycle.
+    pub(crate) fn changed_recently(&self, tick: Tick) -> bool {
+        tick != 0 && self.change_tick.wrapping_sub(tick) == 1
+    }
+
+    pub fn events<T>(&self) -> &Events<T>
+    where
+        Storage: EventStorageProvider<T>,
+    {
+        self.storage
+            .events()
+            .get()
+            .expect("event queues always exist")
+    }
+
+    /// Sends an event, which can be read by `EventReader`s during this and the next cycle.
+    pub fn send_event<T>(&mut self, event: T)
+    where
+        Storage: EventStorageProvider<T>,
+    {
+        self.storage
+            .events_mut()
+            .get_mut()
+            .expect("event queues always exist")
+            .send(event)
+    }
+
     pub fn next_cycle(&mut self) {
-        self.cycle_change_managers()
+        self.cycle_change_managers();
+        self.storage.update_events();
+        self.change_tick = self.change_tick.wrapping_add(1).max(first_tick());
     }
 }
@@ -2,24 +2,38 @@ use super::{ArchetypeID, InArchetypeID, StorageID, World};
 use crate::{
     component_traits::Component,
     internal::{ComponentStorageProvider, DynDispath, OfResources, ResourceStorageProvider},
-    system_parameter::{commands::CommandBuffer, ComponentRequests, SystemParameter},
-    LocalTypeIndex,
+    missing_resource,
+    system_parameter::{
+        chunks::ChunkMut,
+        commands::{CommandBuffer, CommandG, CommandList},
+        ComponentRequests, SystemParameter,
+    },
+    EcsError, EntityID, LocalTypeIndex, TypeIndex,
 };
 use engine_macro::gen_world_run_impls;
 use std::{
-    cell::RefCell,
+    any::Any,
+    cell::{Cell, RefCell},
     ops::{Deref, DerefMut, Range},
+    ptr,
 };
 
 pub(crate) enum WorldRef<'wrld, Storage> {
     Shared(&'wrld World<Storage>),
     Exclusive(&'wrld mut World<Storage>),
+    /// Grants exclusive access to whatever gets requested, while only holding a shared reference.
+    ///
+    /// Used by executor, which ensures that requests of concurrently running systems don't conflict.
+    /// Commands are not applied on drop, executor takes them instead.
+    Scoped(&'wrld World<Storage>),
 }
 
 impl<'wrld, Storage> WorldRef<'wrld, Storage> {
     fn ref_mut(&mut self) -> &mut World<Storage> {
         match self {
-            WorldRef::Shared(_) => panic!("Can't get mut reference from shared kind of WorldRef"),
+            WorldRef::Shared(_) | WorldRef::Scoped(_) => {
+                panic!("Can't get mut reference from shared kind of WorldRef")
+            }
             WorldRef::Exclusive(world) => world,
         }
     }
@@ -32,27 +46,49 @@ impl<'wrld, Storage> Deref for WorldRef<'wrld, Storage> {
         match self {
             WorldRef::Shared(world) => world,
             WorldRef::Exclusive(world) => world,
+            WorldRef::Scoped(world) => world,
         }
     }
 }
 
+/// State that belongs to a particular system and persists between its runs, like cursors of event readers.
+#[derive(Default)]
+pub(crate) struct SystemLocals {
+    slots: Vec<Box<dyn Any + Send>>,
+}
+
 pub struct QueryWorld<'wrld, Storage: DynDispath> {
     pub(crate) inner: WorldRef<'wrld, Storage>,
     currently_requested: RefCell<Vec<ComponentRequests>>,
     parameter_index: RefCell<usize>,
     pub(crate) command_buffer: CommandBuffer<Storage>,
+    /// Locals of the system that is currently running, null if there is no such system.
+    locals: Cell<*mut SystemLocals>,
+    local_index: Cell<usize>,
+    /// IDs reserved for spawned entities, see `reserve_entity`.
+    reservations: Cell<Reservations>,
+}
+
+/// Every `lanes`-th candidate ID, starting from `first`, goes to whoever reserves through this QueryWorld.
+#[derive(Clone, Copy)]
+struct Reservations {
+    first: usize,
+    lanes: usize,
+    taken: usize,
 }
 
 impl<'wrld, Storage: DynDispath> Drop for QueryWorld<'wrld, Storage> {
     fn drop(&mut self) {
-        let mut cmd_buf = Vec::with_capacity(self.command_buffer.len());
-        while let Some(cmd) = self.command_buffer.pop() {
-            cmd_buf.push(cmd);
+        if matches!(self.inner, WorldRef::Scoped(..)) {
+            return;
         }
-        cmd_buf.sort_by_key(|x| x.0);
-        for (_key, cmd) in cmd_buf {
-            cmd(self.inner.ref_mut())
+        let commands = self.take_commands();
+        if commands.is_empty() {
+            return;
         }
+        self.inner
+            .ref_mut()
+            .apply_commands(commands.into_iter().map(|(_key, cmd)| cmd))
     }
 }
 
@@ -89,11 +125,68 @@ impl<'wrld, Storage: DynDispath> QueryWorld<'wrld, Storage> {
             currently_requested: Default::default(),
             parameter_index: 0.into(),
             command_buffer: CommandBuffer::default(),
+            locals: Cell::new(ptr::null_mut()),
+            local_index: Cell::new(0),
+            reservations: Cell::new(Reservations {
+                first: 0,
+                lanes: 1,
+                taken: 0,
+            }),
+        }
+    }
+
+    /// Makes IDs reserved from now on take every `lanes`-th candidate, starting from the `first` one.
+    ///
+    /// Systems that run at the same time get different lanes, so they never reserve the same ID,
+    /// and the IDs they get don't depend on which of them reserves first.
+    pub(crate) fn set_reservation_lane(&self, first: usize, lanes: usize) {
+        self.reservations.set(Reservations {
+            first,
+            lanes,
+            taken: 0,
+        });
+    }
+
+    /// Number of candidates that are taken by this QueryWorld or skipped over, see `set_reservation_lane`.
+    pub(crate) fn reserved_candidates(&self) -> usize {
+        let reservations = self.reservations.get();
+        match reservations.taken {
+            0 => 0,
+            taken => reservations.first + reservations.lanes * (taken - 1) + 1,
         }
     }
 
+    /// Reserves an ID for an entity spawned by a command, which is claimed once the command is applied.
+    pub(crate) fn reserve_entity(&self) -> EntityID {
+        let mut reservations = self.reservations.get();
+        let n = reservations.first + reservations.lanes * reservations.taken;
+        reservations.taken += 1;
+        self.reservations.set(reservations);
+        self.inner.entities.candidate(n)
+    }
+
     pub fn exclusive(&self) -> bool {
-        matches!(self.inner, WorldRef::Exclusive(..))
+        matches!(self.inner, WorldRef::Exclusive(..) | WorldRef::Scoped(..))
+    }
+
+    /// Takes commands submitted so far, in the order they should be applied, so that they are not applied on drop.
+    ///
+    /// Commands can then be logged or serialized, and applied with `World::apply_commands`.
+    pub fn drain_commands(&self) -> Vec<CommandG<Storage>> {
+        self.take_commands()
+            .into_iter()
+            .map(|(_param_index, command)| command)
+            .collect()
+    }
+
+    /// Takes submitted commands, in the order they should be applied.
+    pub(crate) fn take_commands(&self) -> CommandList<Storage> {
+        let mut cmd_buf = Vec::with_capacity(self.command_buffer.len());
+        while let Some(cmd) = self.command_buffer.pop() {
+            cmd_buf.push(cmd);
+        }
+        cmd_buf.sort_by_key(|x| x.0);
+        cmd_buf
     }
 
     /// # Safety
@@ -118,6 +211,30 @@ impl<'wrld, Storage: DynDispath> QueryWorld<'wrld, Storage> {
         storage: StorageID,
         index_in_arche: InArchetypeID,
     ) -> Option<&mut T>
+    where
+        Storage: ComponentStorageProvider<T>,
+    {
+        unsafe {
+            self.inner.storage.storage().get_mut_unsafe(
+                storage,
+                index_in_arche,
+                self.inner.change_tick,
+            )
+        }
+    }
+    /// # Safety
+    ///
+    /// See `get` method.
+    pub(crate) unsafe fn column<T>(&self, storage: StorageID) -> &[T]
+    where
+        Storage: ComponentStorageProvider<T>,
+    {
+        self.inner.storage.storage().column(storage)
+    }
+    /// # Safety
+    ///
+    /// See `get_mut` method.
+    pub(crate) unsafe fn column_mut<T>(&self, storage: StorageID) -> ChunkMut<'_, T>
     where
         Storage: ComponentStorageProvider<T>,
     {
@@ -125,9 +242,20 @@ impl<'wrld, Storage: DynDispath> QueryWorld<'wrld, Storage> {
             self.inner
                 .storage
                 .storage()
-                .get_mut_unsafe(storage, index_in_arche)
+                .column_mut_unsafe(storage, self.inner.change_tick)
         }
     }
+    /// # Safety
+    ///
+    /// See `get` method.
+    pub unsafe fn changed<T>(&self, storage: StorageID, index_in_arche: InArchetypeID) -> bool
+    where
+        Storage: ComponentStorageProvider<T>,
+    {
+        let tick = ComponentStorageProvider::<T>::storage(&self.inner.storage)
+            .changed_tick(storage, index_in_arche);
+        self.inner.changed_recently(tick)
+    }
     pub fn storage_for_archetype<T: Component<Storage>>(
         &self,
         archetype: ArchetypeID,
@@ -135,71 +263,185 @@ impl<'wrld, Storage: DynDispath> QueryWorld<'wrld, Storage> {
         self.inner.archeman.find_storage::<Storage, T>(archetype)
     }
 
+    /// # Safety
+    ///
+    /// Aliasing rules have to be upheld per entity and component type.
+    pub unsafe fn get_sparse<T>(&self, entity: EntityID) -> Option<&T>
+    where
+        Storage: ComponentStorageProvider<T>,
+    {
+        self.inner.storage.storage().sparse().get(entity)
+    }
+    /// # Safety
+    ///
+    /// See `get_sparse` method.
+    /// Not safe to call when exclusive is false.
+    pub unsafe fn get_sparse_mut<T>(&self, entity: EntityID) -> Option<&mut T>
+    where
+        Storage: ComponentStorageProvider<T>,
+    {
+        unsafe {
+            self.inner
+                .storage
+                .storage()
+                .sparse()
+                .get_mut_unsafe(entity, self.inner.change_tick)
+        }
+    }
+    /// # Safety
+    ///
+    /// See `get_sparse` method.
+    pub(crate) unsafe fn changed_sparse<T>(&self, entity: EntityID) -> bool
+    where
+        Storage: ComponentStorageProvider<T>,
+    {
+        ComponentStorageProvider::<T>::storage(&self.inner.storage)
+            .sparse()
+            .changed_tick(entity)
+            .is_some_and(|tick| self.inner.changed_recently(tick))
+    }
+    /// Entities that have a sparse component of this type.
+    ///
+    /// Doesn't access components, so it doesn't have to be requested.
+    pub fn sparse_entities<T: 'wrld>(&'wrld self) -> &'wrld [EntityID]
+    where
+        Storage: ComponentStorageProvider<T>,
+    {
+        ComponentStorageProvider::<T>::storage(&self.inner.storage)
+            .sparse()
+            .entities()
+    }
+    /// True if entity has a sparse component of this type.
+    ///
+    /// Doesn't access components, so it doesn't have to be requested.
+    pub fn contains_sparse(&self, type_index: TypeIndex, entity: EntityID) -> bool {
+        self.inner
+            .storage
+            .dispath(type_index, |list| list.sparse_contains(entity))
+    }
+    pub(crate) fn entity_at(&self, archetype: ArchetypeID, index: InArchetypeID) -> EntityID {
+        self.inner.archeman.archetypes[archetype.0 as usize].entities[index as usize]
+    }
+
     /// # Safety
     ///
     /// Aliasing rules have to be upheld per resource type.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the resource does not exist.
     pub unsafe fn resource<R>(&self) -> &R
     where
         Storage: ResourceStorageProvider<R>,
     {
-        self.inner.storage.storage().get()
+        self.inner.resource()
+    }
+
+    /// # Safety
+    ///
+    /// See `resource` method.
+    pub unsafe fn get_resource<R>(&self) -> Option<&R>
+    where
+        Storage: ResourceStorageProvider<R>,
+    {
+        self.inner.get_resource()
     }
 
     /// # Safety
     ///
     /// See `resource` method.
     /// Not safe to call when exclusive is false.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the resource does not exist.
     #[allow(clippy::mut_from_ref)]
     pub unsafe fn resource_mut<R>(&self) -> &mut R
+    where
+        R: LocalTypeIndex<OfResources<Storage>>,
+        Storage: ResourceStorageProvider<R>,
+    {
+        unsafe { self.get_resource_mut() }.unwrap_or_else(|| missing_resource::<R>())
+    }
+
+    /// # Safety
+    ///
+    /// See `resource_mut` method.
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn get_resource_mut<R>(&self) -> Option<&mut R>
     where
         R: LocalTypeIndex<OfResources<Storage>>,
         Storage: ResourceStorageProvider<R>,
     {
         unsafe {
+            let resource =
+                ResourceStorageProvider::<R>::storage(&self.inner.storage).get_mut_unsafe()?;
             self.inner
                 .changes_new
                 .mark_resource_as_changed_unsafe(R::TYPE_INDEX);
-            self.inner.storage.storage().get_mut_unsafe()
+            Some(resource)
         }
     }
 
+    /// # Panics
+    ///
+    /// Panics if the parameter can't be created, see `try_parameter`.
     pub fn parameter<Param: SystemParameter<'wrld, Storage>>(
         &'wrld self,
     ) -> ParamGuard<Storage, Param> {
-        let (holds, param) = self.parameter_raw();
-        ParamGuard {
+        self.try_parameter().unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Creates a parameter, unless it conflicts with parameters that are currently held, or can't be created.
+    pub fn try_parameter<Param: SystemParameter<'wrld, Storage>>(
+        &'wrld self,
+    ) -> Result<ParamGuard<'wrld, 'wrld, Storage, Param>, EcsError> {
+        let (holds, param) = self.try_parameter_raw()?;
+        Ok(ParamGuard {
             inner: param,
             world: self,
             holds,
-        }
+        })
     }
 
-    pub(crate) fn parameter_raw<Param: SystemParameter<'wrld, Storage>>(
+    pub(crate) fn try_parameter_raw<Param: SystemParameter<'wrld, Storage>>(
         &'wrld self,
-    ) -> (Range<usize>, Param) {
+    ) -> Result<(Range<usize>, Param), EcsError> {
         *self.parameter_index.borrow_mut() += 1;
-        let requests = Param::requests();
         let req_start = self.currently_requested.borrow().len();
-        for new_request in requests {
-            for current_request in self.currently_requested.borrow().iter() {
-                if !self.exclusive() && new_request.any_exclusive() {
-                    panic!(
-                        "Exlusive (&mut) parameters are not supported in non-exclusive QueryWorld"
-                    );
-                }
-                if !new_request.safe_with(current_request) {
-                    panic!(
-                        "{:?} and {:?} are incompatible, and thus cannot be used at the same time.",
-                        current_request, new_request
-                    );
-                }
+        let req_range = || req_start..self.currently_requested.borrow().len();
+        for new_request in Param::requests() {
+            if let Err(err) = self.check_request(&new_request) {
+                self.release_parameter(req_range());
+                return Err(err);
             }
             self.currently_requested.borrow_mut().push(new_request);
         }
-        let req_end = self.currently_requested.borrow().len();
-        let req_range = req_start..req_end;
         // Safety: checked that requests are satisfied.
-        (req_range, unsafe { Param::from_world(self) })
+        match unsafe { Param::try_from_world(self) } {
+            Ok(param) => Ok((req_range(), param)),
+            Err(err) => {
+                self.release_parameter(req_range());
+                Err(err)
+            }
+        }
+    }
+
+    fn check_request(&self, new_request: &ComponentRequests) -> Result<(), EcsError> {
+        if !self.exclusive() && new_request.any_exclusive() {
+            return Err(EcsError::ExclusiveOnShared {
+                parameter: new_request.describe::<Storage>(),
+            });
+        }
+        for current_request in self.currently_requested.borrow().iter() {
+            if !new_request.safe_with(current_request) {
+                return Err(EcsError::IncompatibleRequests {
+                    first: current_request.describe::<Storage>(),
+                    second: new_request.describe::<Storage>(),
+                });
+            }
+        }
+        Ok(())
     }
 
     pub(crate) fn release_parameter(&self, holds: Range<usize>) {
@@ -211,10 +453,61 @@ impl<'wrld, Storage: DynDispath> QueryWorld<'wrld, Storage> {
     pub(crate) fn current_parameter_index(&self) -> usize {
         *self.parameter_index.borrow()
     }
+
+    /// Runs `f`, making `locals` available to parameters that are created during it.
+    pub(crate) fn with_locals<Ret>(
+        &self,
+        locals: &mut SystemLocals,
+        f: impl FnOnce() -> Ret,
+    ) -> Ret {
+        let prev_locals = self.locals.replace(locals);
+        let prev_index = self.local_index.replace(0);
+        let ret = f();
+        self.locals.set(prev_locals);
+        self.local_index.set(prev_index);
+        ret
+    }
+
+    /// Returns next slot of local state of the currently running system.
+    /// Slots are handed out in the same order every run, as parameters are.
+    ///
+    /// Returns None if parameters are requested outside of a system.
+    ///
+    /// # Safety
+    ///
+    /// Returned reference should not be used after the system finishes running.
+    #[allow(clippy::mut_from_ref)]
+    pub(crate) unsafe fn next_local<S: Default + Send + 'static>(&self) -> Option<&mut S> {
+        let locals = self.locals.get();
+        if locals.is_null() {
+            return None;
+        }
+        let index = self.local_index.get();
+        self.local_index.set(index + 1);
+        // Safety: locals outlive the run of the system, and each slot is handed out once per run.
+        let locals = unsafe { &mut *locals };
+        if index == locals.slots.len() {
+            locals.slots.push(Box::<S>::default());
+        }
+        Some(
+            locals.slots[index]
+                .downcast_mut()
+                .expect("system should request the same local state every run"),
+        )
+    }
 }
 
 pub trait WorldRun<'wrld, F, Ret, P> {
-    fn run(&'wrld self, f: F) -> Ret;
+    /// # Panics
+    ///
+    /// Panics if parameters can't be created, see `try_run`.
+    fn run(&'wrld self, f: F) -> Ret {
+        self.try_run(f).unwrap_or_else(|err| panic!("{err}"))
+    }
+    /// Runs `f` with its parameters, unless some of them can't be created.
+    fn try_run(&'wrld self, f: F) -> Result<Ret, EcsError>;
+    /// Requests of all parameters that `f` takes.
+    fn requests() -> Vec<ComponentRequests>;
 }
 
 gen_world_run_impls!(0);
@@ -0,0 +1,331 @@
+use std::{collections::HashMap, fmt, marker::PhantomData};
+
+use serde::{
+    de::{self, DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor},
+    ser::{SerializeMap, SerializeTuple},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use tracing::warn;
+
+use crate::{
+    entities::Entities,
+    internal::StorageSerde,
+    system_parameter::changes::{ChangeManager, ReadOnly, WriteOnly},
+    ArchetypeID, ArchetypeInfo, ArchetypeManager, Tick, TypeIndex, World,
+};
+
+/// Describes how to load worlds that were saved with an older set of components and resources.
+///
+/// Saves refer to types by their names, so types can be added, reordered or removed freely:
+/// removed types are dropped with a warning, new ones are empty (or default, in case of resources).
+/// Transient types are never saved, and are loaded as if they were new, except that entities keep
+/// their transient components with default values.
+/// Sparse components are saved along with their entities, so changing whether a component is sparse
+/// is like removing it and adding a new one with the same name.
+/// Dropping data requires a self-describing format like RON, binary formats fail to load such saves.
+///
+/// Returned by a function named in `: migrations` section of gen_storage_for_world!.
+pub struct Migrations<Storage> {
+    renamed_components: HashMap<&'static str, &'static str>,
+    renamed_resources: HashMap<&'static str, &'static str>,
+    steps: Vec<fn(&mut World<Storage>)>,
+}
+
+impl<Storage> Default for Migrations<Storage> {
+    fn default() -> Self {
+        Self {
+            renamed_components: HashMap::new(),
+            renamed_resources: HashMap::new(),
+            steps: Vec::new(),
+        }
+    }
+}
+
+impl<Storage> Migrations<Storage> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads component that was saved as `old` into `new`.
+    pub fn rename_component(mut self, old: &'static str, new: &'static str) -> Self {
+        self.renamed_components.insert(old, new);
+        self
+    }
+
+    /// Loads resource (or event queue, like `Events<Old>`) that was saved as `old` into `new`.
+    pub fn rename_resource(mut self, old: &'static str, new: &'static str) -> Self {
+        self.renamed_resources.insert(old, new);
+        self
+    }
+
+    /// Adds a step, which increments schema version.
+    ///
+    /// Loaded worlds run steps that were added after they were saved, once their types are remapped.
+    pub fn step(mut self, step: fn(&mut World<Storage>)) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    /// Version of the current schema, which is the number of steps.
+    pub fn version(&self) -> u32 {
+        self.steps.len() as u32
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Components,
+    Resources,
+}
+
+/// Storage map of one kind, written as `name: data` entries.
+struct Entries<'a, Storage>(&'a Storage, Kind);
+
+impl<Storage: StorageSerde> Serialize for Entries<'_, Storage> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let Entries(storage, kind) = *self;
+        match kind {
+            Kind::Components => {
+                let mut map = serializer.serialize_map(Some(Storage::COMPONENT_NAMES.len()))?;
+                storage.serialize_components(&mut map)?;
+                map.end()
+            }
+            Kind::Resources => {
+                let mut map = serializer.serialize_map(Some(Storage::RESOURCE_NAMES.len()))?;
+                storage.serialize_resources(&mut map)?;
+                map.end()
+            }
+        }
+    }
+}
+
+/// Reads storage map of one kind, returning names of saved types in their saved order, renamed by migrations.
+struct EntriesSeed<'a, Storage> {
+    storage: &'a mut Storage,
+    kind: Kind,
+    renamed: &'a HashMap<&'static str, &'static str>,
+}
+
+impl<'de, Storage: StorageSerde> DeserializeSeed<'de> for EntriesSeed<'_, Storage> {
+    type Value = Vec<String>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, Storage: StorageSerde> Visitor<'de> for EntriesSeed<'_, Storage> {
+    type Value = Vec<String>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map from type names to their storages")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut names = Vec::new();
+        while let Some(name) = map.next_key::<String>()? {
+            let name = match self.renamed.get(name.as_str()) {
+                Some(renamed) => renamed.to_string(),
+                None => name,
+            };
+            let found = match self.kind {
+                Kind::Components => self.storage.deserialize_component(&name, &mut map)?,
+                Kind::Resources => self.storage.deserialize_resource(&name, &mut map)?,
+            };
+            if !found {
+                map.next_value::<IgnoredAny>()?;
+            }
+            names.push(name);
+        }
+        Ok(names)
+    }
+}
+
+struct SavedStorage<'a, Storage>(&'a Storage);
+
+impl<Storage: StorageSerde> Serialize for SavedStorage<'_, Storage> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut tuple = serializer.serialize_tuple(2)?;
+        tuple.serialize_element(&Entries(self.0, Kind::Components))?;
+        tuple.serialize_element(&Entries(self.0, Kind::Resources))?;
+        tuple.end()
+    }
+}
+
+/// Storage along with names of saved components and resources, in the order of their saved type indices.
+struct LoadedStorage<Storage> {
+    storage: Storage,
+    components: Vec<String>,
+    resources: Vec<String>,
+}
+
+impl<'de, Storage: StorageSerde> Deserialize<'de> for LoadedStorage<Storage> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_tuple(2, LoadedStorageVisitor(PhantomData))
+    }
+}
+
+struct LoadedStorageVisitor<Storage>(PhantomData<fn() -> Storage>);
+
+impl<'de, Storage: StorageSerde> Visitor<'de> for LoadedStorageVisitor<Storage> {
+    type Value = LoadedStorage<Storage>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("component and resource storages")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let migrations = Storage::migrations();
+        let mut storage = Storage::default();
+        let mut next = |kind, renamed| {
+            let seed = EntriesSeed {
+                storage: &mut storage,
+                kind,
+                renamed,
+            };
+            seq.next_element_seed(seed)?
+                .ok_or_else(|| de::Error::invalid_length(kind as usize, &self))
+        };
+        let components = next(Kind::Components, &migrations.renamed_components)?;
+        let resources = next(Kind::Resources, &migrations.renamed_resources)?;
+        Ok(LoadedStorage {
+            storage,
+            components,
+            resources,
+        })
+    }
+}
+
+#[derive(Serialize)]
+#[serde(bound = "")]
+struct SavedWorld<'a, Storage: StorageSerde> {
+    version: u32,
+    entities: &'a Entities,
+    archetypes: &'a [ArchetypeInfo],
+    storage: SavedStorage<'a, Storage>,
+    changes_prev: &'a ChangeManager<Storage, ReadOnly>,
+    changes_new: &'a ChangeManager<Storage, WriteOnly>,
+    change_tick: Tick,
+}
+
+#[derive(Deserialize)]
+#[serde(bound = "")]
+struct LoadedWorld<Storage: StorageSerde> {
+    version: u32,
+    entities: Entities,
+    archetypes: Vec<ArchetypeInfo>,
+    storage: LoadedStorage<Storage>,
+    changes_prev: ChangeManager<Storage, ReadOnly>,
+    changes_new: ChangeManager<Storage, WriteOnly>,
+    change_tick: Tick,
+}
+
+impl<Storage: StorageSerde> Serialize for World<Storage> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SavedWorld {
+            version: Storage::migrations().version(),
+            entities: &self.entities,
+            archetypes: &self.archeman.archetypes,
+            storage: SavedStorage(&self.storage),
+            changes_prev: &self.changes_prev,
+            changes_new: &self.changes_new,
+            change_tick: self.change_tick,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, Storage: StorageSerde> Deserialize<'de> for World<Storage> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let loaded = LoadedWorld::<Storage>::deserialize(deserializer)?;
+        let migrations = Storage::migrations();
+        if loaded.version > migrations.version() {
+            return Err(de::Error::custom(format_args!(
+                "world was saved with schema version {}, which is newer than {}",
+                loaded.version,
+                migrations.version()
+            )));
+        }
+
+        let components = current_indices(&loaded.storage.components, Storage::COMPONENT_NAMES);
+        let resources = current_indices(&loaded.storage.resources, Storage::RESOURCE_NAMES);
+        let dropped = |saved: &[String], current: &[Option<TypeIndex>]| {
+            saved
+                .iter()
+                .zip(current)
+                .filter(|(_name, index)| index.is_none())
+                .map(|(name, _index)| name.clone())
+                .collect::<Vec<_>>()
+        };
+        let dropped_components = dropped(&loaded.storage.components, &components);
+        let dropped_resources = dropped(&loaded.storage.resources, &resources);
+        if !dropped_components.is_empty() || !dropped_resources.is_empty() {
+            warn!(
+                "Dropped types that no longer exist while loading world: components {dropped_components:?}, resources {dropped_resources:?}"
+            );
+        }
+
+        let mut archeman = ArchetypeManager {
+            archetypes: loaded.archetypes,
+            ..Default::default()
+        };
+        for (archetype_id, archetype) in archeman.archetypes.iter_mut().enumerate() {
+            let mut slots = archetype
+                .component_slots
+                .iter()
+                .filter_map(|&(saved, storage_id)| {
+                    Some((components.get(saved as usize).copied()??, storage_id))
+                })
+                // Components that became sparse are dropped, as their columns can't be read as sparse sets.
+                .filter(|&(type_index, _storage_id)| !Storage::is_sparse(type_index))
+                .collect::<Vec<_>>();
+            slots.sort_by_key(|&(type_index, _storage_id)| type_index);
+            archetype.component_slots = slots.into_boxed_slice();
+            // Archetypes might end up with the same components, if they only differed by removed ones.
+            // Entities stay where they are, new ones go to the first of them.
+            let key = archetype
+                .component_slots
+                .iter()
+                .map(|&(type_index, _storage_id)| type_index)
+                .collect();
+            archeman
+                .archetype_map
+                .entry(key)
+                .or_insert(ArchetypeID(archetype_id as u32));
+        }
+
+        let mut storage = loaded.storage.storage;
+        for archetype in &archeman.archetypes {
+            for &(type_index, storage_id) in archetype.component_slots.iter() {
+                storage.fill_transient(type_index, storage_id, archetype.len());
+            }
+        }
+
+        let mut world = World {
+            entities: loaded.entities,
+            archeman,
+            storage,
+            changes_prev: loaded.changes_prev.remap(&components, &resources),
+            changes_new: loaded.changes_new.remap(&components, &resources),
+            change_tick: loaded.change_tick,
+            hooks: Storage::hooks(),
+        };
+        for step in &migrations.steps[loaded.version as usize..] {
+            step(&mut world);
+        }
+        Ok(world)
+    }
+}
+
+/// Current type index of every saved type, or `None` if it doesn't exist anymore.
+fn current_indices(saved: &[String], names: &[&str]) -> Vec<Option<TypeIndex>> {
+    saved
+        .iter()
+        .map(|name| {
+            names
+                .iter()
+                .position(|current| current == name)
+                .map(|index| index as TypeIndex)
+        })
+        .collect()
+}
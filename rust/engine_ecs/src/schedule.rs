@@ -0,0 +1,243 @@
+use std::{collections::BTreeSet, fmt};
+
+use crate::{
+    executor::ParallelExecutor,
+    internal::{DynDispath, OfResources},
+    system_parameter::changes::ChangesG,
+    EcsError, LocalTypeIndex, System, World,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScheduleError {
+    /// System has an ordering constraint with a label that no system has.
+    UnknownLabel {
+        system: &'static str,
+        label: &'static str,
+    },
+    /// Ordering constraints between those systems form a cycle.
+    Cycle(Vec<&'static str>),
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleError::UnknownLabel { system, label } => {
+                write!(f, "system {system} refers to unknown label {label}")
+            }
+            ScheduleError::Cycle(systems) => {
+                write!(f, "ordering constraints form a cycle between {systems:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ScheduleError {}
+
+/// A set of systems, ordered by their `before`/`after` constraints.
+///
+/// Systems without constraints between them run in the order they were added.
+/// Commands are applied after all systems, in the same order as if systems were run one by one on a single QueryWorld.
+/// Exclusive systems are the exception: commands of systems that ran before them are applied first.
+pub struct Schedule<Storage: DynDispath> {
+    systems: Vec<System<Storage>>,
+    /// Runs systems in the order resolved from their constraints.
+    resolved: Option<ParallelExecutor>,
+}
+
+impl<Storage: DynDispath> Default for Schedule<Storage> {
+    fn default() -> Self {
+        Self {
+            systems: Vec::new(),
+            resolved: None,
+        }
+    }
+}
+
+impl<Storage: DynDispath> Schedule<Storage> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_system(&mut self, system: System<Storage>) {
+        self.systems.push(system);
+        self.resolved = None;
+    }
+
+    pub fn with_system(mut self, system: System<Storage>) -> Self {
+        self.add_system(system);
+        self
+    }
+
+    /// Validates constraints and returns names of systems in the order they will run.
+    pub fn order(&mut self) -> Result<Vec<&'static str>, ScheduleError> {
+        self.resolve()?;
+        let resolved = self.resolved.as_ref().expect("just resolved");
+        Ok(resolved
+            .order()
+            .iter()
+            .map(|&index| self.systems[index].name())
+            .collect())
+    }
+
+    /// Runs systems one by one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if constraints can't be satisfied, see [`Schedule::order`],
+    /// or if parameters of some system can't be acquired.
+    pub fn run(&mut self, world: &mut World<Storage>) {
+        if let Err(errors) = self.try_run(world) {
+            let (system, err) = &errors[0];
+            panic!("system {system} failed: {err}");
+        }
+    }
+
+    /// Runs systems one by one. Systems whose parameters can't be acquired are skipped,
+    /// and reported along with their names once every other system has run.
+    ///
+    /// # Panics
+    ///
+    /// Panics if constraints can't be satisfied, see [`Schedule::order`].
+    pub fn try_run(
+        &mut self,
+        world: &mut World<Storage>,
+    ) -> Result<(), Vec<(&'static str, EcsError)>> {
+        self.resolve_or_panic();
+        let executor = self.resolved.as_ref().expect("just resolved");
+        let errors = executor.run_serial(&mut self.systems, world);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Runs systems on a thread pool, running systems that don't conflict with each other concurrently.
+    ///
+    /// # Panics
+    ///
+    /// Panics if constraints can't be satisfied, see [`Schedule::order`],
+    /// or if parameters of some system can't be acquired.
+    pub fn run_parallel(&mut self, world: &mut World<Storage>)
+    where
+        Storage: Send + Sync,
+        Storage::AnyComponent: Send,
+        Storage::AnyResource: Send,
+    {
+        if let Err(errors) = self.try_run_parallel(world) {
+            let (system, err) = &errors[0];
+            panic!("system {system} failed: {err}");
+        }
+    }
+
+    /// Same as [`Schedule::run_parallel`], but systems whose parameters can't be acquired are skipped,
+    /// and reported along with their names once every other system has run, in the order systems would run.
+    ///
+    /// # Panics
+    ///
+    /// Panics if constraints can't be satisfied, see [`Schedule::order`].
+    pub fn try_run_parallel(
+        &mut self,
+        world: &mut World<Storage>,
+    ) -> Result<(), Vec<(&'static str, EcsError)>>
+    where
+        Storage: Send + Sync,
+        Storage::AnyComponent: Send,
+        Storage::AnyResource: Send,
+    {
+        self.resolve_or_panic();
+        let executor = self.resolved.as_ref().expect("just resolved");
+        let errors = executor.run(&mut self.systems, world);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    pub fn stage_count(&mut self) -> Result<usize, ScheduleError> {
+        Ok(self.resolve()?.stage_count())
+    }
+
+    fn resolve_or_panic(&mut self) {
+        if let Err(err) = self.resolve() {
+            panic!("Invalid schedule: {err}");
+        }
+    }
+
+    fn resolve(&mut self) -> Result<&ParallelExecutor, ScheduleError> {
+        if self.resolved.is_none() {
+            self.resolved = Some(self.resolve_uncached()?);
+        }
+        Ok(self.resolved.as_ref().expect("just resolved"))
+    }
+
+    fn resolve_uncached(&self) -> Result<ParallelExecutor, ScheduleError> {
+        let count = self.systems.len();
+        // Systems that have to run before this one.
+        let mut predecessors = vec![Vec::new(); count];
+        for (index, system) in self.systems.iter().enumerate() {
+            for &label in &system.before {
+                for other in self.labeled(system.name(), label)? {
+                    predecessors[other].push(index);
+                }
+            }
+            for &label in &system.after {
+                for other in self.labeled(system.name(), label)? {
+                    predecessors[index].push(other);
+                }
+            }
+        }
+
+        // Topological sort, picking systems that were added earlier first.
+        let mut remaining = predecessors.iter().map(Vec::len).collect::<Vec<_>>();
+        let mut ready = (0..count)
+            .filter(|&index| remaining[index] == 0)
+            .collect::<BTreeSet<_>>();
+        let mut order = Vec::with_capacity(count);
+        while let Some(index) = ready.pop_first() {
+            order.push(index);
+            for (other, preds) in predecessors.iter().enumerate() {
+                for _ in preds.iter().filter(|&&pred| pred == index) {
+                    remaining[other] -= 1;
+                    if remaining[other] == 0 {
+                        ready.insert(other);
+                    }
+                }
+            }
+        }
+        if order.len() < count {
+            return Err(ScheduleError::Cycle(
+                (0..count)
+                    .filter(|&index| remaining[index] > 0)
+                    .map(|index| self.systems[index].name())
+                    .collect(),
+            ));
+        }
+
+        Ok(ParallelExecutor::new(&self.systems, order, &predecessors))
+    }
+
+    fn labeled(
+        &self,
+        system: &'static str,
+        label: &'static str,
+    ) -> Result<Vec<usize>, ScheduleError> {
+        let found = (0..self.systems.len())
+            .filter(|&index| self.systems[index].has_label(label))
+            .collect::<Vec<_>>();
+        if found.is_empty() {
+            return Err(ScheduleError::UnknownLabel { system, label });
+        }
+        Ok(found)
+    }
+}
+
+/// Run condition that is met when resource `R` was changed during the previous cycle.
+pub fn resource_changed<R, Storage>(changes: ChangesG<Storage>) -> bool
+where
+    Storage: DynDispath,
+    R: LocalTypeIndex<OfResources<Storage>>,
+{
+    changes.resource_changed::<R>()
+}
@@ -0,0 +1,234 @@
+use crate::{
+    internal::DynDispath,
+    query_world::{QueryWorld, SystemLocals, WorldRef},
+    system_parameter::{commands::CommandList, ComponentRequests},
+    EcsError, World, WorldRun,
+};
+
+type SystemFn<Storage, Ret> =
+    dyn for<'w> FnMut(&'w QueryWorld<'w, Storage>) -> Result<Ret, EcsError> + Send;
+
+type ExclusiveFn<Storage, Ret> = dyn FnMut(&mut World<Storage>) -> Ret + Send;
+
+enum Runner<Storage: DynDispath, Ret> {
+    Params(Box<SystemFn<Storage, Ret>>),
+    Exclusive(Box<ExclusiveFn<Storage, Ret>>),
+}
+
+/// Creates a [`System`] from a function (or a `Copy` closure) that takes system parameters.
+#[macro_export]
+macro_rules! system {
+    ($f:expr) => {{
+        let f = $f;
+        // Safety: runner only runs the function requests were taken from.
+        unsafe {
+            $crate::System::new_unchecked(stringify!($f), &f, move |world| {
+                $crate::WorldRun::try_run(world, f)
+            })
+        }
+    }};
+}
+
+/// Creates an exclusive [`System`] from a function that takes `&mut World`, see [`System::exclusive`].
+#[macro_export]
+macro_rules! exclusive_system {
+    ($f:expr) => {
+        $crate::System::exclusive(stringify!($f), $f)
+    };
+}
+
+/// Type-erased system along with requests of its parameters.
+///
+/// Also holds constraints used by [`Schedule`](crate::Schedule).
+pub struct System<Storage: DynDispath, Ret = ()> {
+    name: &'static str,
+    requests: Vec<ComponentRequests>,
+    run: Runner<Storage, Ret>,
+    labels: Vec<&'static str>,
+    pub(crate) before: Vec<&'static str>,
+    pub(crate) after: Vec<&'static str>,
+    conditions: Vec<System<Storage, bool>>,
+    locals: SystemLocals,
+}
+
+impl<Storage: DynDispath, Ret> System<Storage, Ret> {
+    /// Use `system!` macro instead.
+    ///
+    /// # Safety
+    ///
+    /// `run` should only access what parameters of `f` request, which is best achieved by running `f` and nothing else.
+    #[doc(hidden)]
+    pub unsafe fn new_unchecked<'p, F, P>(
+        name: &'static str,
+        _f: &F,
+        run: impl for<'w> FnMut(&'w QueryWorld<'w, Storage>) -> Result<Ret, EcsError> + Send + 'static,
+    ) -> Self
+    where
+        Storage: 'p,
+        QueryWorld<'p, Storage>: WorldRun<'p, F, Ret, P>,
+    {
+        Self {
+            name,
+            requests: <QueryWorld<'p, Storage> as WorldRun<'p, F, Ret, P>>::requests(),
+            run: Runner::Params(Box::new(run)),
+            labels: Vec::new(),
+            before: Vec::new(),
+            after: Vec::new(),
+            conditions: Vec::new(),
+            locals: SystemLocals::default(),
+        }
+    }
+
+    /// System that takes the whole world, for things that can't be done through system parameters.
+    ///
+    /// It conflicts with every other system, and commands of systems that ran before it are applied first.
+    /// Its conditions are regular systems.
+    pub fn exclusive(
+        name: &'static str,
+        f: impl FnMut(&mut World<Storage>) -> Ret + Send + 'static,
+    ) -> Self {
+        Self {
+            name,
+            requests: Vec::new(),
+            run: Runner::Exclusive(Box::new(f)),
+            labels: Vec::new(),
+            before: Vec::new(),
+            after: Vec::new(),
+            conditions: Vec::new(),
+            locals: SystemLocals::default(),
+        }
+    }
+
+    /// Adds a label, which can be referred to by ordering constraints of other systems.
+    ///
+    /// System's name works as a label too.
+    pub fn label(mut self, label: &'static str) -> Self {
+        self.labels.push(label);
+        self
+    }
+
+    /// Run before systems with this label.
+    pub fn before(mut self, label: &'static str) -> Self {
+        self.before.push(label);
+        self
+    }
+
+    /// Run after systems with this label.
+    pub fn after(mut self, label: &'static str) -> Self {
+        self.after.push(label);
+        self
+    }
+
+    /// Only run if `condition` returns true. All conditions have to be met.
+    pub fn run_if(mut self, condition: System<Storage, bool>) -> Self {
+        self.conditions.push(condition);
+        self
+    }
+
+    pub(crate) fn has_label(&self, label: &str) -> bool {
+        self.name == label || self.labels.contains(&label)
+    }
+
+    fn all_requests(&self) -> impl Iterator<Item = &ComponentRequests> {
+        self.requests
+            .iter()
+            .chain(self.conditions.iter().flat_map(|cond| cond.requests.iter()))
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn is_exclusive(&self) -> bool {
+        matches!(self.run, Runner::Exclusive(_))
+    }
+
+    /// Returns true if this system (or one of its conditions) can spawn entities with commands.
+    pub(crate) fn reserves_entities(&self) -> bool {
+        self.all_requests().any(|req| req.reserves_entities)
+    }
+
+    /// Returns true if those systems (along with their conditions) can't run at the same time.
+    pub fn conflicts_with<Ret2>(&self, other: &System<Storage, Ret2>) -> bool {
+        self.is_exclusive()
+            || other.is_exclusive()
+            || self
+                .all_requests()
+                .any(|req| other.all_requests().any(|other| !req.safe_with(other)))
+    }
+
+    /// Runs this system, unless some of its conditions aren't met.
+    ///
+    /// # Panics
+    ///
+    /// Panics if parameters of this system or its conditions can't be acquired, see [`System::try_run`].
+    pub fn run(&mut self, query_world: &QueryWorld<Storage>) -> Option<Ret> {
+        self.try_run(query_world)
+            .unwrap_or_else(|err| panic!("system {} failed: {err}", self.name))
+    }
+
+    /// Same as [`System::run`], but returns an error if parameters can't be acquired.
+    ///
+    /// Exclusive systems can't run on a QueryWorld, see [`System::try_run_exclusive`].
+    pub fn try_run(&mut self, query_world: &QueryWorld<Storage>) -> Result<Option<Ret>, EcsError> {
+        let Runner::Params(run) = &mut self.run else {
+            return Err(EcsError::ExclusiveSystem { system: self.name });
+        };
+        if !conditions_met(&mut self.conditions, query_world)? {
+            return Ok(None);
+        }
+        query_world
+            .with_locals(&mut self.locals, || run(query_world))
+            .map(Some)
+    }
+
+    /// Runs this system with exclusive access to the world, unless some of its conditions aren't met.
+    ///
+    /// Works for any system, commands of a regular one are applied before returning.
+    pub fn try_run_exclusive(
+        &mut self,
+        world: &mut World<Storage>,
+    ) -> Result<Option<Ret>, EcsError> {
+        let Runner::Exclusive(run) = &mut self.run else {
+            return self.try_run(&world.query_world());
+        };
+        if !conditions_met(&mut self.conditions, &world.query_world())? {
+            return Ok(None);
+        }
+        Ok(Some(run(world)))
+    }
+
+    /// Runs this system in a scoped QueryWorld, returning its result along with submitted commands,
+    /// and the number of candidate IDs it used up.
+    ///
+    /// Entities it spawns get every `lanes`-th candidate ID, starting from the `first` one.
+    ///
+    /// # Safety
+    ///
+    /// Nothing running at the same time should conflict with this system.
+    pub(crate) unsafe fn run_scoped(
+        &mut self,
+        world: &World<Storage>,
+        first: usize,
+        lanes: usize,
+    ) -> (Result<Option<Ret>, EcsError>, CommandList<Storage>, usize) {
+        let query_world = QueryWorld::new(WorldRef::Scoped(world));
+        query_world.set_reservation_lane(first, lanes);
+        let ret = self.try_run(&query_world);
+        let reserved = query_world.reserved_candidates();
+        (ret, query_world.take_commands(), reserved)
+    }
+}
+
+/// Runs conditions, returning true if all of them are met.
+fn conditions_met<Storage: DynDispath>(
+    conditions: &mut [System<Storage, bool>],
+    query_world: &QueryWorld<Storage>,
+) -> Result<bool, EcsError> {
+    for condition in conditions {
+        if condition.try_run(query_world)? != Some(true) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
@@ -3,10 +3,14 @@ use crate::DynDispath;
 use smallvec::SmallVec;
 
 pub(crate) mod changes;
+pub(crate) mod chunks;
 pub(crate) mod commands;
+pub(crate) mod events;
+pub(crate) mod index;
+pub(crate) mod local;
 pub(crate) mod query;
 
-use crate::{query_world::QueryWorld, ArchetypeInfo, TypeIndex};
+use crate::{query_world::QueryWorld, ArchetypeInfo, EcsError, TypeIndex};
 
 /// # Safety
 ///
@@ -19,9 +23,20 @@ pub unsafe trait SystemParameter<'a, Storage: DynDispath> {
     ///
     /// Assumes that requests do not "collide" with each other.
     unsafe fn from_world(world: &'a QueryWorld<'a, Storage>) -> Self;
+    /// Like `from_world`, but returns an error instead of panicking, e.g. if a required resource is missing.
+    ///
+    /// # Safety
+    ///
+    /// See `from_world`.
+    unsafe fn try_from_world(world: &'a QueryWorld<'a, Storage>) -> Result<Self, EcsError>
+    where
+        Self: Sized,
+    {
+        Ok(unsafe { Self::from_world(world) })
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub(crate) struct Request {
     type_index: TypeIndex,
     exclusive: bool,
@@ -36,12 +51,18 @@ impl Request {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
 pub struct ComponentRequests {
     pub(crate) requests: SmallVec<[Request; 8]>,
     pub(crate) filter_require: SmallVec<[TypeIndex; 8]>,
     pub(crate) filter_exclude: SmallVec<[TypeIndex; 8]>,
     pub(crate) resource_requests: Vec<Request>,
+    /// Requested components that are kept in sparse sets, see `request_sparse`.
+    pub(crate) sparse: SmallVec<[TypeIndex; 4]>,
+    /// Groups of alternatives, at least one alternative of each group should be satisfied.
+    pub(crate) filter_any: Vec<Vec<ComponentRequests>>,
+    /// Whether entity IDs are reserved through this parameter, see `CommandsG::spawn`.
+    pub(crate) reserves_entities: bool,
 }
 
 impl ComponentRequests {
@@ -61,6 +82,27 @@ impl ComponentRequests {
             Err(ind) => self.requests.insert(ind, new_request),
         }
     }
+    /// Request to use this sparse component, either exclusively or shared.
+    ///
+    /// All entities with a sparse component share its storage, so such requests can't be excused
+    /// by being disjoint, see `safe_with`.
+    pub fn request_sparse(&mut self, type_index: TypeIndex, exclusive: bool) {
+        self.request(type_index, exclusive);
+        if let Err(ind) = self.sparse.binary_search(&type_index) {
+            self.sparse.insert(ind, type_index);
+        }
+    }
+    /// Request shared access to this component, unless it's already requested.
+    ///
+    /// Used by filters that only need to read something from the component.
+    pub fn request_shared(&mut self, type_index: TypeIndex) {
+        if let Err(ind) = self
+            .requests
+            .binary_search_by_key(&type_index, |req| req.type_index)
+        {
+            self.requests.insert(ind, Request::new(type_index, false));
+        }
+    }
     /// Require this component to be present.
     pub fn require(&mut self, type_index: TypeIndex) {
         match self.filter_require.binary_search(&type_index) {
@@ -80,6 +122,31 @@ impl ComponentRequests {
         }
     }
 
+    /// Require at least one of the alternatives to be satisfied.
+    ///
+    /// Accesses requested by alternatives are requested here as well, while their filters only apply to themselves.
+    /// Alternatives are not taken into account by `disjoint_with`, so it stays conservative.
+    pub fn any_of(&mut self, alternatives: Vec<ComponentRequests>) {
+        for alternative in &alternatives {
+            for req in &alternative.requests {
+                if req.exclusive {
+                    self.request(req.type_index, true);
+                } else {
+                    self.request_shared(req.type_index);
+                }
+            }
+            for &type_index in &alternative.sparse {
+                if let Err(ind) = self.sparse.binary_search(&type_index) {
+                    self.sparse.insert(ind, type_index);
+                }
+            }
+            for req in &alternative.resource_requests {
+                self.request_resource(req.type_index, req.exclusive);
+            }
+        }
+        self.filter_any.push(alternatives);
+    }
+
     pub fn request_resource(&mut self, type_index: TypeIndex, exclusive: bool) {
         let new_request = Request::new(type_index, exclusive);
         match self
@@ -164,14 +231,36 @@ impl ComponentRequests {
         false
     }
 
+    /// Returns true if both requests need access to a sparse component and at least one of requests needs exclusive access.
+    fn sparse_conflicts_with(&self, other: &Self) -> bool {
+        self.requests.iter().any(|e| {
+            let Ok(ind) = other
+                .requests
+                .binary_search_by_key(&e.type_index, |req| req.type_index)
+            else {
+                return false;
+            };
+            (e.exclusive || other.requests[ind].exclusive)
+                && (self.sparse.contains(&e.type_index) || other.sparse.contains(&e.type_index))
+        })
+    }
+
     /// Returns true if both requests can be satisfied at the same time.
+    ///
+    /// Requests that never access the same archetype are safe, unless they conflict over a sparse component.
     pub fn safe_with(&self, other: &Self) -> bool {
-        (!self.conflicts_with(other) || self.disjoint_with(other))
+        (!self.conflicts_with(other)
+            || (self.disjoint_with(other) && !self.sparse_conflicts_with(other)))
             && !self.resource_conflicts_with(other)
     }
 
     fn satisfied_by(&self, by: &ArchetypeInfo) -> bool {
-        self.require_satisfied(by) && self.exclude_satisfied(by)
+        self.require_satisfied(by)
+            && self.exclude_satisfied(by)
+            && self
+                .filter_any
+                .iter()
+                .all(|alternatives| alternatives.iter().any(|alt| alt.satisfied_by(by)))
     }
 
     fn require_satisfied(&self, by: &ArchetypeInfo) -> bool {
@@ -212,6 +301,48 @@ impl ComponentRequests {
         true
     }
 
+    /// Lists accessed types by their names, like `[&mut Position, &Velocity, Without<Frozen>]`.
+    pub(crate) fn describe<Storage: DynDispath>(&self) -> String {
+        fn name(names: &[&str], type_index: TypeIndex) -> String {
+            names
+                .get(type_index as usize)
+                .map_or_else(|| format!("#{type_index}"), |name| name.to_string())
+        }
+        fn access(req: &Request, names: &[&str]) -> String {
+            let reference = if req.exclusive { "&mut " } else { "&" };
+            format!("{reference}{}", name(names, req.type_index))
+        }
+
+        let components = Storage::COMPONENT_NAMES;
+        let requested = |type_index: &TypeIndex| {
+            self.requests
+                .iter()
+                .any(|req| req.type_index == *type_index)
+        };
+        let parts = self
+            .requests
+            .iter()
+            .map(|req| access(req, components))
+            .chain(
+                self.filter_require
+                    .iter()
+                    .filter(|type_index| !requested(type_index))
+                    .map(|&type_index| format!("With<{}>", name(components, type_index))),
+            )
+            .chain(
+                self.filter_exclude
+                    .iter()
+                    .map(|&type_index| format!("Without<{}>", name(components, type_index))),
+            )
+            .chain(
+                self.resource_requests
+                    .iter()
+                    .map(|req| access(req, Storage::RESOURCE_NAMES)),
+            )
+            .collect::<Vec<_>>();
+        format!("[{}]", parts.join(", "))
+    }
+
     pub(crate) fn any_exclusive(&self) -> bool {
         self.requests.iter().any(|x| x.exclusive)
             || self.resource_requests.iter().any(|x| x.exclusive)
@@ -281,6 +412,50 @@ mod tests {
         assert!(!req3.conflicts_with(&req4));
     }
 
+    #[test]
+    fn req_optional() {
+        let mut req1 = ComponentRequests::default();
+        let mut req2 = ComponentRequests::default();
+        let mut req3 = ComponentRequests::default();
+
+        req1.request(0, true);
+        req1.require(0);
+
+        // Optional access, doesn't require component to be present.
+        req2.request(0, false);
+        req2.request(1, false);
+        req2.require(1);
+
+        req3.request(0, false);
+        req3.exclude(0);
+
+        assert!(!req1.safe_with(&req2));
+        // Never accesses anything, as archetypes it matches don't have this component.
+        assert!(req1.safe_with(&req3));
+    }
+
+    #[test]
+    fn req_any_of() {
+        let mut req1 = ComponentRequests::default();
+        let mut req2 = ComponentRequests::default();
+        let mut alt1 = ComponentRequests::default();
+        let mut alt2 = ComponentRequests::default();
+
+        req1.request(0, true);
+        req1.require(0);
+
+        alt1.request(0, false);
+        alt1.require(0);
+        alt2.require(1);
+        req2.any_of(vec![alt1, alt2]);
+
+        // Access from alternatives is visible.
+        assert!(req1.conflicts_with(&req2));
+        // Entity might have both components, so can't be disjoint.
+        assert!(!req1.disjoint_with(&req2));
+        assert!(!req1.safe_with(&req2));
+    }
+
     #[test]
     fn req_resource() {
         let mut req1 = ComponentRequests::default();
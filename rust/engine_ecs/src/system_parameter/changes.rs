@@ -5,7 +5,7 @@ use serde::{Deserialize, Serialize};
 use crate::{
     ecs_cell::EcsCell,
     internal::{DynDispath, OfResources},
-    LocalTypeIndex, TypeIndex,
+    Component, EntityID, LocalTypeIndex, TypeIndex,
 };
 
 use super::SystemParameter;
@@ -16,8 +16,15 @@ pub(crate) struct ReadOnly;
 pub(crate) struct WriteOnly;
 
 #[derive(Clone, Serialize, Deserialize)]
+#[serde(bound = "")]
 pub(crate) struct ChangeManager<Storage, Rw> {
     changed_resources: Box<[EcsCell<bool>]>,
+    /// Entities that got a component, per component type.
+    #[serde(default)]
+    spawned: Vec<Vec<EntityID>>,
+    /// Entities that lost a component, per component type.
+    #[serde(default)]
+    despawned: Vec<Vec<EntityID>>,
     _phantom: PhantomData<fn() -> (Storage, Rw)>,
 }
 
@@ -26,11 +33,52 @@ impl<Storage: DynDispath, Rw> Default for ChangeManager<Storage, Rw> {
         Self {
             changed_resources: vec![EcsCell::new(false); Storage::RESOURCE_TYPES as usize]
                 .into_boxed_slice(),
+            spawned: vec![Vec::new(); Storage::COMPONENT_TYPES as usize],
+            despawned: vec![Vec::new(); Storage::COMPONENT_TYPES as usize],
             _phantom: PhantomData,
         }
     }
 }
 
+/// Lists might be missing when loaded from an older save, so they are created on demand.
+fn entity_list_mut(lists: &mut Vec<Vec<EntityID>>, index: TypeIndex) -> &mut Vec<EntityID> {
+    let index = index as usize;
+    if lists.len() <= index {
+        lists.resize_with(index + 1, Vec::new);
+    }
+    &mut lists[index]
+}
+
+impl<Storage: DynDispath, Rw> ChangeManager<Storage, Rw> {
+    /// Moves lists loaded from a save to type indices of the current schema.
+    ///
+    /// Item `i` of `components` is the current index of a component that was saved with index `i`,
+    /// or `None` if it was removed. Same goes for `resources`.
+    pub(crate) fn remap(
+        self,
+        components: &[Option<TypeIndex>],
+        resources: &[Option<TypeIndex>],
+    ) -> Self {
+        let mut remapped = Self::default();
+        for (saved, changed) in self.changed_resources.into_vec().into_iter().enumerate() {
+            if let Some(&Some(index)) = resources.get(saved) {
+                remapped.changed_resources[index as usize] = changed;
+            }
+        }
+        for (lists, remapped_lists) in [
+            (self.spawned, &mut remapped.spawned),
+            (self.despawned, &mut remapped.despawned),
+        ] {
+            for (saved, entities) in lists.into_iter().enumerate() {
+                if let Some(&Some(index)) = components.get(saved) {
+                    *entity_list_mut(remapped_lists, index) = entities;
+                }
+            }
+        }
+        remapped
+    }
+}
+
 impl<Storage: DynDispath> ChangeManager<Storage, WriteOnly> {
     pub(crate) fn mark_resource_as_changed(&mut self, index: TypeIndex) {
         *self.changed_resources[index as usize].get_mut() = true;
@@ -43,9 +91,23 @@ impl<Storage: DynDispath> ChangeManager<Storage, WriteOnly> {
         unsafe { *self.changed_resources[usize::try_from(index).unwrap()].get_mut_unsafe() = true };
     }
 
+    pub(crate) fn mark_spawned(&mut self, index: TypeIndex, entity: EntityID) {
+        entity_list_mut(&mut self.spawned, index).push(entity);
+    }
+
+    pub(crate) fn mark_spawned_many(&mut self, index: TypeIndex, entities: &[EntityID]) {
+        entity_list_mut(&mut self.spawned, index).extend_from_slice(entities);
+    }
+
+    pub(crate) fn mark_despawned(&mut self, index: TypeIndex, entity: EntityID) {
+        entity_list_mut(&mut self.despawned, index).push(entity);
+    }
+
     pub(crate) fn to_read_only(self) -> ChangeManager<Storage, ReadOnly> {
         ChangeManager {
             changed_resources: self.changed_resources,
+            spawned: self.spawned,
+            despawned: self.despawned,
             _phantom: PhantomData,
         }
     }
@@ -55,6 +117,16 @@ impl<Storage: DynDispath> ChangeManager<Storage, ReadOnly> {
     pub(crate) fn resource_changed(&self, index: TypeIndex) -> bool {
         *self.changed_resources[index as usize].get()
     }
+
+    pub(crate) fn spawned(&self, index: TypeIndex) -> &[EntityID] {
+        self.spawned.get(index as usize).map_or(&[], Vec::as_slice)
+    }
+
+    pub(crate) fn despawned(&self, index: TypeIndex) -> &[EntityID] {
+        self.despawned
+            .get(index as usize)
+            .map_or(&[], Vec::as_slice)
+    }
 }
 
 pub struct ChangesG<'a, Storage> {
@@ -79,3 +151,63 @@ unsafe impl<'wrld, Storage: DynDispath> SystemParameter<'wrld, Storage>
         }
     }
 }
+
+/// Entities that got component `T` during the previous cycle, either by being spawned with it or by having it inserted.
+///
+/// Entity might not exist anymore, if it was despawned later during the same cycle.
+pub struct SpawnedG<'a, Storage, T> {
+    entities: &'a [EntityID],
+    _phantom: PhantomData<fn() -> (Storage, T)>,
+}
+
+/// Entities that lost component `T` during the previous cycle, either by being despawned or by having it removed.
+pub struct DespawnedG<'a, Storage, T> {
+    entities: &'a [EntityID],
+    _phantom: PhantomData<fn() -> (Storage, T)>,
+}
+
+impl<'a, Storage, T> SpawnedG<'a, Storage, T> {
+    pub fn iter(&self) -> impl Iterator<Item = EntityID> + 'a {
+        self.entities.iter().copied()
+    }
+    pub fn contains(&self, entity: EntityID) -> bool {
+        self.entities.contains(&entity)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+}
+
+impl<'a, Storage, T> DespawnedG<'a, Storage, T> {
+    pub fn iter(&self) -> impl Iterator<Item = EntityID> + 'a {
+        self.entities.iter().copied()
+    }
+    pub fn contains(&self, entity: EntityID) -> bool {
+        self.entities.contains(&entity)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+}
+
+unsafe impl<'wrld, Storage: DynDispath, T: Component<Storage>> SystemParameter<'wrld, Storage>
+    for SpawnedG<'wrld, Storage, T>
+{
+    unsafe fn from_world(world: &'wrld crate::QueryWorld<'wrld, Storage>) -> Self {
+        SpawnedG {
+            entities: world.inner.changes_prev.spawned(T::TYPE_INDEX),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+unsafe impl<'wrld, Storage: DynDispath, T: Component<Storage>> SystemParameter<'wrld, Storage>
+    for DespawnedG<'wrld, Storage, T>
+{
+    unsafe fn from_world(world: &'wrld crate::QueryWorld<'wrld, Storage>) -> Self {
+        DespawnedG {
+            entities: world.inner.changes_prev.despawned(T::TYPE_INDEX),
+            _phantom: PhantomData,
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use std::mem;
+
+use crate::{
+    component_traits::TableComponent,
+    internal::{ComponentStorageProvider, DynDispath},
+    query_world::QueryWorld,
+    ArchetypeID, EntityID, Tick,
+};
+
+use super::query::QueryParameter;
+
+/// Mutable components of consecutive entities, along with ticks of their last mutable access.
+pub struct ChunkMut<'a, T> {
+    values: &'a mut [T],
+    ticks: &'a mut [Tick],
+    tick: Tick,
+}
+
+impl<'a, T> Default for ChunkMut<'a, T> {
+    fn default() -> Self {
+        Self {
+            values: &mut [],
+            ticks: &mut [],
+            tick: 0,
+        }
+    }
+}
+
+impl<'a, T> ChunkMut<'a, T> {
+    pub(crate) fn new(values: &'a mut [T], ticks: &'a mut [Tick], tick: Tick) -> Self {
+        Self {
+            values,
+            ticks,
+            tick,
+        }
+    }
+
+    fn split_front(&mut self, mid: usize) -> Self {
+        let (values, rest_values) = mem::take(&mut self.values).split_at_mut(mid);
+        let (ticks, rest_ticks) = mem::take(&mut self.ticks).split_at_mut(mid);
+        self.values = rest_values;
+        self.ticks = rest_ticks;
+        Self::new(values, ticks, self.tick)
+    }
+
+    fn take_first(&mut self) -> &'a mut T {
+        let (value, rest_values) = mem::take(&mut self.values)
+            .split_first_mut()
+            .expect("chunk should not be empty");
+        let (tick, rest_ticks) = mem::take(&mut self.ticks)
+            .split_first_mut()
+            .expect("ticks match values");
+        *tick = self.tick;
+        self.values = rest_values;
+        self.ticks = rest_ticks;
+        value
+    }
+
+    /// Marks every component of the chunk as changed.
+    fn into_slice(self) -> &'a mut [T] {
+        self.ticks.fill(self.tick);
+        self.values
+    }
+}
+
+/// Query parameter, data of which can be taken for a whole archetype at once.
+///
+/// Only implemented for table components, as sparse ones aren't stored in archetypes.
+///
+/// # Safety
+///
+/// Chunks should only give access to components that are requested by `add_requests`.
+pub unsafe trait ChunkParameter<'wrld, Storage: DynDispath>:
+    QueryParameter<'wrld, Storage>
+{
+    /// Data of consecutive entities of an archetype, that can be split between threads.
+    type Chunk;
+    /// What `QueryG::iter_chunks` yields, slices of components.
+    type Slices;
+
+    /// # Safety
+    ///
+    /// Same as `QueryParameter::get_from_world`, for every entity of the archetype.
+    unsafe fn get_chunk(
+        world: &'wrld QueryWorld<'wrld, Storage>,
+        archetype: ArchetypeID,
+    ) -> Self::Chunk;
+    /// Splits off first `mid` entities of the chunk.
+    fn split_front(chunk: &mut Self::Chunk, mid: usize) -> Self::Chunk;
+    /// Takes item of the first entity of the chunk.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the chunk is empty.
+    fn take_first(chunk: &mut Self::Chunk) -> Self;
+    fn into_slices(chunk: Self::Chunk) -> Self::Slices;
+}
+
+unsafe impl<'wrld, Storage: DynDispath> ChunkParameter<'wrld, Storage> for EntityID {
+    type Chunk = &'wrld [EntityID];
+    type Slices = &'wrld [EntityID];
+
+    unsafe fn get_chunk(
+        world: &'wrld QueryWorld<'wrld, Storage>,
+        archetype: ArchetypeID,
+    ) -> Self::Chunk {
+        &world.inner.archeman.archetypes[archetype.0 as usize].entities
+    }
+    fn split_front(chunk: &mut Self::Chunk, mid: usize) -> Self::Chunk {
+        split_front(chunk, mid)
+    }
+    fn take_first(chunk: &mut Self::Chunk) -> Self {
+        *take_first(chunk)
+    }
+    fn into_slices(chunk: Self::Chunk) -> Self::Slices {
+        chunk
+    }
+}
+
+unsafe impl<'wrld, Storage, T> ChunkParameter<'wrld, Storage> for &'wrld T
+where
+    Storage: DynDispath + ComponentStorageProvider<T>,
+    T: TableComponent<Storage>,
+    &'wrld T: QueryParameter<'wrld, Storage>,
+{
+    type Chunk = &'wrld [T];
+    type Slices = &'wrld [T];
+
+    unsafe fn get_chunk(
+        world: &'wrld QueryWorld<'wrld, Storage>,
+        archetype: ArchetypeID,
+    ) -> Self::Chunk {
+        let storage = world
+            .storage_for_archetype::<T>(archetype)
+            .expect("component assumed to exist, as we've asked for it");
+        unsafe { world.column(storage) }
+    }
+    fn split_front(chunk: &mut Self::Chunk, mid: usize) -> Self::Chunk {
+        split_front(chunk, mid)
+    }
+    fn take_first(chunk: &mut Self::Chunk) -> Self {
+        take_first(chunk)
+    }
+    fn into_slices(chunk: Self::Chunk) -> Self::Slices {
+        chunk
+    }
+}
+
+unsafe impl<'wrld, Storage, T> ChunkParameter<'wrld, Storage> for &'wrld mut T
+where
+    Storage: DynDispath + ComponentStorageProvider<T>,
+    T: TableComponent<Storage>,
+    &'wrld mut T: QueryParameter<'wrld, Storage>,
+{
+    type Chunk = ChunkMut<'wrld, T>;
+    type Slices = &'wrld mut [T];
+
+    unsafe fn get_chunk(
+        world: &'wrld QueryWorld<'wrld, Storage>,
+        archetype: ArchetypeID,
+    ) -> Self::Chunk {
+        let storage = world
+            .storage_for_archetype::<T>(archetype)
+            .expect("component assumed to exist, as we've asked for it");
+        unsafe { world.column_mut(storage) }
+    }
+    fn split_front(chunk: &mut Self::Chunk, mid: usize) -> Self::Chunk {
+        chunk.split_front(mid)
+    }
+    fn take_first(chunk: &mut Self::Chunk) -> Self {
+        chunk.take_first()
+    }
+    fn into_slices(chunk: Self::Chunk) -> Self::Slices {
+        chunk.into_slice()
+    }
+}
+
+unsafe impl<'wrld, Storage, T> ChunkParameter<'wrld, Storage> for Option<&'wrld T>
+where
+    Storage: DynDispath + ComponentStorageProvider<T>,
+    T: TableComponent<Storage>,
+    Option<&'wrld T>: QueryParameter<'wrld, Storage>,
+{
+    type Chunk = Option<&'wrld [T]>;
+    type Slices = Option<&'wrld [T]>;
+
+    unsafe fn get_chunk(
+        world: &'wrld QueryWorld<'wrld, Storage>,
+        archetype: ArchetypeID,
+    ) -> Self::Chunk {
+        let storage = world.storage_for_archetype::<T>(archetype)?;
+        Some(unsafe { world.column(storage) })
+    }
+    fn split_front(chunk: &mut Self::Chunk, mid: usize) -> Self::Chunk {
+        chunk.as_mut().map(|chunk| split_front(chunk, mid))
+    }
+    fn take_first(chunk: &mut Self::Chunk) -> Self {
+        chunk.as_mut().map(take_first)
+    }
+    fn into_slices(chunk: Self::Chunk) -> Self::Slices {
+        chunk
+    }
+}
+
+unsafe impl<'wrld, Storage, T> ChunkParameter<'wrld, Storage> for Option<&'wrld mut T>
+where
+    Storage: DynDispath + ComponentStorageProvider<T>,
+    T: TableComponent<Storage>,
+    Option<&'wrld mut T>: QueryParameter<'wrld, Storage>,
+{
+    type Chunk = Option<ChunkMut<'wrld, T>>;
+    type Slices = Option<&'wrld mut [T]>;
+
+    unsafe fn get_chunk(
+        world: &'wrld QueryWorld<'wrld, Storage>,
+        archetype: ArchetypeID,
+    ) -> Self::Chunk {
+        let storage = world.storage_for_archetype::<T>(archetype)?;
+        Some(unsafe { world.column_mut(storage) })
+    }
+    fn split_front(chunk: &mut Self::Chunk, mid: usize) -> Self::Chunk {
+        chunk.as_mut().map(|chunk| chunk.split_front(mid))
+    }
+    fn take_first(chunk: &mut Self::Chunk) -> Self {
+        chunk.as_mut().map(ChunkMut::take_first)
+    }
+    fn into_slices(chunk: Self::Chunk) -> Self::Slices {
+        chunk.map(ChunkMut::into_slice)
+    }
+}
+
+fn split_front<'a, T>(chunk: &mut &'a [T], mid: usize) -> &'a [T] {
+    let (front, rest) = chunk.split_at(mid);
+    *chunk = rest;
+    front
+}
+
+fn take_first<'a, T>(chunk: &mut &'a [T]) -> &'a T {
+    let (first, rest) = chunk.split_first().expect("chunk should not be empty");
+    *chunk = rest;
+    first
+}
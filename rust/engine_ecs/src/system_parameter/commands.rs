@@ -1,44 +1,251 @@
-use std::{marker::PhantomData, sync::Arc};
+use std::{any, fmt, sync::Arc};
 
 use crossbeam_queue::SegQueue;
-use smallvec::SmallVec;
+use serde::{Deserialize, Serialize};
+use smallvec::{smallvec, SmallVec};
+use tracing::warn;
 
-use crate::{internal::DynDispath, World};
+use crate::{
+    component_traits::{Bundle, TypeIndexStorage},
+    internal::{DynComponent, DynDispath, DynResource},
+    query_world::QueryWorld,
+    EcsError, EntityID, World,
+};
 
 use super::{ComponentRequests, SystemParameter};
 
-pub type CommandFn<Storage> = dyn FnOnce(&mut World<Storage>);
+pub type CommandFn<Storage> = dyn FnOnce(&mut World<Storage>) + Send;
 
-type ParamIndex = usize;
+pub(crate) type ParamIndex = usize;
 
-pub(crate) type CommandBuffer<Storage> = Arc<SegQueue<(ParamIndex, Box<CommandFn<Storage>>)>>;
+/// Commands taken from a buffer, sorted by parameter index.
+pub(crate) type CommandList<Storage> = Vec<(ParamIndex, CommandG<Storage>)>;
 
-pub struct CommandsG<Storage> {
-    pending: CommandBuffer<Storage>,
+pub(crate) type CommandBuffer<Storage> = Arc<SegQueue<(ParamIndex, CommandG<Storage>)>>;
+
+/// Change to the world, submitted by a system and applied after it's done.
+///
+/// Every command except `Submit` is plain data, so that commands can be logged, serialized and replayed
+/// with `World::apply_commands`.
+#[derive(Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "Storage::AnyComponent: Serialize, Storage::AnyResource: Serialize",
+    deserialize = "Storage::AnyComponent: Deserialize<'de>, Storage::AnyResource: Deserialize<'de>"
+))]
+pub enum CommandG<Storage: DynDispath> {
+    /// Spawns an entity with an ID reserved by `CommandsG::spawn`.
+    Spawn {
+        entity: EntityID,
+        components: Vec<Storage::AnyComponent>,
+    },
+    Despawn(EntityID),
+    DespawnRecursive(EntityID),
+    Insert {
+        entity: EntityID,
+        components: Vec<Storage::AnyComponent>,
+    },
+    Remove {
+        entity: EntityID,
+        components: TypeIndexStorage,
+    },
+    SetParent {
+        child: EntityID,
+        parent: EntityID,
+    },
+    RemoveParent(EntityID),
+    InsertResource(Storage::AnyResource),
+    /// Arbitrary closure, which can't be serialized.
+    #[serde(skip)]
+    Submit(Box<CommandFn<Storage>>),
+}
+
+impl<Storage: DynDispath> fmt::Debug for CommandG<Storage> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Spawn { entity, components } => f
+                .debug_struct("Spawn")
+                .field("entity", entity)
+                .field("components", components)
+                .finish(),
+            Self::Despawn(entity) => f.debug_tuple("Despawn").field(entity).finish(),
+            Self::DespawnRecursive(entity) => {
+                f.debug_tuple("DespawnRecursive").field(entity).finish()
+            }
+            Self::Insert { entity, components } => f
+                .debug_struct("Insert")
+                .field("entity", entity)
+                .field("components", components)
+                .finish(),
+            Self::Remove { entity, components } => f
+                .debug_struct("Remove")
+                .field("entity", entity)
+                .field("components", components)
+                .finish(),
+            Self::SetParent { child, parent } => f
+                .debug_struct("SetParent")
+                .field("child", child)
+                .field("parent", parent)
+                .finish(),
+            Self::RemoveParent(entity) => f.debug_tuple("RemoveParent").field(entity).finish(),
+            Self::InsertResource(resource) => {
+                f.debug_tuple("InsertResource").field(resource).finish()
+            }
+            Self::Submit(_) => f.write_str("Submit(..)"),
+        }
+    }
+}
+
+impl<Storage: DynDispath> World<Storage> {
+    /// Applies commands in order, as if they were submitted by a system.
+    ///
+    /// IDs of spawned entities are claimed up front, so that entities spawned by hooks along the way can't take them.
+    pub fn apply_commands(&mut self, commands: impl IntoIterator<Item = CommandG<Storage>>) {
+        let commands: Vec<_> = commands.into_iter().collect();
+        for command in &commands {
+            if let CommandG::Spawn { entity, .. } = command {
+                self.entities.claim(*entity);
+            }
+        }
+        for command in commands {
+            self.apply_command(command);
+        }
+    }
+
+    /// Applies a command, as if it was submitted by a system.
+    ///
+    /// If the ID of a `Spawn` command is already taken in this world, a warning is logged and nothing is spawned,
+    /// since later commands would refer to the entity by that ID. IDs are free when commands are replayed
+    /// on a copy of the world they were recorded in, see `apply_commands` for replaying commands that spawn entities from hooks.
+    pub fn apply_command(&mut self, command: CommandG<Storage>) {
+        match command {
+            CommandG::Spawn { entity, components } => {
+                if !self.entities.is_reserved(entity) && !self.entities.claim(entity) {
+                    warn!("Skipped spawning an entity with {entity:?}, which is already taken");
+                    return;
+                }
+                let type_ids = components.iter().map(DynComponent::type_index).collect();
+                self.spawn_reserved(entity, type_ids, |world, archetype| {
+                    for component in components {
+                        component.add_to_archetype_in_storage(world, archetype, entity);
+                    }
+                });
+            }
+            CommandG::Despawn(entity) => {
+                self.despawn(entity);
+            }
+            CommandG::DespawnRecursive(entity) => {
+                self.despawn_recursive(entity);
+            }
+            CommandG::Insert { entity, components } => {
+                let type_ids = components.iter().map(DynComponent::type_index).collect();
+                self.insert_components(entity, type_ids, |world, archetype| {
+                    for component in components {
+                        component.add_to_archetype_in_storage(world, archetype, entity);
+                    }
+                });
+            }
+            CommandG::Remove { entity, components } => {
+                self.remove_components(entity, &components);
+            }
+            CommandG::SetParent { child, parent } => {
+                self.set_parent(child, parent);
+            }
+            CommandG::RemoveParent(entity) => {
+                self.remove_parent(entity);
+            }
+            CommandG::InsertResource(resource) => resource.insert_into(self),
+            CommandG::Submit(f) => f(self),
+        }
+    }
+}
+
+pub struct CommandsG<'wrld, Storage: DynDispath> {
+    world: &'wrld QueryWorld<'wrld, Storage>,
     param_index: ParamIndex,
-    _phantom: PhantomData<fn() -> Storage>,
 }
 
-impl<Storage> CommandsG<Storage> {
-    pub fn submit(&self, f: impl FnOnce(&mut World<Storage>) + 'static) {
-        self.pending.push((self.param_index, Box::new(f)))
+impl<'wrld, Storage: DynDispath> CommandsG<'wrld, Storage> {
+    pub(crate) fn new(world: &'wrld QueryWorld<'wrld, Storage>, param_index: ParamIndex) -> Self {
+        Self { world, param_index }
+    }
+
+    fn push(&self, command: CommandG<Storage>) {
+        self.world.command_buffer.push((self.param_index, command))
+    }
+
+    /// Spawns an entity with this bundle once the system is done.
+    ///
+    /// Returned ID can be used right away, e.g. by other commands, but the entity does not exist until then.
+    /// It only depends on the world and on the position of the system in its schedule, not on other systems running at the same time.
+    pub fn spawn<B: Bundle<Storage>>(&self, bundle: B) -> EntityID {
+        let entity = self.world.reserve_entity();
+        let mut components = Vec::new();
+        bundle.into_components(&mut components);
+        self.push(CommandG::Spawn { entity, components });
+        entity
+    }
+
+    pub fn despawn(&self, entity: EntityID) {
+        self.push(CommandG::Despawn(entity))
+    }
+
+    pub fn despawn_recursive(&self, entity: EntityID) {
+        self.push(CommandG::DespawnRecursive(entity))
+    }
+
+    pub fn insert<B: Bundle<Storage>>(&self, entity: EntityID, bundle: B) {
+        let mut components = Vec::new();
+        bundle.into_components(&mut components);
+        self.push(CommandG::Insert { entity, components })
+    }
+
+    pub fn remove<B: Bundle<Storage>>(&self, entity: EntityID) {
+        self.push(CommandG::Remove {
+            entity,
+            components: B::type_ids(),
+        })
+    }
+
+    pub fn set_parent(&self, child: EntityID, parent: EntityID) {
+        self.push(CommandG::SetParent { child, parent })
+    }
+
+    pub fn remove_parent(&self, child: EntityID) {
+        self.push(CommandG::RemoveParent(child))
+    }
+
+    pub fn insert_resource<R>(&self, resource: R)
+    where
+        Storage::AnyResource: From<R>,
+    {
+        self.push(CommandG::InsertResource(resource.into()))
+    }
+
+    /// Runs a closure with exclusive access to the world. Such commands can't be serialized.
+    pub fn submit(&self, f: impl FnOnce(&mut World<Storage>) + Send + 'static) {
+        self.push(CommandG::Submit(Box::new(f)))
     }
 }
 
-unsafe impl<'a, Storage: DynDispath> SystemParameter<'a, Storage> for CommandsG<Storage> {
+unsafe impl<'a, Storage: DynDispath> SystemParameter<'a, Storage> for CommandsG<'a, Storage> {
     fn requests() -> SmallVec<[ComponentRequests; 8]> {
-        SmallVec::new()
-    }
-
-    unsafe fn from_world(world: &'a crate::query_world::QueryWorld<'a, Storage>) -> Self {
-        assert!(
-            world.exclusive(),
-            "Commands can only be used with exclusive QueryWorld"
-        );
-        Self {
-            pending: world.command_buffer.clone(),
-            param_index: world.current_parameter_index(),
-            _phantom: PhantomData,
+        // Doesn't access anything, only lets schedules know that this system reserves IDs.
+        smallvec![ComponentRequests {
+            reserves_entities: true,
+            ..Default::default()
+        }]
+    }
+
+    unsafe fn from_world(world: &'a QueryWorld<'a, Storage>) -> Self {
+        unsafe { Self::try_from_world(world) }.unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    unsafe fn try_from_world(world: &'a QueryWorld<'a, Storage>) -> Result<Self, EcsError> {
+        if !world.exclusive() {
+            return Err(EcsError::ExclusiveOnShared {
+                parameter: any::type_name::<Self>().to_string(),
+            });
         }
+        Ok(Self::new(world, world.current_parameter_index()))
     }
 }
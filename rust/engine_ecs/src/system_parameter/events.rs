@@ -0,0 +1,173 @@
+use std::{marker::PhantomData, mem};
+
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
+
+use crate::{
+    internal::{DynDispath, EventStorageProvider},
+    query_world::QueryWorld,
+};
+
+use super::{ComponentRequests, SystemParameter};
+
+/// Double-buffered queue of events of type `T`.
+///
+/// Events stay available for the cycle they were sent in and the next one, buffers are swapped by `World::next_cycle`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Events<T> {
+    /// Events sent during the previous cycle.
+    previous: Vec<T>,
+    /// Events sent during the current cycle.
+    current: Vec<T>,
+    /// Amount of events sent before the ones in `previous`.
+    previous_start: usize,
+}
+
+impl<T> Default for Events<T> {
+    fn default() -> Self {
+        Self {
+            previous: Vec::new(),
+            current: Vec::new(),
+            previous_start: 0,
+        }
+    }
+}
+
+impl<T> Events<T> {
+    pub fn send(&mut self, event: T) {
+        self.current.push(event);
+    }
+
+    /// Amount of events ever sent, which is also the cursor position right after the last event.
+    pub fn event_count(&self) -> usize {
+        self.current_start() + self.current.len()
+    }
+
+    /// Iterates over all events that are still available.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.previous.iter().chain(self.current.iter())
+    }
+
+    pub fn len(&self) -> usize {
+        self.previous.len() + self.current.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterates over available events, starting from the one `cursor` points to.
+    pub fn iter_from(&self, cursor: usize) -> impl Iterator<Item = &T> {
+        let previous_skip = cursor
+            .saturating_sub(self.previous_start)
+            .min(self.previous.len());
+        let current_skip = cursor
+            .saturating_sub(self.current_start())
+            .min(self.current.len());
+        self.previous[previous_skip..]
+            .iter()
+            .chain(self.current[current_skip..].iter())
+    }
+
+    fn current_start(&self) -> usize {
+        self.previous_start + self.previous.len()
+    }
+
+    /// Drops events from the previous cycle, and makes current ones previous.
+    pub(crate) fn update(&mut self) {
+        self.previous_start = self.current_start();
+        mem::swap(&mut self.previous, &mut self.current);
+        self.current.clear();
+    }
+}
+
+pub struct EventWriterG<'a, Storage, T> {
+    events: &'a mut Events<T>,
+    _phantom: PhantomData<fn() -> Storage>,
+}
+
+impl<'a, Storage, T> EventWriterG<'a, Storage, T> {
+    pub fn send(&mut self, event: T) {
+        self.events.send(event)
+    }
+    pub fn send_batch(&mut self, events: impl IntoIterator<Item = T>) {
+        self.events.current.extend(events)
+    }
+}
+
+/// Reads events of type `T`, each event once.
+///
+/// Position is remembered per system, so that every system sees every event.
+/// When used outside of a system, reads all events that are still available.
+pub struct EventReaderG<'a, Storage, T> {
+    events: &'a Events<T>,
+    cursor: usize,
+    saved_cursor: Option<&'a mut usize>,
+    _phantom: PhantomData<fn() -> Storage>,
+}
+
+impl<'a, Storage, T> EventReaderG<'a, Storage, T> {
+    /// Iterates over events that weren't read yet.
+    pub fn read(&mut self) -> impl Iterator<Item = &'a T> {
+        let cursor = mem::replace(&mut self.cursor, self.events.event_count());
+        if let Some(saved_cursor) = &mut self.saved_cursor {
+            **saved_cursor = self.cursor;
+        }
+        self.events.iter_from(cursor)
+    }
+    pub fn len(&self) -> usize {
+        self.events.iter_from(self.cursor).count()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+unsafe impl<'a, Storage, T> SystemParameter<'a, Storage> for EventWriterG<'a, Storage, T>
+where
+    Storage: DynDispath + EventStorageProvider<T>,
+{
+    fn requests() -> SmallVec<[ComponentRequests; 8]> {
+        let mut ret = SmallVec::new();
+        let mut req = ComponentRequests::default();
+        req.request_resource(Storage::EVENTS_INDEX, true);
+        ret.push(req);
+        ret
+    }
+
+    unsafe fn from_world(world: &'a QueryWorld<'a, Storage>) -> Self {
+        Self {
+            events: unsafe { world.inner.storage.events().get_mut_unsafe() }
+                .expect("event queues always exist"),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+unsafe impl<'a, Storage, T> SystemParameter<'a, Storage> for EventReaderG<'a, Storage, T>
+where
+    Storage: DynDispath + EventStorageProvider<T>,
+{
+    fn requests() -> SmallVec<[ComponentRequests; 8]> {
+        let mut ret = SmallVec::new();
+        let mut req = ComponentRequests::default();
+        req.request_resource(Storage::EVENTS_INDEX, false);
+        ret.push(req);
+        ret
+    }
+
+    unsafe fn from_world(world: &'a QueryWorld<'a, Storage>) -> Self {
+        let saved_cursor = unsafe { world.next_local::<usize>() };
+        Self {
+            events: world
+                .inner
+                .storage
+                .events()
+                .get()
+                .expect("event queues always exist"),
+            cursor: saved_cursor.as_deref().copied().unwrap_or(0),
+            saved_cursor,
+            _phantom: PhantomData,
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use std::{collections::HashMap, hash::Hash, marker::PhantomData, ops::Deref};
+
+use smallvec::SmallVec;
+
+use crate::{
+    component_traits::Component,
+    internal::{ComponentStorageProvider, DynDispath, IndexStorageProvider},
+    query_world::QueryWorld,
+    EntityID, Hooks, Tick, World,
+};
+
+use super::{ComponentRequests, SystemParameter};
+
+/// Key function of an index over components of this type, see `ComponentIndex`.
+///
+/// Keys should only depend on the component.
+pub trait IndexedBy<K> {
+    fn key(&self) -> K;
+}
+
+/// Entities with component `C`, looked up by key `K` computed from the component.
+///
+/// Declared in `: indexes` section of gen_storage_for_world! as pairs of component and key types,
+/// e.g. `Building (VesselID, TilePos)`. Indexes are not saved, and are built on first use.
+/// Added and removed components are applied to the index right away by hooks, while mutated ones are picked up
+/// when the index is accessed through `IndexG` or `World::index`, looking only at storages that were mutated.
+pub struct ComponentIndex<C, K> {
+    entities: HashMap<K, SmallVec<[EntityID; 1]>>,
+    keys: HashMap<EntityID, K>,
+    /// Change tick of the world when the index was last brought up to date, None if it was never built.
+    refreshed: Option<Tick>,
+    _phantom: PhantomData<fn() -> C>,
+}
+
+impl<C, K> Default for ComponentIndex<C, K> {
+    fn default() -> Self {
+        Self {
+            entities: HashMap::new(),
+            keys: HashMap::new(),
+            refreshed: None,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<C, K: Clone> Clone for ComponentIndex<C, K> {
+    fn clone(&self) -> Self {
+        Self {
+            entities: self.entities.clone(),
+            keys: self.keys.clone(),
+            refreshed: self.refreshed,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<C, K: Hash + Eq + Clone> ComponentIndex<C, K> {
+    /// Entities whose components have this key, in no particular order.
+    pub fn get(&self, key: &K) -> &[EntityID] {
+        self.entities.get(key).map_or(&[], SmallVec::as_slice)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.entities.contains_key(key)
+    }
+
+    /// Key of the entity's component, as of the last time the index was brought up to date.
+    pub fn key_of(&self, entity: EntityID) -> Option<&K> {
+        self.keys.get(&entity)
+    }
+
+    pub(crate) fn insert(&mut self, entity: EntityID, key: K) {
+        if self.keys.get(&entity) == Some(&key) {
+            return;
+        }
+        self.remove(entity);
+        self.entities.entry(key.clone()).or_default().push(entity);
+        self.keys.insert(entity, key);
+    }
+
+    pub(crate) fn remove(&mut self, entity: EntityID) {
+        let Some(key) = self.keys.remove(&entity) else {
+            return;
+        };
+        let entities = self
+            .entities
+            .get_mut(&key)
+            .expect("keys and entities match");
+        entities.retain(|other| *other != entity);
+        if entities.is_empty() {
+            self.entities.remove(&key);
+        }
+    }
+
+    /// Adds a component that was just added to an entity, unless the index wasn't built yet.
+    fn added(&mut self, entity: EntityID, key: K) {
+        if self.refreshed.is_some() {
+            self.insert(entity, key);
+        }
+    }
+
+    /// Updates keys of components that might have changed since the last update, or builds the index from scratch.
+    fn refresh<Storage>(&mut self, world: &World<Storage>)
+    where
+        Storage: DynDispath + ComponentStorageProvider<C>,
+        C: Component<Storage> + IndexedBy<K>,
+    {
+        let rebuild = self.refreshed.is_none();
+        if rebuild {
+            self.entities.clear();
+            self.keys.clear();
+        }
+        let since = self.refreshed.unwrap_or(world.change_tick);
+        // Ticks only grow within a cycle, so anything accessed since `since` has a tick at least that big.
+        let changed = |tick: Tick| {
+            tick != 0
+                && world.change_tick.wrapping_sub(tick) <= world.change_tick.wrapping_sub(since)
+        };
+        let list = ComponentStorageProvider::<C>::storage(&world.storage);
+        if rebuild || changed(list.sparse().column_changed_tick()) {
+            for (entity, component, tick) in list.sparse().iter() {
+                if rebuild || changed(tick) {
+                    self.insert(entity, component.key());
+                }
+            }
+        }
+        for archetype in &world.archeman.archetypes {
+            let Some(storage) = archetype
+                .component_slots
+                .iter()
+                .find(|(type_index, _storage_id)| *type_index == C::TYPE_INDEX)
+                .map(|(_type_index, storage_id)| *storage_id)
+            else {
+                continue;
+            };
+            if !rebuild && !changed(list.column_changed_tick(storage)) {
+                continue;
+            }
+            let components = list.column(storage);
+            for (index, (&entity, component)) in
+                archetype.entities.iter().zip(components).enumerate()
+            {
+                if rebuild || changed(list.changed_tick(storage, index as u32)) {
+                    self.insert(entity, component.key());
+                }
+            }
+        }
+        self.refreshed = Some(world.change_tick);
+    }
+}
+
+/// Adds hooks that keep the index up to date with added and removed components, used by gen_storage_for_world!.
+pub fn index_hooks<Storage, C, K>(hooks: Hooks<Storage>) -> Hooks<Storage>
+where
+    Storage: DynDispath + ComponentStorageProvider<C> + IndexStorageProvider<C, K>,
+    C: Component<Storage> + IndexedBy<K> + 'static,
+    K: Hash + Eq + Clone + 'static,
+{
+    hooks
+        .on_add::<C>(|entity, commands| {
+            let id = entity.id();
+            commands.submit(move |world| {
+                let Some(key) = world.get::<C>(id).map(IndexedBy::key) else {
+                    return;
+                };
+                if let Some(index) = world.storage.index_mut().get_mut() {
+                    index.added(id, key);
+                }
+            });
+        })
+        .on_remove::<C>(|entity, commands| {
+            let id = entity.id();
+            commands.submit(move |world| {
+                if let Some(index) = world.storage.index_mut().get_mut() {
+                    index.remove(id);
+                }
+            });
+        })
+}
+
+impl<Storage: DynDispath> World<Storage> {
+    /// Brings the index up to date and returns it.
+    pub fn index<C, K>(&mut self) -> &ComponentIndex<C, K>
+    where
+        Storage: ComponentStorageProvider<C> + IndexStorageProvider<C, K>,
+        C: Component<Storage> + IndexedBy<K>,
+        K: Hash + Eq + Clone,
+    {
+        // Safety: world is borrowed exclusively, and refreshing only reads components.
+        let index = unsafe { self.storage.index().get_mut_unsafe() }.expect("indexes always exist");
+        index.refresh(self);
+        index
+    }
+}
+
+/// Looks up entities with component `C` by key `K`, see `ComponentIndex`.
+///
+/// Reads all components of type `C`, so it can't be used along with mutable queries of them.
+pub struct IndexG<'a, Storage, C, K> {
+    index: &'a ComponentIndex<C, K>,
+    _phantom: PhantomData<fn() -> Storage>,
+}
+
+impl<'a, Storage, C, K> Deref for IndexG<'a, Storage, C, K> {
+    type Target = ComponentIndex<C, K>;
+
+    fn deref(&self) -> &Self::Target {
+        self.index
+    }
+}
+
+unsafe impl<'a, Storage, C, K> SystemParameter<'a, Storage> for IndexG<'a, Storage, C, K>
+where
+    Storage: DynDispath + ComponentStorageProvider<C> + IndexStorageProvider<C, K>,
+    C: Component<Storage> + IndexedBy<K>,
+    K: Hash + Eq + Clone,
+{
+    fn requests() -> SmallVec<[ComponentRequests; 8]> {
+        let mut ret = SmallVec::new();
+        let mut req = ComponentRequests::default();
+        req.request(C::TYPE_INDEX, false);
+        req.request_resource(<Storage as IndexStorageProvider<C, K>>::INDEX_INDEX, true);
+        ret.push(req);
+        ret
+    }
+
+    unsafe fn from_world(world: &'a QueryWorld<'a, Storage>) -> Self {
+        let index =
+            unsafe { world.inner.storage.index().get_mut_unsafe() }.expect("indexes always exist");
+        index.refresh(&world.inner);
+        Self {
+            index,
+            _phantom: PhantomData,
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use std::{
+    any,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+};
+
+use smallvec::SmallVec;
+
+use crate::{error::EcsError, internal::DynDispath, query_world::QueryWorld};
+
+use super::{ComponentRequests, SystemParameter};
+
+/// State that belongs to a single system, and persists between its runs.
+///
+/// Starts as `T::default()`. Every `LocalG` parameter of a system has its own state, even if types are the same.
+/// Can't be used outside of a system.
+pub struct LocalG<'a, Storage, T> {
+    value: &'a mut T,
+    _phantom: PhantomData<fn() -> Storage>,
+}
+
+impl<'a, Storage, T> Deref for LocalG<'a, Storage, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.value
+    }
+}
+
+impl<'a, Storage, T> DerefMut for LocalG<'a, Storage, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.value
+    }
+}
+
+unsafe impl<'a, Storage, T> SystemParameter<'a, Storage> for LocalG<'a, Storage, T>
+where
+    Storage: DynDispath,
+    T: Default + Send + 'static,
+{
+    fn requests() -> SmallVec<[ComponentRequests; 8]> {
+        SmallVec::new()
+    }
+
+    unsafe fn from_world(world: &'a QueryWorld<'a, Storage>) -> Self {
+        unsafe { Self::try_from_world(world) }.unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    unsafe fn try_from_world(world: &'a QueryWorld<'a, Storage>) -> Result<Self, EcsError> {
+        let value = unsafe { world.next_local::<T>() }.ok_or(EcsError::LocalOutsideSystem {
+            parameter: any::type_name::<T>(),
+        })?;
+        Ok(Self {
+            value,
+            _phantom: PhantomData,
+        })
+    }
+}
@@ -1,32 +1,40 @@
-use super::SystemParameter;
+use super::{chunks::ChunkParameter, SystemParameter};
 use crate::{
-    internal::DynDispath, query_world::QueryWorld, system_parameter::ComponentRequests,
-    ArchetypeID, Component, EntityID, InArchetypeID,
+    internal::{ComponentStorageProvider, DynDispath},
+    query_world::QueryWorld,
+    system_parameter::ComponentRequests,
+    ArchetypeID, ArchetypeInfo, Component, EntityID, InArchetypeID, StorageID,
 };
 use engine_macro::gen_query_param_tuple_impls;
+use rayon::{iter::plumbing::UnindexedConsumer, prelude::*};
 use smallvec::SmallVec;
-use std::marker::PhantomData;
+use std::{
+    collections::HashMap,
+    marker::PhantomData,
+    sync::{Arc, RwLock},
+};
 
 /// Query that is Generic over storage.
 pub struct QueryG<
     'wrld,
     Storage: DynDispath,
     Param: QueryParameter<'wrld, Storage>,
-    Limits: QueryLimits = (),
+    Limits: QueryLimits<Storage> = (),
 > {
     pub(crate) world: &'wrld QueryWorld<'wrld, Storage>,
     pub(crate) _phantom: PhantomData<fn() -> (Param, Limits)>,
 }
 
-unsafe impl<'wrld, T: QueryParameter<'wrld, Storage>, Limits: QueryLimits, Storage: DynDispath>
-    SystemParameter<'wrld, Storage> for QueryG<'wrld, Storage, T, Limits>
+unsafe impl<
+        'wrld,
+        T: QueryParameter<'wrld, Storage>,
+        Limits: QueryLimits<Storage>,
+        Storage: DynDispath,
+    > SystemParameter<'wrld, Storage> for QueryG<'wrld, Storage, T, Limits>
 {
     fn requests() -> SmallVec<[ComponentRequests; 8]> {
         let mut req_vec = SmallVec::new();
-        let mut req = ComponentRequests::default();
-        T::add_requests(&mut req);
-        Limits::add_requests(&mut req);
-        req_vec.push(req);
+        req_vec.push(Self::query_requests());
         req_vec
     }
 
@@ -43,11 +51,20 @@ pub struct QueryIter<
     'wrld,
     Storage: DynDispath,
     Param: QueryParameter<'wrld, Storage>,
-    Limits: QueryLimits,
+    Limits: QueryLimits<Storage>,
 > {
     pub(crate) query: &'a mut QueryG<'wrld, Storage, Param, Limits>,
-    pub(crate) arche_index: ArchetypeID,
+    /// Archetypes that match the query.
+    pub(crate) archetypes: Arc<[ArchetypeID]>,
+    /// Position of the current archetype in `archetypes`, or of the current entity in `sparse_entities`.
+    pub(crate) position: usize,
     pub(crate) in_arche_index: InArchetypeID,
+    /// Entities to visit instead of archetypes, see `QueryParameter::sparse_entities` and `QueryG::iter_children_of`.
+    pub(crate) sparse_entities: Option<&'wrld [EntityID]>,
+    /// Requests of the query, used to check archetypes of `sparse_entities`. Empty otherwise.
+    pub(crate) requests: ComponentRequests,
+    /// Result of `QueryLimits::prepare` for the archetype that was visited last.
+    pub(crate) prepared: Option<(ArchetypeID, Limits::Prepared)>,
 }
 
 impl<
@@ -55,26 +72,16 @@ impl<
         'wrld,
         Storage: DynDispath,
         Param: QueryParameter<'wrld, Storage>,
-        Limits: QueryLimits,
+        Limits: QueryLimits<Storage>,
     > QueryIter<'a, 'wrld, Storage, Param, Limits>
 {
     pub(crate) fn skip_to_valid_arche(&mut self) {
-        let mut req = ComponentRequests::default();
-        Param::add_requests(&mut req);
-        Limits::add_requests(&mut req);
-
-        while let Some(arche) = self
-            .query
-            .world
-            .inner
-            .archeman
-            .archetypes
-            .get(self.arche_index.0 as usize)
-        {
-            if !arche.entities.is_empty() && req.satisfied_by(arche) {
+        let archetypes = &self.query.world.inner.archeman.archetypes;
+        while let Some(arche_index) = self.archetypes.get(self.position) {
+            if !archetypes[arche_index.0 as usize].entities.is_empty() {
                 break;
             } else {
-                self.arche_index.0 += 1;
+                self.position += 1;
             }
         }
     }
@@ -85,45 +92,88 @@ impl<
         'wrld,
         Storage: DynDispath,
         Param: QueryParameter<'wrld, Storage>,
-        Limits: QueryLimits,
+        Limits: QueryLimits<Storage>,
     > Iterator for QueryIter<'a, 'wrld, Storage, Param, Limits>
 {
     type Item = Param;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let archeman = &self.query.world.inner.archeman;
-        if self.arche_index.0 as usize >= archeman.archetypes.len() {
+        if let Some(entities) = self.sparse_entities {
+            while let Some(&ent_id) = entities.get(self.position) {
+                self.position += 1;
+                if let Some(item) = self.query.get_matching(ent_id, &self.requests) {
+                    return Some(item);
+                }
+            }
             return None;
         }
-        let ent_id =
-            archeman.archetypes[self.arche_index.0 as usize].entities[self.in_arche_index as usize];
+        let archeman = &self.query.world.inner.archeman;
+        loop {
+            let &arche_index = self.archetypes.get(self.position)?;
+            let in_arche_index = self.in_arche_index;
 
-        let param = unsafe {
-            Param::get_from_world(
-                self.query.world,
-                self.arche_index,
-                self.in_arche_index,
-                ent_id,
-            )
-        };
-        self.in_arche_index += 1;
-        if self.in_arche_index >= archeman.archetypes[self.arche_index.0 as usize].len() {
-            self.in_arche_index = 0;
-            self.arche_index.0 += 1;
-            self.skip_to_valid_arche();
-        }
+            self.in_arche_index += 1;
+            if self.in_arche_index >= archeman.archetypes[arche_index.0 as usize].len() {
+                self.in_arche_index = 0;
+                self.position += 1;
+                self.skip_to_valid_arche();
+            }
 
-        Some(param)
+            let ent_id =
+                archeman.archetypes[arche_index.0 as usize].entities[in_arche_index as usize];
+            // Safety: invariant checked when Query was created.
+            let prepared = match self.prepared {
+                Some((archetype, prepared)) if archetype == arche_index => prepared,
+                // Safety: invariant checked when Query was created.
+                _ => unsafe { Limits::prepare(self.query.world, arche_index) },
+            };
+            self.prepared = Some((arche_index, prepared));
+            if unsafe { Limits::matches(self.query.world, prepared, arche_index, in_arche_index) }
+                && Param::matches(self.query.world, ent_id)
+            {
+                return Some(unsafe {
+                    Param::get_from_world(self.query.world, arche_index, in_arche_index, ent_id)
+                });
+            }
+        }
     }
 }
 
 impl<'wrld, T, Limits, Storage: DynDispath> QueryG<'wrld, Storage, T, Limits>
 where
     T: QueryParameter<'wrld, Storage>,
-    Limits: QueryLimits,
+    Limits: QueryLimits<Storage>,
 {
+    fn query_requests() -> ComponentRequests {
+        let mut req = ComponentRequests::default();
+        T::add_requests(&mut req);
+        Limits::add_requests(&mut req);
+        req
+    }
+
+    /// Get query's item for this entity, if it exists and matches the query.
     pub fn get(&mut self, ent: EntityID) -> Option<T> {
+        self.get_matching(ent, &Self::query_requests())
+    }
+
+    /// Same as `get`, with `req` built by `query_requests`.
+    fn get_matching(&mut self, ent: EntityID, req: &ComponentRequests) -> Option<T> {
         let ent_info = self.world.inner.entities.get(ent)?;
+        let arche = &self.world.inner.archeman.archetypes[ent_info.archetype_id.0 as usize];
+        if !req.satisfied_by(arche)
+            || !unsafe {
+                let prepared = Limits::prepare(self.world, ent_info.archetype_id);
+                Limits::matches(
+                    self.world,
+                    prepared,
+                    ent_info.archetype_id,
+                    ent_info.in_archetype_id,
+                )
+            }
+            || !T::matches(self.world, ent)
+        {
+            return None;
+        }
         // SAFERY: invariant checked when Query was created.
         Some(unsafe {
             T::get_from_world(
@@ -135,22 +185,309 @@ where
         })
     }
 
+    /// Iterates over children of `parent` that match the query, without looking at other entities.
+    pub fn iter_children_of(
+        &mut self,
+        parent: EntityID,
+    ) -> QueryIter<'_, 'wrld, Storage, T, Limits> {
+        // Hierarchy can only change through an exclusive World, so it stays the same while QueryWorld exists.
+        let children = self.world.inner.children(parent);
+        QueryIter {
+            query: self,
+            archetypes: Arc::new([]),
+            position: 0,
+            in_arche_index: 0,
+            sparse_entities: Some(children),
+            requests: Self::query_requests(),
+            prepared: None,
+        }
+    }
+
+    fn matching_archetypes(&self) -> Arc<[ArchetypeID]> {
+        self.world
+            .inner
+            .archeman
+            .matching_archetypes(&Self::query_requests())
+    }
+
     pub fn iter(&mut self) -> QueryIter<'_, 'wrld, Storage, T, Limits> {
+        if let Some(entities) = T::sparse_entities(self.world) {
+            return QueryIter {
+                query: self,
+                archetypes: Arc::new([]),
+                position: 0,
+                in_arche_index: 0,
+                sparse_entities: Some(entities),
+                requests: Self::query_requests(),
+                prepared: None,
+            };
+        }
+        let archetypes = self.matching_archetypes();
         let mut query_iter = QueryIter {
             query: self,
-            arche_index: ArchetypeID(0),
+            archetypes,
+            position: 0,
             in_arche_index: 0,
+            sparse_entities: None,
+            requests: ComponentRequests::default(),
+            prepared: None,
         };
         query_iter.skip_to_valid_arche();
         query_iter
     }
 }
 
+/// Amount of entities that parallel iteration hands to a thread at once.
+const PAR_BATCH_SIZE: usize = 256;
+
+impl<'wrld, T, Limits, Storage: DynDispath> QueryG<'wrld, Storage, T, Limits>
+where
+    T: ChunkParameter<'wrld, Storage>,
+    Limits: QueryLimits<Storage>,
+{
+    /// Iterates over whole columns of components, one item per matching archetype.
+    ///
+    /// Mutable slices mark all components in them as changed.
+    ///
+    /// Fails to compile if limits depend on a particular entity, like `ChangedG` or filters by sparse components do.
+    pub fn iter_chunks(&mut self) -> QueryChunks<'_, 'wrld, Storage, T, Limits> {
+        const {
+            assert!(
+                !Limits::PER_ENTITY,
+                "Chunks can't be filtered per entity, use `iter` instead"
+            )
+        };
+        let archetypes = self.matching_archetypes();
+        QueryChunks {
+            query: self,
+            archetypes,
+            position: 0,
+        }
+    }
+
+    /// Iterates over matching entities on the thread pool.
+    ///
+    /// Archetypes are split into batches, so that threads never share component columns.
+    /// Sparse components can't be accessed, but can still be used as filters.
+    pub fn par_iter(&mut self) -> QueryParIter<'_, 'wrld, Storage, T>
+    where
+        T: Send + 'wrld,
+        T::Chunk: Send,
+    {
+        let world = self.world;
+        let mut batches = Vec::new();
+        for &archetype in self.matching_archetypes().iter() {
+            let len = world.inner.archeman.archetypes[archetype.0 as usize].len();
+            // Has to be checked before chunks are taken, as taking items from them updates change ticks.
+            let matches = if Limits::PER_ENTITY {
+                // Safety: invariant checked when Query was created.
+                let prepared = unsafe { Limits::prepare(world, archetype) };
+                (0..len)
+                    .map(|index| unsafe { Limits::matches(world, prepared, archetype, index) })
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            // Safety: invariant checked when Query was created.
+            let mut chunk = unsafe { T::get_chunk(world, archetype) };
+            for start in (0..len as usize).step_by(PAR_BATCH_SIZE) {
+                let size = PAR_BATCH_SIZE.min(len as usize - start);
+                batches.push(Batch::<Storage, T> {
+                    chunk: T::split_front(&mut chunk, size),
+                    size,
+                    position: 0,
+                    matches: matches.get(start..start + size).unwrap_or(&[]).to_vec(),
+                });
+            }
+        }
+        QueryParIter {
+            batches,
+            _query: PhantomData,
+        }
+    }
+
+    /// Runs `f` for every matching entity on the thread pool, see `par_iter`.
+    pub fn for_each_par(&mut self, f: impl Fn(T) + Send + Sync)
+    where
+        T: Send + 'wrld,
+        T::Chunk: Send,
+    {
+        self.par_iter().for_each(f)
+    }
+}
+
+pub struct QueryChunks<
+    'a,
+    'wrld,
+    Storage: DynDispath,
+    Param: ChunkParameter<'wrld, Storage>,
+    Limits: QueryLimits<Storage>,
+> {
+    query: &'a mut QueryG<'wrld, Storage, Param, Limits>,
+    archetypes: Arc<[ArchetypeID]>,
+    position: usize,
+}
+
+impl<
+        'a,
+        'wrld,
+        Storage: DynDispath,
+        Param: ChunkParameter<'wrld, Storage>,
+        Limits: QueryLimits<Storage>,
+    > Iterator for QueryChunks<'a, 'wrld, Storage, Param, Limits>
+{
+    type Item = Param::Slices;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let world = self.query.world;
+        loop {
+            let &archetype = self.archetypes.get(self.position)?;
+            self.position += 1;
+            if !world.inner.archeman.archetypes[archetype.0 as usize]
+                .entities
+                .is_empty()
+            {
+                // Safety: invariant checked when Query was created, and every archetype is visited once.
+                return Some(Param::into_slices(unsafe {
+                    Param::get_chunk(world, archetype)
+                }));
+            }
+        }
+    }
+}
+
+/// Parallel iterator over matching entities, see `QueryG::par_iter`.
+///
+/// Borrows the query, so that mutable components can't be handed out twice.
+pub struct QueryParIter<'a, 'wrld, Storage: DynDispath, Param: ChunkParameter<'wrld, Storage>> {
+    batches: Vec<Batch<'wrld, Storage, Param>>,
+    /// Query stays mutably borrowed, but isn't shared with threads.
+    _query: PhantomData<&'a mut ()>,
+}
+
+impl<'a, 'wrld, Storage: DynDispath, Param: ChunkParameter<'wrld, Storage> + Send> ParallelIterator
+    for QueryParIter<'a, 'wrld, Storage, Param>
+where
+    Param::Chunk: Send,
+{
+    type Item = Param;
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        self.batches
+            .into_par_iter()
+            .flat_map_iter(|batch| batch)
+            .drive_unindexed(consumer)
+    }
+}
+
+/// Part of an archetype, that is iterated over by a single thread.
+struct Batch<'wrld, Storage: DynDispath, T: ChunkParameter<'wrld, Storage>> {
+    chunk: T::Chunk,
+    size: usize,
+    position: usize,
+    /// Results of `QueryLimits::matches` for every entity, empty if limits don't depend on entities.
+    matches: Vec<bool>,
+}
+
+impl<'wrld, Storage: DynDispath, T: ChunkParameter<'wrld, Storage>> Iterator
+    for Batch<'wrld, Storage, T>
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.position < self.size {
+            let matches = self.matches.get(self.position).copied().unwrap_or(true);
+            self.position += 1;
+            if matches {
+                return Some(T::take_first(&mut self.chunk));
+            }
+            T::split_front(&mut self.chunk, 1);
+        }
+        None
+    }
+}
+
+/// Archetypes that match each kind of query, so that queries don't have to test every archetype.
+///
+/// Archetypes are never removed, so lists only have to be extended when new archetypes are created.
+#[derive(Default)]
+pub(crate) struct QueryCache {
+    lists: RwLock<HashMap<ComponentRequests, MatchingArchetypes>>,
+}
+
+#[derive(Clone)]
+struct MatchingArchetypes {
+    /// Archetype generation this list is up to date with.
+    generation: u64,
+    /// Amount of archetypes that were already tested.
+    tested: usize,
+    archetypes: Arc<[ArchetypeID]>,
+}
+
+impl Clone for QueryCache {
+    fn clone(&self) -> Self {
+        Self {
+            lists: RwLock::new(self.lists.read().expect("not poisoned").clone()),
+        }
+    }
+}
+
+impl QueryCache {
+    pub(crate) fn matching(
+        &self,
+        archetypes: &[ArchetypeInfo],
+        generation: u64,
+        req: &ComponentRequests,
+    ) -> Arc<[ArchetypeID]> {
+        if let Some(list) = self.lists.read().expect("not poisoned").get(req) {
+            if list.generation == generation {
+                return list.archetypes.clone();
+            }
+        }
+
+        let mut lists = self.lists.write().expect("not poisoned");
+        let list = lists
+            .entry(req.clone())
+            .or_insert_with(|| MatchingArchetypes {
+                generation,
+                tested: 0,
+                archetypes: Arc::new([]),
+            });
+        if list.tested < archetypes.len() {
+            let new_matches = archetypes
+                .iter()
+                .enumerate()
+                .skip(list.tested)
+                .filter(|(_, arche)| req.satisfied_by(arche))
+                .map(|(index, _)| ArchetypeID(index as u32));
+            list.archetypes = list.archetypes.iter().copied().chain(new_matches).collect();
+            list.tested = archetypes.len();
+        }
+        list.generation = generation;
+        list.archetypes.clone()
+    }
+}
+
 /// # Safety
 ///
 /// Requests should cover all components that are accessed.
 pub unsafe trait QueryParameter<'wrld, Storage: DynDispath> {
+    /// True if sparse components are accessed, which are looked up by entity rather than taken from archetypes.
+    const SPARSE: bool = false;
     fn add_requests(req: &mut ComponentRequests);
+    /// Checks that entity has required sparse components, as archetypes don't say anything about them.
+    fn matches(_world: &QueryWorld<Storage>, _ent_id: EntityID) -> bool {
+        true
+    }
+    /// Entities of the smallest sparse set that is required, if any.
+    ///
+    /// Only those entities can match, so queries iterate over them instead of every archetype.
+    fn sparse_entities(_world: &'wrld QueryWorld<'wrld, Storage>) -> Option<&'wrld [EntityID]> {
+        None
+    }
     /// # Safety
     ///
     /// Assumes that requests do not "collide" with each other.
@@ -175,6 +512,13 @@ unsafe impl<'wrld, Storage: DynDispath> QueryParameter<'wrld, Storage> for Entit
     }
 }
 
+/// Picks the shortest of entity lists, see `QueryParameter::sparse_entities`.
+pub fn shortest_entity_list<const N: usize>(
+    lists: [Option<&[EntityID]>; N],
+) -> Option<&[EntityID]> {
+    lists.into_iter().flatten().min_by_key(|list| list.len())
+}
+
 gen_query_param_tuple_impls!(1);
 gen_query_param_tuple_impls!(2);
 gen_query_param_tuple_impls!(3);
@@ -182,24 +526,130 @@ gen_query_param_tuple_impls!(4);
 gen_query_param_tuple_impls!(5);
 gen_query_param_tuple_impls!(6);
 
-pub trait QueryLimits {
+pub trait QueryLimits<Storage: DynDispath> {
+    /// True if `matches` has to be checked for every entity.
+    const PER_ENTITY: bool = false;
+    /// What `matches` needs to know about an archetype, found by `prepare` once for all of its entities.
+    type Prepared: Copy;
     fn add_requests(req: &mut ComponentRequests);
+    /// Does the part of `matches` that only depends on the archetype.
+    ///
+    /// # Safety
+    ///
+    /// Assumes that requests are satisfied and that archetype matches them.
+    unsafe fn prepare(world: &QueryWorld<Storage>, archetype: ArchetypeID) -> Self::Prepared;
+    /// Checks conditions that depend on a particular entity rather than on its archetype.
+    ///
+    /// # Safety
+    ///
+    /// Same as `prepare`, which `prepared` has to come from for this archetype.
+    unsafe fn matches(
+        _world: &QueryWorld<Storage>,
+        _prepared: Self::Prepared,
+        _archetype: ArchetypeID,
+        _index: InArchetypeID,
+    ) -> bool {
+        true
+    }
 }
 
-impl QueryLimits for () {
+impl<Storage: DynDispath> QueryLimits<Storage> for () {
+    type Prepared = ();
+
     fn add_requests(_req: &mut ComponentRequests) {}
+
+    unsafe fn prepare(_world: &QueryWorld<Storage>, _archetype: ArchetypeID) {}
 }
 
 pub struct WithG<Storage, T: Component<Storage>>(PhantomData<fn() -> (T, Storage)>);
 pub struct WithoutG<Storage, T: Component<Storage>>(PhantomData<fn() -> (T, Storage)>);
+/// Only matches entities which had `T` mutably accessed during the previous cycle.
+pub struct ChangedG<Storage, T: Component<Storage>>(PhantomData<fn() -> (T, Storage)>);
+/// Matches entities that match at least one of filters in a tuple.
+pub struct OrG<Storage, T>(PhantomData<fn() -> (T, Storage)>);
+/// Matches entities that have at least one of components in a tuple.
+pub struct AnyOfG<Storage, T>(PhantomData<fn() -> (T, Storage)>);
+
+impl<Storage: DynDispath, T: Component<Storage>> QueryLimits<Storage> for WithG<Storage, T> {
+    const PER_ENTITY: bool = Storage::SPARSE[T::TYPE_INDEX as usize];
+
+    type Prepared = ();
 
-impl<Storage, T: Component<Storage>> QueryLimits for WithG<Storage, T> {
     fn add_requests(req: &mut ComponentRequests) {
-        req.require(T::TYPE_INDEX);
+        if !Self::PER_ENTITY {
+            req.require(T::TYPE_INDEX);
+        }
+    }
+
+    unsafe fn prepare(_world: &QueryWorld<Storage>, _archetype: ArchetypeID) {}
+
+    unsafe fn matches(
+        world: &QueryWorld<Storage>,
+        _prepared: (),
+        archetype: ArchetypeID,
+        index: InArchetypeID,
+    ) -> bool {
+        !Self::PER_ENTITY || world.contains_sparse(T::TYPE_INDEX, world.entity_at(archetype, index))
     }
 }
-impl<Storage, T: Component<Storage>> QueryLimits for WithoutG<Storage, T> {
+impl<Storage: DynDispath, T: Component<Storage>> QueryLimits<Storage> for WithoutG<Storage, T> {
+    const PER_ENTITY: bool = Storage::SPARSE[T::TYPE_INDEX as usize];
+
+    type Prepared = ();
+
     fn add_requests(req: &mut ComponentRequests) {
-        req.exclude(T::TYPE_INDEX);
+        if !Self::PER_ENTITY {
+            req.exclude(T::TYPE_INDEX);
+        }
+    }
+
+    unsafe fn prepare(_world: &QueryWorld<Storage>, _archetype: ArchetypeID) {}
+
+    unsafe fn matches(
+        world: &QueryWorld<Storage>,
+        _prepared: (),
+        archetype: ArchetypeID,
+        index: InArchetypeID,
+    ) -> bool {
+        !Self::PER_ENTITY
+            || !world.contains_sparse(T::TYPE_INDEX, world.entity_at(archetype, index))
+    }
+}
+impl<Storage, T> QueryLimits<Storage> for ChangedG<Storage, T>
+where
+    Storage: DynDispath + ComponentStorageProvider<T>,
+    T: Component<Storage>,
+{
+    const PER_ENTITY: bool = true;
+    /// Storage of `T` in the archetype, unless it's sparse.
+    type Prepared = Option<StorageID>;
+
+    fn add_requests(req: &mut ComponentRequests) {
+        req.request_shared(T::TYPE_INDEX);
+        if !Storage::is_sparse(T::TYPE_INDEX) {
+            req.require(T::TYPE_INDEX);
+        }
+    }
+
+    unsafe fn prepare(world: &QueryWorld<Storage>, archetype: ArchetypeID) -> Option<StorageID> {
+        if Storage::is_sparse(T::TYPE_INDEX) {
+            return None;
+        }
+        let storage = world
+            .storage_for_archetype::<T>(archetype)
+            .expect("component assumed to exist, as we've asked for it");
+        Some(storage)
+    }
+
+    unsafe fn matches(
+        world: &QueryWorld<Storage>,
+        storage: Option<StorageID>,
+        archetype: ArchetypeID,
+        index: InArchetypeID,
+    ) -> bool {
+        match storage {
+            Some(storage) => unsafe { world.changed::<T>(storage, index) },
+            None => unsafe { world.changed_sparse::<T>(world.entity_at(archetype, index)) },
+        }
     }
 }
@@ -1,8 +1,12 @@
 #[cfg(test)]
 mod tests {
-    use engine_ecs::{EntityID, ParamGuard, World, WorldRun};
+    use engine_ecs::{
+        exclusive_system, resource_changed, system, Bundle, Children, EcsError, EntityID,
+        ParamGuard, Parent, QueryData, Schedule, ScheduleError, SystemParam, World, WorldRun,
+    };
     use engine_macro::gen_storage_for_world;
     use serde::{Deserialize, Serialize};
+    use std::sync::atomic::{AtomicUsize, Ordering};
 
     #[derive(Default, Clone, Serialize, Deserialize, PartialEq, Eq, Debug)]
     struct Component1(u8);
@@ -12,12 +16,20 @@ mod tests {
     struct Component3(u16);
     #[derive(Default, Clone, Serialize, Deserialize, PartialEq, Eq, Debug)]
     struct Resource1(u32);
+    #[derive(Clone, Serialize, Deserialize, PartialEq, Eq, Debug)]
+    struct Resource2(u32);
+    #[derive(Clone, Serialize, Deserialize, PartialEq, Eq, Debug)]
+    struct Event1(u32);
 
     gen_storage_for_world! {
         : components
             Component1 Component2 Component3
         : resources
             Resource1
+        : optional_resources
+            Resource2
+        : events
+            Event1
     }
 
     #[test]
@@ -104,6 +116,61 @@ mod tests {
         );
     }
 
+    #[test]
+    fn entity_insert() {
+        let mut world = World::<ComponentStorage>::new();
+        let ent1 = world.spawn(Component1(0));
+        let ent2 = world.spawn(Component1(1));
+        let ent3 = world.spawn(Component1(2));
+
+        assert!(world.insert(ent1, (Component2(10), Component3(20))));
+        assert_eq!(world.get::<Component1>(ent1), Some(&Component1(0)));
+        assert_eq!(world.get::<Component2>(ent1), Some(&Component2(10)));
+        assert_eq!(world.get::<Component3>(ent1), Some(&Component3(20)));
+
+        assert_eq!(world.get::<Component1>(ent2), Some(&Component1(1)));
+        assert_eq!(world.get::<Component1>(ent3), Some(&Component1(2)));
+        assert_eq!(world.get::<Component2>(ent3), None);
+        assert_eq!(world.entity_count(), 3);
+    }
+
+    #[test]
+    fn entity_insert_replaces() {
+        let mut world = World::<ComponentStorage>::new();
+        let ent1 = world.spawn((Component1(0), Component2(1)));
+        let ent2 = world.spawn((Component1(2), Component2(3)));
+
+        assert!(world.insert(ent1, Component2(5)));
+        assert_eq!(world.get::<Component1>(ent1), Some(&Component1(0)));
+        assert_eq!(world.get::<Component2>(ent1), Some(&Component2(5)));
+        assert_eq!(world.get::<Component1>(ent2), Some(&Component1(2)));
+        assert_eq!(world.get::<Component2>(ent2), Some(&Component2(3)));
+
+        world.despawn(ent2);
+        assert!(!world.insert(ent2, Component3(0)));
+    }
+
+    #[test]
+    fn entity_remove() {
+        let mut world = World::<ComponentStorage>::new();
+        let ent1 = world.spawn((Component1(0), Component2(1), Component3(2)));
+        let ent2 = world.spawn((Component1(3), Component2(4), Component3(5)));
+
+        assert!(world.remove::<(Component2, Component3)>(ent1));
+        assert_eq!(world.get::<Component1>(ent1), Some(&Component1(0)));
+        assert_eq!(world.get::<Component2>(ent1), None);
+        assert_eq!(world.get::<Component3>(ent1), None);
+        assert_eq!(world.get::<Component2>(ent2), Some(&Component2(4)));
+
+        assert!(world.remove::<Component2>(ent1));
+        assert_eq!(world.get::<Component1>(ent1), Some(&Component1(0)));
+
+        let query_world = world.query_world();
+        let mut query: ParamGuard<_, Query<(EntityID, &Component1)>> = query_world.parameter();
+        let res = query.iter().collect::<Vec<_>>();
+        assert_eq!(res.len(), 2);
+    }
+
     #[test]
     fn roundtrip_bincode() {
         let mut world_ini = World::<ComponentStorage>::new();
@@ -219,6 +286,716 @@ mod tests {
         let _query2: ParamGuard<_, Query<(&mut Component1, &Component2)>> = query_world.parameter();
     }
 
+    #[test]
+    fn query_optional() {
+        let mut world = World::<ComponentStorage>::new();
+        let ent1 = world.spawn((Component1(0), Component2(1)));
+        let ent2 = world.spawn((Component1(2), Component2(3), Component3(4)));
+        let ent3 = world.spawn(Component3(5));
+
+        {
+            let query_world = world.query_world();
+            let mut query: ParamGuard<_, Query<(EntityID, &Component1, Option<&Component3>)>> =
+                query_world.parameter();
+            let mut items = query.iter().collect::<Vec<_>>();
+            items.sort_by_key(|item| item.1 .0);
+            assert_eq!(
+                items,
+                vec![
+                    (ent1, &Component1(0), None),
+                    (ent2, &Component1(2), Some(&Component3(4)))
+                ]
+            );
+        }
+        {
+            let query_world = world.query_world();
+            let mut query: ParamGuard<_, Query<(&Component2, Option<&mut Component3>)>> =
+                query_world.parameter();
+            for (c2, c3) in query.iter() {
+                if let Some(c3) = c3 {
+                    c3.0 += c2.0 as u16;
+                }
+            }
+        }
+        assert_eq!(world.get::<Component3>(ent2), Some(&Component3(7)));
+        assert_eq!(world.get::<Component3>(ent3), Some(&Component3(5)));
+    }
+
+    #[test]
+    fn query_filter_combinations() {
+        let mut world = World::<ComponentStorage>::new();
+        let ent1 = world.spawn((Component1(0), Component2(1)));
+        let ent2 = world.spawn((Component1(2), Component3(3)));
+        let ent3 = world.spawn((Component1(4), Component2(5), Component3(6)));
+        let ent4 = world.spawn(Component1(7));
+        let ent5 = world.spawn(Component2(8));
+
+        type WithoutComponent3 = (With<Component1>, Without<Component3>);
+        type WithEither = (With<Component1>, Or<(With<Component2>, With<Component3>)>);
+        type OnlyComponent3 = (AnyOf<(Component2, Component3)>, Without<Component2>);
+        let sorted = |mut items: Vec<EntityID>| {
+            items.sort();
+            items
+        };
+        let query_world = world.query_world();
+        {
+            let mut query: ParamGuard<_, Query<EntityID, WithoutComponent3>> =
+                query_world.parameter();
+            let items = query.iter().collect();
+            assert_eq!(sorted(items), sorted(vec![ent1, ent4]));
+        }
+        {
+            let mut query: ParamGuard<_, Query<EntityID, WithEither>> = query_world.parameter();
+            let items = query.iter().collect();
+            assert_eq!(sorted(items), sorted(vec![ent1, ent2, ent3]));
+            assert_eq!(query.get(ent4), None);
+            assert_eq!(query.get(ent5), None);
+        }
+        {
+            let mut query: ParamGuard<_, Query<EntityID, OnlyComponent3>> = query_world.parameter();
+            assert_eq!(query.iter().collect::<Vec<_>>(), vec![ent2]);
+        }
+    }
+
+    #[test]
+    fn query_or_changed() {
+        let mut world = World::<ComponentStorage>::new();
+        let ent1 = world.spawn((Component1(0), Component2(1)));
+        let ent2 = world.spawn((Component1(2), Component3(3)));
+        let ent3 = world.spawn(Component1(4));
+        world.next_cycle();
+        world.next_cycle();
+
+        world.get_mut::<Component2>(ent1).unwrap().0 = 10;
+        world.next_cycle();
+
+        type ChangedOrWith = Or<(Changed<Component2>, With<Component3>)>;
+        let query_world = world.query_world();
+        let mut query: ParamGuard<_, Query<EntityID, ChangedOrWith>> = query_world.parameter();
+        let mut items = query.iter().collect::<Vec<_>>();
+        items.sort();
+        let mut expected = vec![ent1, ent2];
+        expected.sort();
+        assert_eq!(items, expected);
+        assert_eq!(query.get(ent3), None);
+    }
+
+    #[test]
+    fn query_new_archetypes() {
+        let mut world = World::<ComponentStorage>::new();
+        let generation = world.archetype_generation();
+        let ent1 = world.spawn((Component1(0), Component2(1)));
+        let ent2 = world.spawn(Component1(2));
+        assert_eq!(world.archetype_generation(), generation + 2);
+        world.spawn(Component1(3));
+        assert_eq!(world.archetype_generation(), generation + 2);
+
+        {
+            let query_world = world.query_world();
+            let mut query: ParamGuard<_, Query<EntityID, With<Component2>>> =
+                query_world.parameter();
+            assert_eq!(query.iter().collect::<Vec<_>>(), vec![ent1]);
+        }
+        let ent3 = world.spawn((Component2(4), Component3(5)));
+        world.insert(ent2, Component2(6));
+        world.remove::<Component2>(ent1);
+        {
+            let query_world = world.query_world();
+            let mut query: ParamGuard<_, Query<EntityID, With<Component2>>> =
+                query_world.parameter();
+            assert_eq!(query.iter().collect::<Vec<_>>(), vec![ent2, ent3]);
+        }
+        let cloned = world.clone();
+        let query_world = cloned.query_world_shared();
+        let mut query: ParamGuard<_, Query<EntityID, With<Component2>>> = query_world.parameter();
+        assert_eq!(query.iter().count(), 2);
+    }
+
+    #[test]
+    fn query_par_iter() {
+        let mut world = World::<ComponentStorage>::new();
+        for i in 0..1000 {
+            if i % 3 == 0 {
+                world.spawn((Component1(1), Component2(i), Component3(0)));
+            } else {
+                world.spawn((Component1(2), Component2(i)));
+            }
+        }
+        let ent = world.spawn((Component1(3), Component3(0)));
+        world.next_cycle();
+        world.next_cycle();
+        world.get_mut::<Component1>(ent).unwrap().0 = 4;
+        world.next_cycle();
+
+        let query_world = world.query_world();
+        let mut query: ParamGuard<_, Query<(&Component1, &mut Component2)>> =
+            query_world.parameter();
+        query.for_each_par(|(c1, c2)| c2.0 += c1.0 as u32);
+        assert_eq!(
+            query.iter().map(|(_, c2)| c2.0).sum::<u32>(),
+            (0..1000).sum::<u32>() + 334 + 666 * 2
+        );
+        drop(query);
+
+        let visited = AtomicUsize::new(0);
+        let mut query: ParamGuard<_, Query<&mut Component3, Changed<Component1>>> =
+            query_world.parameter();
+        query.for_each_par(|c3| {
+            c3.0 += 1;
+            visited.fetch_add(1, Ordering::Relaxed);
+        });
+        assert_eq!(visited.into_inner(), 1);
+        drop(query);
+        drop(query_world);
+        assert_eq!(world.get::<Component3>(ent), Some(&Component3(1)));
+    }
+
+    #[test]
+    fn query_iter_chunks() {
+        let mut world = World::<ComponentStorage>::new();
+        let ent1 = world.spawn((Component1(0), Component2(1)));
+        let ent2 = world.spawn((Component1(2), Component2(3)));
+        let ent3 = world.spawn((Component1(4), Component3(5)));
+        world.next_cycle();
+        world.next_cycle();
+
+        let query_world = world.query_world();
+        {
+            let mut query: ParamGuard<_, Query<(EntityID, &mut Component1), With<Component2>>> =
+                query_world.parameter();
+            let mut chunks = query.iter_chunks();
+            let (entities, c1) = chunks.next().unwrap();
+            assert_eq!(entities, &[ent1, ent2]);
+            for c in c1.iter_mut() {
+                c.0 += 10;
+            }
+            assert!(chunks.next().is_none());
+        }
+        {
+            let mut query: ParamGuard<_, Query<(&Component1, Option<&Component3>)>> =
+                query_world.parameter();
+            let chunks = query.iter_chunks().collect::<Vec<_>>();
+            assert_eq!(
+                chunks,
+                vec![
+                    (&[Component1(10), Component1(12)][..], None),
+                    (&[Component1(4)][..], Some(&[Component3(5)][..])),
+                ]
+            );
+        }
+        drop(query_world);
+        world.next_cycle();
+        let query_world = world.query_world();
+        let mut query: ParamGuard<_, Query<EntityID, Changed<Component1>>> =
+            query_world.parameter();
+        let mut changed = query.iter().collect::<Vec<_>>();
+        changed.sort();
+        let mut expected = vec![ent1, ent2];
+        expected.sort();
+        assert_eq!(changed, expected);
+        assert_eq!(query.get(ent3), None);
+    }
+
+    #[test]
+    fn hierarchy() {
+        let mut world = World::<ComponentStorage>::new();
+        let vessel1 = world.spawn(Component2(0));
+        let vessel2 = world.spawn(Component2(1));
+        let building1 = world.spawn(Component1(2));
+        let building2 = world.spawn((Component1(3), Component3(4)));
+        let building3 = world.spawn(Component1(5));
+
+        assert!(world.set_parent(building1, vessel1));
+        assert!(world.set_parent(building2, vessel1));
+        assert!(world.set_parent(building3, vessel2));
+        assert_eq!(world.children(vessel1), &[building1, building2]);
+        assert_eq!(world.parent(building3), Some(vessel2));
+        // Would form a cycle.
+        assert!(!world.set_parent(vessel1, building1));
+        assert!(!world.set_parent(vessel1, vessel1));
+
+        {
+            let query_world = world.query_world();
+            let mut query: ParamGuard<_, Query<&Component1, Without<Component3>>> =
+                query_world.parameter();
+            assert_eq!(
+                query.iter_children_of(vessel1).collect::<Vec<_>>(),
+                vec![&Component1(2)]
+            );
+            let mut query: ParamGuard<_, Query<(EntityID, &Parent)>> = query_world.parameter();
+            assert_eq!(query.iter().count(), 3);
+            assert_eq!(query.get(building3).unwrap().1.get(), vessel2);
+        }
+
+        assert!(world.set_parent(building3, vessel1));
+        assert_eq!(world.children(vessel2), &[]);
+        assert_eq!(world.get::<Children>(vessel2), None);
+        assert!(world.remove_parent(building3));
+        assert!(!world.remove_parent(building3));
+        assert_eq!(world.children(vessel1), &[building1, building2]);
+
+        world.despawn(building1);
+        assert_eq!(world.children(vessel1), &[building2]);
+        world.despawn(vessel1);
+        assert_eq!(world.parent(building2), None);
+        assert!(world.get::<Component3>(building2).is_some());
+
+        let child = world.spawn(Component1(6));
+        let grandchild = world.spawn(Component1(7));
+        world.set_parent(building2, vessel2);
+        world.set_parent(child, building2);
+        world.set_parent(grandchild, child);
+        assert!(world.despawn_recursive(building2));
+        assert_eq!(world.entity_count(), 2);
+        assert_eq!(world.children(vessel2), &[]);
+        assert!(world.get::<Component1>(building3).is_some());
+    }
+
+    #[derive(Bundle)]
+    struct InnerBundle {
+        c2: Component2,
+        c3: Component3,
+    }
+
+    #[derive(Bundle)]
+    struct OuterBundle {
+        c1: Component1,
+        inner: InnerBundle,
+    }
+
+    #[test]
+    fn bundle_derive() {
+        let mut world = World::<ComponentStorage>::new();
+        let ent1 = world.spawn(OuterBundle {
+            c1: Component1(0),
+            inner: InnerBundle {
+                c2: Component2(1),
+                c3: Component3(2),
+            },
+        });
+        let ent2 = world.spawn((Component1(3), Component2(4), Component3(5)));
+        assert_eq!(world.archetype_generation(), 1);
+        assert_eq!(world.get::<Component2>(ent1), Some(&Component2(1)));
+        assert_eq!(world.get::<Component3>(ent1), Some(&Component3(2)));
+
+        world.remove::<InnerBundle>(ent2);
+        assert_eq!(world.get::<Component1>(ent2), Some(&Component1(3)));
+        assert_eq!(world.get::<Component2>(ent2), None);
+
+        let ent3 = world.spawn((
+            Component1(6),
+            (),
+            (),
+            (),
+            (),
+            (),
+            (),
+            (),
+            (),
+            (),
+            (),
+            (),
+            (),
+            (),
+            (),
+            Component2(7),
+        ));
+        assert_eq!(world.get::<Component2>(ent3), Some(&Component2(7)));
+    }
+
+    #[derive(SystemParam)]
+    struct SumParams<'w> {
+        query: Query<'w, &'w Component2>,
+        res: &'w mut Resource1,
+        commands: Commands<'w>,
+    }
+
+    #[derive(QueryData)]
+    struct ItemView<'w> {
+        id: EntityID,
+        c1: &'w Component1,
+        c3: Option<&'w mut Component3>,
+    }
+
+    #[test]
+    fn system_param_derive() {
+        fn sum<'a>(mut params: SumParams<'a>) {
+            params.res.0 = params.query.iter().map(|c2| c2.0).sum();
+            params.commands.submit(|world| {
+                world.spawn(Component2(10));
+            });
+        }
+        fn read<'a>(_query: Query<'a, &'a Component2>, _res: &Resource1) {}
+
+        let mut world = World::<ComponentStorage>::new();
+        world.spawn((Component1(0), Component2(1)));
+        world.spawn((Component2(2),));
+
+        let mut schedule = Schedule::new()
+            .with_system(system!(sum))
+            .with_system(system!(read));
+        assert_eq!(schedule.stage_count(), Ok(2));
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<Resource1>().0, 3);
+        assert_eq!(world.entity_count(), 3);
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<Resource1>().0, 13);
+    }
+
+    #[test]
+    fn query_data_derive() {
+        let mut world = World::<ComponentStorage>::new();
+        let ent1 = world.spawn((Component1(1), Component3(0)));
+        let ent2 = world.spawn((Component1(2), Component2(0)));
+        world.spawn((Component2(3),));
+
+        let query_world = world.query_world();
+        let mut query: ParamGuard<_, Query<ItemView>> = query_world.parameter();
+        let mut items = query
+            .iter()
+            .map(|item| (item.id, item.c1.0, item.c3.is_some()))
+            .collect::<Vec<_>>();
+        items.sort();
+        let mut expected = vec![(ent1, 1, true), (ent2, 2, false)];
+        expected.sort();
+        assert_eq!(items, expected);
+
+        query.for_each_par(|item| {
+            if let Some(c3) = item.c3 {
+                c3.0 += item.c1.0 as u16;
+            }
+        });
+        drop(query);
+        drop(query_world);
+        assert_eq!(world.get::<Component3>(ent1), Some(&Component3(1)));
+    }
+
+    #[test]
+    fn entity_ref() {
+        let mut world = World::<ComponentStorage>::new();
+        let ent = world.spawn((Component1(1), Component3(3)));
+        assert!(world.entity(EntityID::default()).is_none());
+
+        let entity = world.entity(ent).unwrap();
+        assert_eq!(entity.id(), ent);
+        assert_eq!(entity.get::<Component1>(), Some(&Component1(1)));
+        assert_eq!(entity.get::<Component2>(), None);
+        assert!(entity.contains::<Component3>());
+        assert_eq!(entity.component_types().count(), 2);
+
+        let mut entity = world.entity_mut(ent).unwrap();
+        entity.get_mut::<Component1>().unwrap().0 = 2;
+        entity.insert(Component2(2)).remove::<Component3>();
+        assert_eq!(entity.get::<Component1>(), Some(&Component1(2)));
+        assert_eq!(entity.get::<Component2>(), Some(&Component2(2)));
+        assert!(!entity.contains::<Component3>());
+        entity.despawn();
+        assert!(world.entity(ent).is_none());
+        assert_eq!(world.entity_count(), 0);
+    }
+
+    #[test]
+    fn optional_resources() {
+        fn copy(source: Option<&Resource2>, target: &mut Resource1) {
+            target.0 = source.map_or(0, |source| source.0);
+        }
+
+        let mut world = World::<ComponentStorage>::new();
+        assert!(world.contains_resource::<Resource1>());
+        assert!(!world.contains_resource::<Resource2>());
+
+        let mut schedule = Schedule::new().with_system(system!(copy));
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<Resource1>().0, 0);
+
+        world.insert_resource(Resource2(3));
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<Resource1>().0, 3);
+
+        assert_eq!(world.remove_resource::<Resource2>(), Some(Resource2(3)));
+        assert_eq!(world.remove_resource::<Resource2>(), None);
+        assert!(world.get_resource::<Resource2>().is_none());
+
+        let world =
+            bincode::deserialize::<World<ComponentStorage>>(&bincode::serialize(&world).unwrap())
+                .unwrap();
+        assert!(!world.contains_resource::<Resource2>());
+    }
+
+    #[test]
+    #[should_panic(expected = "Resource2` does not exist")]
+    fn missing_resource() {
+        fn read(_resource: &Resource2) {}
+
+        let mut world = World::<ComponentStorage>::new();
+        Schedule::new().with_system(system!(read)).run(&mut world);
+    }
+
+    #[test]
+    fn fallible_parameters() {
+        let mut world = World::<ComponentStorage>::new();
+        world.spawn((Component1(0), Component2(1)));
+
+        {
+            let query_world = world.query_world();
+            let _query1: ParamGuard<_, Query<(&mut Component1, &Component2)>> =
+                query_world.parameter();
+            let err = query_world
+                .try_parameter::<Query<&Component1>>()
+                .err()
+                .unwrap();
+            assert_eq!(
+                err,
+                EcsError::IncompatibleRequests {
+                    first: "[&mut Component1, &Component2]".to_string(),
+                    second: "[&Component1]".to_string(),
+                }
+            );
+            // Failed parameter doesn't hold its requests.
+            assert!(query_world.try_parameter::<Query<&Component2>>().is_ok());
+        }
+
+        let query_world = world.query_world_shared();
+        let err = query_world.try_parameter::<Commands>().err().unwrap();
+        assert!(matches!(err, EcsError::ExclusiveOnShared { .. }));
+        assert_eq!(
+            query_world.try_run(|_: &Resource2| {}).err(),
+            Some(EcsError::MissingResource {
+                resource: std::any::type_name::<Resource2>()
+            })
+        );
+    }
+
+    #[test]
+    fn schedule_skips_failing_systems() {
+        fn read(_resource: &Resource2) {}
+        fn write<'a>(mut query: Query<'a, &'a mut Component1>) {
+            for component in query.iter() {
+                component.0 += 1;
+            }
+        }
+
+        for parallel in [false, true] {
+            let mut world = World::<ComponentStorage>::new();
+            let ent = world.spawn(Component1(0));
+            let mut schedule = Schedule::new()
+                .with_system(system!(read))
+                .with_system(system!(write));
+            let mut try_run = |world: &mut World<ComponentStorage>| {
+                if parallel {
+                    schedule.try_run_parallel(world)
+                } else {
+                    schedule.try_run(world)
+                }
+            };
+            let errors = try_run(&mut world).unwrap_err();
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].0, "read");
+            assert_eq!(world.get::<Component1>(ent), Some(&Component1(1)));
+
+            world.insert_resource(Resource2(0));
+            assert_eq!(try_run(&mut world), Ok(()));
+        }
+    }
+
+    #[test]
+    fn schedule_spawns_deterministic_ids() {
+        fn spawn1(commands: Commands) {
+            for i in 0..3 {
+                commands.spawn(Component1(i));
+            }
+        }
+        fn spawn2(commands: Commands) {
+            for i in 0..3 {
+                commands.spawn(Component2(i));
+            }
+        }
+
+        let mut world = World::<ComponentStorage>::new();
+        let spawned = world.spawn_batch((0..4).map(Component3));
+        world.despawn(spawned[1]);
+        world.despawn(spawned[2]);
+
+        let spawned_ids = |parallel| {
+            let mut world = world.clone();
+            let mut schedule = Schedule::new()
+                .with_system(system!(spawn1))
+                .with_system(system!(spawn2));
+            if parallel {
+                schedule.run_parallel(&mut world);
+            } else {
+                schedule.run(&mut world);
+            }
+            let query_world = world.query_world_shared();
+            let mut query1: ParamGuard<_, Query<(EntityID, &Component1)>> = query_world.parameter();
+            let mut query2: ParamGuard<_, Query<(EntityID, &Component2)>> = query_world.parameter();
+            let mut ids: Vec<_> = query1.iter().map(|(ent, c)| (1, c.0 as u32, ent)).collect();
+            ids.extend(query2.iter().map(|(ent, c)| (2, c.0, ent)));
+            ids.sort();
+            ids
+        };
+        let serial = spawned_ids(false);
+        assert_eq!(serial.len(), 6);
+        for _ in 0..10 {
+            assert_eq!(spawned_ids(true), serial);
+        }
+    }
+
+    #[test]
+    fn schedule_spawns_dont_skip_slots() {
+        fn spawn_many(commands: Commands) {
+            for i in 0..100 {
+                commands.spawn(Component1(i));
+            }
+        }
+        fn spawn_more(commands: Commands) {
+            for i in 0..10 {
+                commands.spawn(Component2(i));
+            }
+        }
+        fn read1<'a>(mut query: Query<'a, &'a Component1>) {
+            query.iter().count();
+        }
+        fn read2<'a>(mut query: Query<'a, &'a Component2>) {
+            query.iter().count();
+        }
+        fn read3<'a>(mut query: Query<'a, &'a Component3>) {
+            query.iter().count();
+        }
+
+        for parallel in [false, true] {
+            let mut world = World::<ComponentStorage>::new();
+            let mut schedule = Schedule::new()
+                .with_system(system!(read1))
+                .with_system(system!(spawn_many))
+                .with_system(system!(read2))
+                .with_system(system!(spawn_more).after("spawn_many"))
+                .with_system(system!(read3));
+            if parallel {
+                schedule.run_parallel(&mut world);
+            } else {
+                schedule.run(&mut world);
+            }
+            let query_world = world.query_world_shared();
+            let mut query: ParamGuard<_, Query<EntityID>> = query_world.parameter();
+            let mut indices: Vec<_> = query.iter().map(|ent| ent.to_raw() & 0xffff_ffff).collect();
+            indices.sort();
+            // Systems that don't spawn anything don't leave holes, neither do stages that run one after another.
+            assert_eq!(indices, (1..=110).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn exclusive_systems() {
+        fn spawn(commands: Commands) {
+            commands.spawn(Component1(1));
+        }
+        fn count(world: &mut World<ComponentStorage>) {
+            world.resource_mut::<Resource1>().0 = world.entity_count();
+            world.spawn(Component2(2));
+        }
+        fn read<'a>(mut query: Query<'a, &'a Component2>, res: &mut Resource1) {
+            res.0 += query.iter().count() as u32 * 10;
+        }
+
+        for parallel in [false, true] {
+            let mut world = World::<ComponentStorage>::new();
+            let mut schedule = Schedule::new()
+                .with_system(system!(spawn))
+                .with_system(exclusive_system!(count))
+                .with_system(system!(read));
+            assert_eq!(schedule.stage_count(), Ok(3));
+            if parallel {
+                schedule.run_parallel(&mut world);
+            } else {
+                schedule.run(&mut world);
+            }
+            assert_eq!(world.resource::<Resource1>().0, 11);
+        }
+
+        let mut world = World::<ComponentStorage>::new();
+        let query_world = world.query_world();
+        assert_eq!(
+            exclusive_system!(count).try_run(&query_world).err(),
+            Some(EcsError::ExclusiveSystem { system: "count" })
+        );
+    }
+
+    #[test]
+    fn local_state() {
+        fn count(mut first: Local<u32>, mut second: Local<u32>, res: &mut Resource1) {
+            *first += 1;
+            *second += 10;
+            res.0 += *first + *second;
+        }
+
+        let mut world = World::<ComponentStorage>::new();
+        let mut schedule = Schedule::new()
+            .with_system(system!(count))
+            .with_system(system!(count));
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<Resource1>().0, 22);
+        // Each system keeps its own state.
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<Resource1>().0, 66);
+
+        let query_world = world.query_world();
+        assert_eq!(
+            query_world.try_parameter::<Local<u32>>().err(),
+            Some(EcsError::LocalOutsideSystem { parameter: "u32" })
+        );
+    }
+
+    #[test]
+    fn hooks() {
+        let mut world = World::<ComponentStorage>::new();
+        world.on_add::<Component1>(|entity, commands| {
+            let value = entity.get::<Component1>().unwrap().0;
+            commands.insert(entity.id(), Component3(value.into()));
+        });
+        world.on_remove::<Component2>(|entity, commands| {
+            let value = entity.get::<Component2>().unwrap().0;
+            commands.submit(move |world| world.resource_mut::<Resource1>().0 += value);
+        });
+
+        let ent1 = world.spawn((Component1(1), Component2(10)));
+        assert_eq!(world.get::<Component3>(ent1), Some(&Component3(1)));
+        // Replacing a component doesn't add it.
+        world.insert(ent1, Component1(2));
+        assert_eq!(world.get::<Component3>(ent1), Some(&Component3(1)));
+        world.remove::<Component2>(ent1);
+        assert_eq!(world.resource::<Resource1>().0, 10);
+        world.remove::<Component2>(ent1);
+        assert_eq!(world.resource::<Resource1>().0, 10);
+
+        let ent2 = world.spawn(Component2(5));
+        world.insert(ent2, Component1(3));
+        assert_eq!(world.get::<Component3>(ent2), Some(&Component3(3)));
+
+        // Clones keep hooks, and commands of systems trigger them too.
+        let mut world = world.clone();
+        {
+            let query_world = world.query_world();
+            query_world.parameter::<Commands>().despawn(ent2);
+        }
+        assert_eq!(world.resource::<Resource1>().0, 15);
+
+        // Hooks can despawn an entity that is borrowed by EntityMut.
+        let mut world = World::<ComponentStorage>::new();
+        world.on_add::<Component3>(|entity, commands| {
+            commands.despawn(entity.id());
+        });
+        let ent = world.spawn(Component1(0));
+        let mut entity = world.entity_mut(ent).unwrap();
+        entity.insert(Component3(0));
+        assert!(entity.is_despawned());
+        assert_eq!(entity.get::<Component1>(), None);
+        assert!(!entity.contains::<Component1>());
+        assert_eq!(entity.component_types().count(), 0);
+        entity.remove::<Component1>();
+        assert!(world.entity(ent).is_none());
+    }
+
     #[test]
     fn query_disjoint() {
         let mut world = World::<ComponentStorage>::new();
@@ -297,10 +1074,252 @@ mod tests {
             query_world.run(sys);
         }
 
-        let param = world.resource();
+        let param = world.resource::<Resource1>();
         assert_eq!(param.0, 11);
     }
 
+    #[test]
+    fn spawn_detection() {
+        let mut world = World::<ComponentStorage>::new();
+        let ent1 = world.spawn((Component1(0), Component2(1)));
+        let ent2 = world.spawn(Component2(2));
+        world.next_cycle();
+        world.despawn(ent2);
+        world.insert(ent1, Component3(3));
+
+        {
+            let query_world = world.query_world();
+            let spawned: ParamGuard<_, Spawned<Component2>> = query_world.parameter();
+            assert_eq!(spawned.iter().collect::<Vec<_>>(), vec![ent1, ent2]);
+            let spawned: ParamGuard<_, Spawned<Component3>> = query_world.parameter();
+            assert!(spawned.is_empty());
+            let despawned: ParamGuard<_, Despawned<Component2>> = query_world.parameter();
+            assert!(despawned.is_empty());
+        }
+
+        world.next_cycle();
+        let query_world = world.query_world();
+        let spawned: ParamGuard<_, Spawned<Component2>> = query_world.parameter();
+        assert!(spawned.is_empty());
+        let spawned: ParamGuard<_, Spawned<Component3>> = query_world.parameter();
+        assert_eq!(spawned.iter().collect::<Vec<_>>(), vec![ent1]);
+        let despawned: ParamGuard<_, Despawned<Component2>> = query_world.parameter();
+        assert!(despawned.contains(ent2));
+        assert!(!despawned.contains(ent1));
+    }
+
+    #[test]
+    fn despawn_detection_on_remove() {
+        let mut world = World::<ComponentStorage>::new();
+        let ent1 = world.spawn((Component1(0), Component2(1)));
+        world.next_cycle();
+        world.remove::<(Component2, Component3)>(ent1);
+        world.next_cycle();
+
+        let query_world = world.query_world();
+        let despawned: ParamGuard<_, Despawned<Component2>> = query_world.parameter();
+        assert_eq!(despawned.iter().collect::<Vec<_>>(), vec![ent1]);
+        let despawned: ParamGuard<_, Despawned<Component1>> = query_world.parameter();
+        assert!(despawned.is_empty());
+        let despawned: ParamGuard<_, Despawned<Component3>> = query_world.parameter();
+        assert!(despawned.is_empty());
+    }
+
+    #[test]
+    fn changed_filter() {
+        let mut world = World::<ComponentStorage>::new();
+        let ent1 = world.spawn((Component1(0), Component2(1)));
+        let ent2 = world.spawn((Component1(2), Component2(3)));
+        world.next_cycle();
+        world.next_cycle();
+
+        {
+            let query_world = world.query_world();
+            let mut query: ParamGuard<_, Query<EntityID, Changed<Component1>>> =
+                query_world.parameter();
+            assert_eq!(query.iter().count(), 0);
+        }
+        world.get_mut::<Component1>(ent1).unwrap().0 = 5;
+        {
+            // Changes of the current cycle aren't visible yet.
+            let query_world = world.query_world();
+            let mut query: ParamGuard<_, Query<EntityID, Changed<Component1>>> =
+                query_world.parameter();
+            assert_eq!(query.iter().count(), 0);
+        }
+        world.next_cycle();
+        {
+            let query_world = world.query_world();
+            let mut query: ParamGuard<_, Query<EntityID, Changed<Component1>>> =
+                query_world.parameter();
+            assert_eq!(query.iter().collect::<Vec<_>>(), vec![ent1]);
+            assert_eq!(query.get(ent1), Some(ent1));
+            assert_eq!(query.get(ent2), None);
+            drop(query);
+            let mut query: ParamGuard<_, Query<&mut Component2, Changed<Component1>>> =
+                query_world.parameter();
+            for c2 in query.iter() {
+                c2.0 = 10;
+            }
+        }
+        world.next_cycle();
+
+        // Each change is only seen during the cycle after it was made.
+        let query_world = world.query_world();
+        let mut query: ParamGuard<_, Query<EntityID, Changed<Component1>>> =
+            query_world.parameter();
+        assert_eq!(query.iter().count(), 0);
+        let mut query: ParamGuard<_, Query<EntityID, Changed<Component2>>> =
+            query_world.parameter();
+        assert_eq!(query.iter().collect::<Vec<_>>(), vec![ent1]);
+    }
+
+    #[test]
+    fn parallel_executor() {
+        fn increment<'a>(mut query: Query<'a, &'a mut Component1>) {
+            for c1 in query.iter() {
+                c1.0 += 1;
+            }
+        }
+        fn sum<'a>(mut query: Query<'a, &'a Component2>, res: &mut Resource1) {
+            res.0 = query.iter().map(|c2| c2.0).sum();
+        }
+        fn copy<'a>(mut query: Query<'a, (&'a Component1, &'a mut Component3)>, res: &Resource1) {
+            for (c1, c3) in query.iter() {
+                c3.0 = c1.0 as u16 + res.0 as u16;
+            }
+        }
+        fn spawn(commands: Commands) {
+            commands.submit(|world| {
+                world.spawn(Component2(100));
+            });
+        }
+
+        let mut world = World::<ComponentStorage>::new();
+        let ent1 = world.spawn((Component1(0), Component2(1), Component3(2)));
+        world.spawn((Component2(3),));
+
+        let mut schedule = Schedule::new()
+            .with_system(system!(increment))
+            .with_system(system!(sum))
+            .with_system(system!(copy))
+            .with_system(system!(spawn));
+        assert_eq!(schedule.stage_count(), Ok(2));
+
+        schedule.run_parallel(&mut world);
+        assert_eq!(world.resource::<Resource1>().0, 4);
+        assert_eq!(world.get::<Component1>(ent1), Some(&Component1(1)));
+        assert_eq!(world.get::<Component3>(ent1), Some(&Component3(5)));
+        assert_eq!(world.entity_count(), 3);
+
+        schedule.run_parallel(&mut world);
+        assert_eq!(world.resource::<Resource1>().0, 104);
+        assert_eq!(world.get::<Component3>(ent1), Some(&Component3(106)));
+        assert_eq!(world.entity_count(), 4);
+    }
+
+    #[test]
+    fn schedule_order() {
+        fn a() {}
+        fn b() {}
+        fn c() {}
+
+        let mut schedule = Schedule::<ComponentStorage>::new()
+            .with_system(system!(a).after("c"))
+            .with_system(system!(b).label("b_label"))
+            .with_system(system!(c).after("b_label"));
+        assert_eq!(schedule.order(), Ok(vec!["b", "c", "a"]));
+
+        schedule.add_system(system!(b).label("b_label").after("a"));
+        assert_eq!(
+            schedule.order(),
+            Err(ScheduleError::Cycle(vec!["a", "c", "b"]))
+        );
+
+        let mut schedule = Schedule::<ComponentStorage>::new().with_system(system!(a).after("d"));
+        assert_eq!(
+            schedule.order(),
+            Err(ScheduleError::UnknownLabel {
+                system: "a",
+                label: "d"
+            })
+        );
+    }
+
+    #[test]
+    fn schedule_run_if() {
+        fn set(res: &mut Resource1) {
+            res.0 += 1;
+        }
+        fn count<'a>(mut query: Query<'a, &'a mut Component1>) {
+            for c1 in query.iter() {
+                c1.0 += 1;
+            }
+        }
+
+        let mut world = World::<ComponentStorage>::new();
+        let ent = world.spawn(Component1(0));
+        let mut schedule = Schedule::new()
+            .with_system(system!(count).run_if(system!(resource_changed::<Resource1, _>)))
+            .with_system(system!(set).run_if(system!(|res: &Resource1| res.0 < 2)));
+
+        world.next_cycle();
+        schedule.run(&mut world);
+        world.next_cycle();
+        schedule.run(&mut world);
+        world.next_cycle();
+        schedule.run(&mut world);
+        world.next_cycle();
+        schedule.run(&mut world);
+
+        assert_eq!(world.resource::<Resource1>().0, 2);
+        assert_eq!(world.get::<Component1>(ent), Some(&Component1(2)));
+    }
+
+    #[test]
+    fn events() {
+        fn write(mut writer: EventWriter<Event1>, res: &Resource1) {
+            writer.send(Event1(res.0));
+        }
+        fn sum(mut reader: EventReader<Event1>, res: &mut Resource1) {
+            res.0 += reader.read().map(|ev| ev.0).sum::<u32>();
+        }
+        fn count<'a>(mut reader: EventReader<Event1>, mut query: Query<'a, &'a mut Component2>) {
+            let count = reader.read().count() as u32;
+            for c2 in query.iter() {
+                c2.0 += count;
+            }
+        }
+
+        let mut world = World::<ComponentStorage>::new();
+        let ent = world.spawn(Component2(0));
+        world.resource_mut::<Resource1>().0 = 1;
+        world.send_event(Event1(10));
+        let mut schedule = Schedule::new()
+            .with_system(system!(sum))
+            .with_system(system!(write))
+            .with_system(system!(count));
+
+        schedule.run(&mut world);
+        // sum only sees the event sent from outside, count sees both.
+        assert_eq!(world.resource::<Resource1>().0, 11);
+        assert_eq!(world.get::<Component2>(ent), Some(&Component2(2)));
+
+        world.next_cycle();
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<Resource1>().0, 22);
+        assert_eq!(world.get::<Component2>(ent), Some(&Component2(3)));
+
+        world.next_cycle();
+        assert_eq!(world.events::<Event1>().len(), 1);
+        world.next_cycle();
+        assert!(world.events::<Event1>().is_empty());
+        let query_world = world.query_world();
+        let mut reader: ParamGuard<_, EventReader<Event1>> = query_world.parameter();
+        assert!(reader.is_empty());
+        assert_eq!(reader.read().count(), 0);
+    }
+
     #[test]
     fn command() {
         let mut world = World::<ComponentStorage>::new();
@@ -315,4 +1334,434 @@ mod tests {
 
         assert_eq!(world.entity_count(), 1);
     }
+
+    #[test]
+    fn typed_commands() {
+        let mut world = World::<ComponentStorage>::new();
+        let parent = world.spawn((Component1(0), Component2(0)));
+        let mut replayed = world.clone();
+
+        let recorded = {
+            let query_world = world.query_world();
+            let commands = query_world.parameter::<Commands>();
+            let child = commands.spawn((Component1(1), Component2(2)));
+            assert_ne!(child, parent);
+            commands.set_parent(child, parent);
+            commands.insert(parent, Component3(3));
+            commands.remove::<Component1>(parent);
+            commands.insert_resource(Resource1(5));
+            drop(commands);
+            query_world.drain_commands()
+        };
+        assert_eq!(world.entity_count(), 1);
+        assert!(format!("{:?}", recorded[0]).contains("Component2"));
+
+        let serialized = bincode::serialize(&recorded).unwrap();
+        for command in recorded {
+            world.apply_command(command);
+        }
+        let deserialized: Vec<Command> = bincode::deserialize(&serialized).unwrap();
+        for command in deserialized {
+            replayed.apply_command(command);
+        }
+        // The child is already spawned, so spawning it again does nothing.
+        let mut again: Vec<Command> = bincode::deserialize(&serialized).unwrap();
+        world.apply_command(again.remove(0));
+
+        for world in [world, replayed] {
+            assert_eq!(world.entity_count(), 2);
+            let child = world.children(parent)[0];
+            assert_eq!(world.get::<Component2>(child), Some(&Component2(2)));
+            assert_eq!(world.get::<Component1>(parent), None);
+            assert_eq!(world.get::<Component3>(parent), Some(&Component3(3)));
+            assert_eq!(world.resource::<Resource1>().0, 5);
+        }
+    }
+
+    #[test]
+    fn commands_reserve_entities() {
+        let mut world = World::<ComponentStorage>::new();
+        let ent1 = world.spawn(Component1(0));
+        let ent2 = {
+            let query_world = world.query_world();
+            let commands = query_world.parameter::<Commands>();
+            let ent2 = commands.spawn(Component1(1));
+            let ent3 = commands.spawn(Component1(2));
+            commands.despawn(ent3);
+            commands.despawn(ent1);
+            let mut query: ParamGuard<_, Query<&Component1>> = query_world.parameter();
+            assert_eq!(query.get(ent2), None);
+            assert_eq!(query.iter().count(), 1);
+            ent2
+        };
+        assert_eq!(world.entity_count(), 1);
+        assert_eq!(world.get::<Component1>(ent1), None);
+        assert_eq!(world.get::<Component1>(ent2), Some(&Component1(1)));
+
+        let ent4 = world.spawn(Component1(3));
+        assert!(world.despawn(ent4));
+        assert!(!world.despawn(ent4));
+    }
+
+    /// Next version of `ComponentStorage`: `Component1` is renamed, `Component2` and `Resource2` are removed.
+    mod v2 {
+        use super::*;
+        use engine_ecs::Migrations;
+
+        #[derive(Clone, Serialize, Deserialize, PartialEq, Eq, Debug)]
+        pub struct Renamed1(pub u8);
+        #[derive(Default, Clone, Serialize, Deserialize, PartialEq, Eq, Debug)]
+        pub struct Resource3(pub u32);
+
+        fn migrations() -> Migrations<ComponentStorage> {
+            Migrations::new()
+                .rename_component("Component1", "Renamed1")
+                .step(|world| world.resource_mut::<Resource1>().0 += 100)
+        }
+
+        gen_storage_for_world! {
+            : components
+                Component3 Renamed1
+            : resources
+                Resource3 Resource1
+            : events
+                Event1
+            : migrations
+                migrations
+        }
+    }
+
+    #[test]
+    fn load_older_schema() {
+        let mut world = World::<ComponentStorage>::new();
+        let ent1 = world.spawn((Component1(1), Component2(2), Component3(3)));
+        let ent2 = world.spawn((Component1(4), Component3(5)));
+        let ent3 = world.spawn(Component2(6));
+        world.set_parent(ent2, ent1);
+        world.resource_mut::<Resource1>().0 = 7;
+        world.insert_resource(Resource2(8));
+        world.send_event(Event1(9));
+
+        let saved = ron::to_string(&world).unwrap();
+        let mut loaded: World<v2::ComponentStorage> = ron::from_str(&saved).unwrap();
+        assert_eq!(loaded.entity_count(), 3);
+        assert_eq!(loaded.get::<v2::Renamed1>(ent1), Some(&v2::Renamed1(1)));
+        assert_eq!(loaded.get::<Component3>(ent2), Some(&Component3(5)));
+        assert!(loaded
+            .entity(ent3)
+            .unwrap()
+            .component_types()
+            .next()
+            .is_none());
+        assert_eq!(loaded.children(ent1), &[ent2]);
+        assert_eq!(loaded.resource::<Resource1>().0, 107);
+        assert_eq!(loaded.resource::<v2::Resource3>().0, 0);
+        assert_eq!(loaded.events::<Event1>().len(), 1);
+
+        // Both archetypes lost `Component2`, and now have the same components.
+        let ent4 = loaded.spawn((v2::Renamed1(10), Component3(11)));
+        {
+            let query_world = loaded.query_world();
+            let mut query: ParamGuard<_, v2::Query<(&v2::Renamed1, &Component3)>> =
+                query_world.parameter();
+            assert_eq!(query.iter().count(), 3);
+        }
+        assert_eq!(loaded.get::<v2::Renamed1>(ent4), Some(&v2::Renamed1(10)));
+
+        // Binary formats can't skip data of removed types.
+        let saved = bincode::serialize(&world).unwrap();
+        assert!(bincode::deserialize::<World<v2::ComponentStorage>>(&saved).is_err());
+        // Saves from a newer schema are rejected.
+        let saved = ron::to_string(&loaded).unwrap();
+        assert!(ron::from_str::<World<ComponentStorage>>(&saved).is_err());
+    }
+
+    mod scratch {
+        use super::*;
+
+        gen_storage_for_world! {
+            : components
+                Component1 transient Component2
+            : resources
+                transient Resource1
+        }
+    }
+
+    #[test]
+    fn transient_types() {
+        let mut world = World::<scratch::ComponentStorage>::new();
+        let ent = world.spawn((Component1(1), Component2(2)));
+        world.resource_mut::<Resource1>().0 = 3;
+
+        let saved = bincode::serialize(&world).unwrap();
+        let loaded: World<scratch::ComponentStorage> = bincode::deserialize(&saved).unwrap();
+        assert_eq!(loaded.get::<Component1>(ent), Some(&Component1(1)));
+        assert_eq!(loaded.get::<Component2>(ent), Some(&Component2(0)));
+        assert_eq!(loaded.resource::<Resource1>().0, 0);
+
+        // Data saved before types were marked as transient is discarded.
+        let mut world = World::<ComponentStorage>::new();
+        let ent = world.spawn((Component1(1), Component2(2)));
+        world.resource_mut::<Resource1>().0 = 3;
+        let saved = ron::to_string(&world).unwrap();
+        let loaded: World<scratch::ComponentStorage> = ron::from_str(&saved).unwrap();
+        assert_eq!(loaded.get::<Component2>(ent), Some(&Component2(0)));
+        assert_eq!(loaded.resource::<Resource1>().0, 0);
+    }
+
+    mod indexed {
+        use super::*;
+        use engine_ecs::IndexedBy;
+
+        impl IndexedBy<u8> for Component1 {
+            fn key(&self) -> u8 {
+                self.0
+            }
+        }
+
+        gen_storage_for_world! {
+            : components
+                Component1 Component2
+            : indexes
+                Component1 u8
+        }
+    }
+
+    #[test]
+    fn component_index() {
+        let mut world = World::<indexed::ComponentStorage>::new();
+        let ent1 = world.spawn(Component1(1));
+        let ent2 = world.spawn((Component1(1), Component2(2)));
+        let ent3 = world.spawn(Component1(3));
+        let index = world.index::<Component1, u8>();
+        let mut ones = index.get(&1).to_vec();
+        ones.sort();
+        let mut expected = vec![ent1, ent2];
+        expected.sort();
+        assert_eq!(ones, expected);
+        assert_eq!(index.get(&3), &[ent3]);
+        assert_eq!(index.key_of(ent3), Some(&3));
+
+        world.get_mut::<Component1>(ent1).unwrap().0 = 3;
+        world.despawn(ent3);
+        world.remove::<Component1>(ent2);
+        {
+            let query_world = world.query_world();
+            let index = query_world.parameter::<indexed::Index<Component1, u8>>();
+            assert_eq!(index.get(&3), &[ent1]);
+            assert!(!index.contains_key(&1));
+            assert_eq!(index.key_of(ent2), None);
+        }
+
+        // Changes are picked up even if the index wasn't used for a few cycles.
+        world.next_cycle();
+        world.get_mut::<Component1>(ent1).unwrap().0 = 5;
+        world.next_cycle();
+        world.next_cycle();
+        assert_eq!(world.index::<Component1, u8>().get(&5), &[ent1]);
+
+        // Added components are indexed by hooks, without looking at storages.
+        let ent4 = world.spawn(Component1(7));
+        let ent5 = world.spawn(Component2(0));
+        world.insert(ent5, Component1(7));
+        let mut sevens = world.index::<Component1, u8>().get(&7).to_vec();
+        sevens.sort();
+        let mut expected = vec![ent4, ent5];
+        expected.sort();
+        assert_eq!(sevens, expected);
+        world.despawn(ent4);
+        world.despawn(ent5);
+
+        // Index is not saved, and is rebuilt on first use.
+        let saved = bincode::serialize(&world).unwrap();
+        let mut loaded: World<indexed::ComponentStorage> = bincode::deserialize(&saved).unwrap();
+        assert_eq!(loaded.index::<Component1, u8>().get(&5), &[ent1]);
+    }
+
+    #[derive(Default, Clone, Serialize, Deserialize, PartialEq, Eq, Debug)]
+    struct Tag;
+
+    mod sparse {
+        use super::*;
+
+        gen_storage_for_world! {
+            : components
+                table Component1 sparse Component2 sparse Tag
+        }
+    }
+
+    #[test]
+    fn sparse_components() {
+        use sparse::{AnyOf, Changed, Query, With, Without};
+        type BothComponents<'a> = Query<'a, (EntityID, &'a Component1, &'a mut Component2)>;
+        type WithoutTag<'a> = Query<'a, (EntityID, Option<&'a Component2>), Without<Tag>>;
+        type TaggedAnyOf = (With<Tag>, AnyOf<(Component1, Tag)>);
+
+        let mut world = World::<sparse::ComponentStorage>::new();
+        let ent1 = world.spawn((Component1(1), Component2(2)));
+        let ent2 = world.spawn(Component1(3));
+        let ent3 = world.spawn((Component2(4), Tag));
+
+        // Sparse components don't move entities between archetypes.
+        let generation = world.archetype_generation();
+        world.insert(ent2, (Component2(5), Tag));
+        world.remove::<Tag>(ent2);
+        world.insert(ent1, Tag);
+        assert_eq!(world.archetype_generation(), generation);
+        assert_eq!(world.get::<Component2>(ent2), Some(&Component2(5)));
+        assert!(world.entity(ent1).unwrap().contains::<Tag>());
+        assert!(!world.entity(ent2).unwrap().contains::<Tag>());
+
+        let sorted = |mut items: Vec<EntityID>| {
+            items.sort();
+            items
+        };
+        {
+            let query_world = world.query_world();
+            let mut query: ParamGuard<_, BothComponents> = query_world.parameter();
+            for (_ent, component1, component2) in query.iter() {
+                component2.0 += component1.0 as u32;
+            }
+            let items = query.iter().map(|(ent, _, _)| ent).collect();
+            assert_eq!(sorted(items), sorted(vec![ent1, ent2]));
+            assert!(query.get(ent3).is_none());
+        }
+        {
+            let query_world = world.query_world();
+            let mut query: ParamGuard<_, WithoutTag> = query_world.parameter();
+            assert_eq!(
+                query.iter().collect::<Vec<_>>(),
+                vec![(ent2, Some(&Component2(8)))]
+            );
+            // Driven by the sparse set of Component2, still filtered by limits.
+            let mut query: ParamGuard<_, Query<&Component2, Without<Tag>>> =
+                query_world.parameter();
+            assert_eq!(query.iter().collect::<Vec<_>>(), vec![&Component2(8)]);
+            let mut query: ParamGuard<_, Query<EntityID, TaggedAnyOf>> = query_world.parameter();
+            assert_eq!(sorted(query.iter().collect()), sorted(vec![ent1, ent3]));
+            let mut query: ParamGuard<_, Query<&Component1, With<Tag>>> = query_world.parameter();
+            let sum = AtomicUsize::new(0);
+            query.for_each_par(|component| {
+                sum.fetch_add(component.0 as usize, Ordering::Relaxed);
+            });
+            assert_eq!(sum.into_inner(), 1);
+        }
+
+        world.next_cycle();
+        world.next_cycle();
+        world.get_mut::<Component2>(ent3).unwrap().0 = 6;
+        world.next_cycle();
+        {
+            let query_world = world.query_world();
+            let mut query: ParamGuard<_, Query<EntityID, Changed<Component2>>> =
+                query_world.parameter();
+            assert_eq!(query.iter().collect::<Vec<_>>(), vec![ent3]);
+        }
+
+        world.despawn(ent3);
+        let saved = bincode::serialize(&world).unwrap();
+        let loaded: World<sparse::ComponentStorage> = bincode::deserialize(&saved).unwrap();
+        assert_eq!(loaded.get::<Component2>(ent1), Some(&Component2(3)));
+        assert_eq!(loaded.get::<Component2>(ent3), None);
+        assert!(loaded.entity(ent1).unwrap().contains::<Tag>());
+        assert_eq!(
+            loaded.entity(ent2).unwrap().component_types().count(),
+            2,
+            "Component1 and Component2"
+        );
+    }
+
+    #[test]
+    fn sparse_mutable_queries_dont_run_in_parallel() {
+        use sparse::{Query, With, Without};
+        static RUNNING: AtomicUsize = AtomicUsize::new(0);
+        static OVERLAPS: AtomicUsize = AtomicUsize::new(0);
+        fn increment<'a>(components: impl Iterator<Item = &'a mut Component2>) {
+            if RUNNING.fetch_add(1, Ordering::SeqCst) != 0 {
+                OVERLAPS.fetch_add(1, Ordering::SeqCst);
+            }
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            for component in components {
+                component.0 += 1;
+            }
+            RUNNING.fetch_sub(1, Ordering::SeqCst);
+        }
+        // Disjoint by archetype, but entities of both keep Component2 in the same sparse set.
+        fn with<'a>(mut query: Query<'a, &'a mut Component2, With<Component1>>) {
+            increment(query.iter());
+        }
+        fn without<'a>(mut query: Query<'a, &'a mut Component2, Without<Component1>>) {
+            increment(query.iter());
+        }
+
+        let mut world = World::<sparse::ComponentStorage>::new();
+        let ent1 = world.spawn((Component1(0), Component2(0)));
+        let ent2 = world.spawn(Component2(10));
+        assert!(system!(with).conflicts_with(&system!(without)));
+        let mut schedule = Schedule::new()
+            .with_system(system!(with))
+            .with_system(system!(without));
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+        for _ in 0..3 {
+            pool.install(|| schedule.run_parallel(&mut world));
+        }
+        assert_eq!(OVERLAPS.load(Ordering::SeqCst), 0);
+        assert_eq!(world.get::<Component2>(ent1), Some(&Component2(3)));
+        assert_eq!(world.get::<Component2>(ent2), Some(&Component2(13)));
+    }
+
+    #[test]
+    fn spawn_batch() {
+        let mut world = World::<ComponentStorage>::new();
+        world.reserve::<(Component1, Component2)>(100);
+        let generation = world.archetype_generation();
+        let ents = world.spawn_batch((0..100).map(|i| (Component1(i), Component2(i as u32 * 2))));
+        assert_eq!(world.archetype_generation(), generation);
+        assert_eq!(ents.len(), 100);
+        assert_eq!(world.entity_count(), 100);
+        for (i, &ent) in ents.iter().enumerate() {
+            assert_eq!(world.get::<Component1>(ent), Some(&Component1(i as u8)));
+            assert_eq!(
+                world.get::<Component2>(ent),
+                Some(&Component2(i as u32 * 2))
+            );
+        }
+
+        // Batches mix with entities spawned one by one.
+        let single = world.spawn((Component1(7), Component2(8)));
+        let more = world.spawn_batch(vec![Component3(1), Component3(2)]);
+        world.despawn(ents[0]);
+        {
+            let query_world = world.query_world();
+            let mut query: ParamGuard<_, Query<&Component1>> = query_world.parameter();
+            assert_eq!(query.iter().count(), 100);
+            assert_eq!(query.get(single), Some(&Component1(7)));
+            let mut query: ParamGuard<_, Query<&Component3>> = query_world.parameter();
+            assert_eq!(query.get(more[1]), Some(&Component3(2)));
+        }
+
+        // Hooks run for every entity of a batch, and entities they spawn get IDs of their own.
+        world.on_add::<Component2>(|entity, commands| {
+            let value = entity.get::<Component2>().unwrap().0;
+            commands.spawn(Component3(value as u16));
+        });
+        let bundles = (0..3).map(|i| OuterBundle {
+            c1: Component1(i),
+            inner: InnerBundle {
+                c2: Component2(i as u32 + 10),
+                c3: Component3(i.into()),
+            },
+        });
+        let batch = world.spawn_batch(bundles);
+        assert_eq!(world.get::<Component2>(batch[2]), Some(&Component2(12)));
+        let query_world = world.query_world();
+        let mut query: ParamGuard<_, Query<&Component3, Without<Component1>>> =
+            query_world.parameter();
+        let mut spawned_by_hooks: Vec<_> = query.iter().map(|c| c.0).collect();
+        spawned_by_hooks.sort();
+        assert_eq!(spawned_by_hooks, [1, 2, 10, 11, 12]);
+    }
 }
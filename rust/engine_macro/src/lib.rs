@@ -1,12 +1,20 @@
-use std::iter;
+use std::{collections::HashSet, iter, mem};
 
 use proc_macro::TokenStream;
 use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, parse_quote, Data, DeriveInput, Fields, Generics, Ident, Lifetime, Type,
+};
 
 enum GenState {
     None,
     Components,
     Resources,
+    OptionalResources,
+    Events,
+    Migrations,
+    Hooks,
+    Indexes,
 }
 
 #[proc_macro]
@@ -14,6 +22,19 @@ pub fn gen_storage_for_world(input: TokenStream) -> TokenStream {
     let mut gen_state = GenState::None;
     let mut component_names = Vec::new();
     let mut resource_names = Vec::new();
+    let mut optional_resource_names = Vec::new();
+    let mut event_names = Vec::new();
+    let mut migrations_fn = None;
+    let mut hooks_fn = None;
+    // Pairs of component and key types, see `ComponentIndex`.
+    let mut index_entries = Vec::new();
+    let mut index_component = None;
+    // Transient types are not saved, and are defaulted on load.
+    let mut transient_names = HashSet::new();
+    let mut next_transient = false;
+    // Sparse components are stored by entity instead of in archetype tables.
+    let mut sparse_names = HashSet::new();
+    let mut next_sparse = false;
     let mut serialize_en = true;
     let mut clone_en = true;
 
@@ -26,6 +47,11 @@ pub fn gen_storage_for_world(input: TokenStream) -> TokenStream {
                 match new_mode.as_str() {
                     "components" => gen_state = GenState::Components,
                     "resources" => gen_state = GenState::Resources,
+                    "optional_resources" => gen_state = GenState::OptionalResources,
+                    "events" => gen_state = GenState::Events,
+                    "migrations" => gen_state = GenState::Migrations,
+                    "hooks" => gen_state = GenState::Hooks,
+                    "indexes" => gen_state = GenState::Indexes,
                     "no_clone" => {
                         clone_en = false;
                     }
@@ -35,23 +61,61 @@ pub fn gen_storage_for_world(input: TokenStream) -> TokenStream {
                     _ => panic!("Unknown requested state: {}", new_mode),
                 }
             }
+            "transient" => next_transient = true,
+            "sparse" => next_sparse = true,
+            // Table storage is the default, the keyword just makes it explicit.
+            "table" => next_sparse = false,
             _ => {
+                if mem::take(&mut next_transient) {
+                    transient_names.insert(token_str.clone());
+                }
+                if mem::take(&mut next_sparse) {
+                    if !matches!(gen_state, GenState::Components) {
+                        panic!("Only components can be sparse, {token_str} is not one");
+                    }
+                    if transient_names.contains(&token_str) {
+                        panic!("Sparse component {token_str} can't be transient, as entities don't remember having it");
+                    }
+                    sparse_names.insert(token_str.clone());
+                }
                 match gen_state {
-                    GenState::None => panic!("Set current mode with ': <mode>', where <mode> is one of 'components', 'resources'"),
+                    GenState::None => panic!("Set current mode with ': <mode>', where <mode> is one of 'components', 'resources', 'optional_resources', 'events', 'migrations', 'hooks', 'indexes'"),
                     GenState::Components => component_names.push(token_str),
                     GenState::Resources => resource_names.push(token_str),
+                    GenState::OptionalResources => optional_resource_names.push(token_str),
+                    GenState::Events => event_names.push(token_str),
+                    GenState::Migrations => {
+                        if migrations_fn.replace(format_ident!("{token_str}")).is_some() {
+                            panic!("Only one migrations function can be set");
+                        }
+                    }
+                    GenState::Hooks => {
+                        if hooks_fn.replace(format_ident!("{token_str}")).is_some() {
+                            panic!("Only one hooks function can be set");
+                        }
+                    }
+                    GenState::Indexes => match index_component.take() {
+                        Some(component) => index_entries.push((component, token_str)),
+                        None => index_component = Some(token_str),
+                    },
                 }
             }
         }
     }
+    if let Some(component) = index_component {
+        panic!("Index of {component} needs a key type");
+    }
     // for token in input {
     //     component_names.push(token.to_string());
     // }
 
-    let component_storage_names = component_names
-        .iter()
-        .enumerate()
-        .map(|(i, _c)| format_ident!("component_storage_{}", i))
+    // Optional resources are absent until inserted, so they don't have to implement `Default`.
+    let optional_resource_count = optional_resource_names.len();
+    resource_names.extend(optional_resource_names);
+    let required_resource_count = resource_names.len() - optional_resource_count;
+
+    let component_storage_names = (0..component_names.len() + 2)
+        .map(|i| format_ident!("component_storage_{}", i))
         .collect::<Vec<_>>();
 
     let resource_storage_names = resource_names
@@ -60,10 +124,47 @@ pub fn gen_storage_for_world(input: TokenStream) -> TokenStream {
         .map(|(i, _c)| format_ident!("resource_storage_{}", i))
         .collect::<Vec<_>>();
 
-    let component_types = component_names
+    let event_storage_names = event_names
+        .iter()
+        .enumerate()
+        .map(|(i, _c)| format_ident!("event_storage_{}", i))
+        .collect::<Vec<_>>();
+
+    let index_storage_names = (0..index_entries.len())
+        .map(|i| format_ident!("index_storage_{}", i))
+        .collect::<Vec<_>>();
+    let index_component_types = index_entries
+        .iter()
+        .map(|(component, _key)| format_ident!("{component}"))
+        .collect::<Vec<_>>();
+    let index_key_types = index_entries
+        .iter()
+        .map(|(_component, key)| syn::parse_str::<Type>(key).expect("index key should be a type"))
+        .collect::<Vec<_>>();
+
+    let event_types = event_names
+        .iter()
+        .map(|c| format_ident!("{}", c))
+        .collect::<Vec<_>>();
+
+    let user_component_types = component_names
         .iter()
         .map(|c| format_ident!("{c}"))
         .collect::<Vec<_>>();
+    // Built-in components take the last indices, see `DynDispath::COMPONENT_TYPES`.
+    let builtin_component_types = [quote!(::engine_ecs::Parent), quote!(::engine_ecs::Children)];
+    let component_types = user_component_types
+        .iter()
+        .map(|c| quote!(#c))
+        .chain(builtin_component_types.iter().cloned())
+        .collect::<Vec<_>>();
+    let component_variants = user_component_types
+        .iter()
+        .cloned()
+        .chain([format_ident!("Parent"), format_ident!("Children")])
+        .collect::<Vec<_>>();
+    // Hierarchy can only be changed through `World`, so that it stays consistent.
+    let mutable_component_types = &component_types[..user_component_types.len()];
 
     let resource_types = resource_names
         .iter()
@@ -77,12 +178,17 @@ pub fn gen_storage_for_world(input: TokenStream) -> TokenStream {
 
     let counter = iter::successors(Some(0u32), |x| Some(x + 1));
     let counter2 = iter::successors(Some(0u32), |x| Some(x + 1));
-    let counter3 = iter::successors(Some(0u32), |x| Some(x + 1));
 
     let counter_resources = 0..(resource_names.len() as u32);
     let counter_resources_2 = 0..(resource_names.len() as u32);
 
-    let resource_type_count = resource_names.len() as u32;
+    let component_type_count = component_types.len() as u32;
+    // Event queues are resources too, placed after the regular ones.
+    let counter_events = (resource_names.len() as u32)..;
+    // So are indexes, placed after event queues.
+    let counter_indexes = ((resource_names.len() + event_names.len()) as u32)..;
+    let resource_type_count =
+        (resource_names.len() + event_names.len() + index_entries.len()) as u32;
 
     let serialize_derives = if serialize_en {
         quote!(
@@ -91,9 +197,205 @@ pub fn gen_storage_for_world(input: TokenStream) -> TokenStream {
     } else {
         quote!()
     };
-    let serialize_hints = if serialize_en {
+    let resource_defaults = (0..resource_names.len()).map(|i| {
+        if i < required_resource_count {
+            quote!(::std::default::Default::default())
+        } else {
+            quote!(::engine_ecs::internal::ResourceStorage::empty())
+        }
+    });
+
+    // Names identify types in saves, see `Migrations`.
+    let component_name_strs = component_variants
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>();
+    let resource_name_strs = resource_names
+        .iter()
+        .cloned()
+        .chain(event_names.iter().map(|e| format!("Events<{e}>")))
+        .chain(index_entries.iter().map(|(component, key)| {
+            let key = key.split_whitespace().collect::<String>();
+            format!("Index<{component},{key}>")
+        }))
+        .collect::<Vec<_>>();
+    let resource_fields = resource_storage_names
+        .iter()
+        .chain(event_storage_names.iter())
+        .chain(index_storage_names.iter())
+        .collect::<Vec<_>>();
+    let resource_transient = resource_names
+        .iter()
+        .chain(event_names.iter())
+        .map(|r| transient_names.contains(r))
+        // Indexes are rebuilt on first use.
+        .chain(index_entries.iter().map(|_index| true))
+        .collect::<Vec<_>>();
+    let component_transient = component_variants
+        .iter()
+        .map(|c| transient_names.contains(&c.to_string()))
+        .collect::<Vec<_>>();
+    let component_sparse = component_variants
+        .iter()
+        .map(|c| sparse_names.contains(&c.to_string()))
+        .collect::<Vec<_>>();
+    // Queries access table and sparse components differently, so they get separate impls.
+    let (table_component_types, table_component_indices) =
+        split_by_kind(&component_types, &component_sparse, false);
+    let (sparse_component_types, sparse_component_indices) =
+        split_by_kind(&component_types, &component_sparse, true);
+    let (table_mutable_types, table_mutable_indices) =
+        split_by_kind(mutable_component_types, &component_sparse, false);
+
+    // Transient types still have an entry, so that saved type indices can be recovered from the order of entries.
+    // Sparse components are saved as their sparse sets, which hold entities along with components.
+    let serialize_entries =
+        |names: &[String], fields: &[&Ident], transient: &[bool], sparse: &[bool]| {
+            names
+                .iter()
+                .zip(fields)
+                .zip(transient.iter().zip(sparse))
+                .map(|((name, field), (&transient, &sparse))| {
+                    if transient {
+                        quote!(map.serialize_entry(#name, &())?;)
+                    } else if sparse {
+                        quote!(map.serialize_entry(#name, self.#field.sparse())?;)
+                    } else {
+                        quote!(map.serialize_entry(#name, &self.#field)?;)
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+    let deserialize_arms = |names: &[String],
+                            fields: &[&Ident],
+                            transient: &[bool],
+                            sparse: &[bool]| {
+        names
+                .iter()
+                .zip(fields)
+                .zip(transient.iter().zip(sparse))
+                .map(|((name, field), (&transient, &sparse))| {
+                    if transient {
+                        quote!(#name => { map.next_value::<::engine_ecs::internal::Transient>()?; true })
+                    } else if sparse {
+                        quote!(#name => { *self.#field.sparse_mut() = map.next_value()?; true })
+                    } else {
+                        quote!(#name => { self.#field = map.next_value()?; true })
+                    }
+                })
+                .collect::<Vec<_>>()
+    };
+    let component_fields = component_storage_names.iter().collect::<Vec<_>>();
+    let serialize_components = serialize_entries(
+        &component_name_strs,
+        &component_fields,
+        &component_transient,
+        &component_sparse,
+    );
+    let resource_sparse = vec![false; resource_fields.len()];
+    let serialize_resources = serialize_entries(
+        &resource_name_strs,
+        &resource_fields,
+        &resource_transient,
+        &resource_sparse,
+    );
+    let deserialize_components = deserialize_arms(
+        &component_name_strs,
+        &component_fields,
+        &component_transient,
+        &component_sparse,
+    );
+    let deserialize_resources = deserialize_arms(
+        &resource_name_strs,
+        &resource_fields,
+        &resource_transient,
+        &resource_sparse,
+    );
+    let transient_component_types = component_types
+        .iter()
+        .zip(&component_transient)
+        .filter(|(_c, &transient)| transient)
+        .map(|(c, _transient)| c)
+        .collect::<Vec<_>>();
+    let transient_component_fields = component_storage_names
+        .iter()
+        .zip(&component_transient)
+        .filter(|(_c, &transient)| transient)
+        .map(|(c, _transient)| c)
+        .collect::<Vec<_>>();
+
+    // Commands carrying transient types can't be serialized either.
+    let skip_transient = |transient: &bool| {
+        if serialize_en && *transient {
+            quote!(#[serde(skip)])
+        } else {
+            quote!()
+        }
+    };
+    let component_variant_attrs = component_transient
+        .iter()
+        .map(skip_transient)
+        .collect::<Vec<_>>();
+    let resource_variant_attrs = resource_transient[..resource_names.len()]
+        .iter()
+        .map(skip_transient)
+        .collect::<Vec<_>>();
+
+    let migrations = match migrations_fn {
+        Some(migrations_fn) => quote!(#migrations_fn()),
+        None => quote!(::engine_ecs::Migrations::new()),
+    };
+    let hooks = match hooks_fn {
+        Some(hooks_fn) => quote!(#hooks_fn()),
+        None => quote!(::engine_ecs::Hooks::new()),
+    };
+    let hooks = quote!(
+        let hooks = #hooks;
+        #( let hooks = ::engine_ecs::internal::index_hooks::<Self, #index_component_types, #index_key_types>(hooks); )*
+        hooks
+    );
+    let storage_serde = if serialize_en {
         quote!(
-            #[serde(default)]
+            impl ::engine_ecs::internal::StorageSerde for ComponentStorage {
+                fn migrations() -> ::engine_ecs::Migrations<Self> {
+                    #migrations
+                }
+
+                fn serialize_components<M: ::serde::ser::SerializeMap>(&self, map: &mut M) -> Result<(), M::Error> {
+                    #( #serialize_components )*
+                    Ok(())
+                }
+
+                fn serialize_resources<M: ::serde::ser::SerializeMap>(&self, map: &mut M) -> Result<(), M::Error> {
+                    #( #serialize_resources )*
+                    Ok(())
+                }
+
+                fn deserialize_component<'de, A: ::serde::de::MapAccess<'de>>(&mut self, name: &str, map: &mut A) -> Result<bool, A::Error> {
+                    Ok(match name {
+                        #( #deserialize_components )*
+                        _ => false,
+                    })
+                }
+
+                fn deserialize_resource<'de, A: ::serde::de::MapAccess<'de>>(&mut self, name: &str, map: &mut A) -> Result<bool, A::Error> {
+                    Ok(match name {
+                        #( #deserialize_resources )*
+                        _ => false,
+                    })
+                }
+
+                fn fill_transient(&mut self, type_index: ::engine_ecs::TypeIndex, storage: ::engine_ecs::StorageID, len: ::engine_ecs::internal::InArchetypeID) {
+                    match type_index {
+                        #(
+                            <#transient_component_types as ::engine_ecs::LocalTypeIndex<ComponentStorage>>::TYPE_INDEX => {
+                                self.#transient_component_fields.fill_default(storage, len)
+                            }
+                        )*
+                        _ => {}
+                    }
+                }
+            }
         )
     } else {
         quote!()
@@ -108,21 +410,39 @@ pub fn gen_storage_for_world(input: TokenStream) -> TokenStream {
     };
 
     quote!(
-        #[derive(Default)]
         #clone_derives
-        #serialize_derives
         pub struct ComponentStorage {
             #( #component_storages ,)*
             #(
-                #serialize_hints
                 #resource_storage_names: ::engine_ecs::internal::ResourceStorage<#resource_types>,
             )*
+            #(
+                #event_storage_names: ::engine_ecs::internal::ResourceStorage<::engine_ecs::Events<#event_types>>,
+            )*
+            #(
+                #index_storage_names: ::engine_ecs::internal::ResourceStorage<::engine_ecs::ComponentIndex<#index_component_types, #index_key_types>>,
+            )*
+        }
+
+        #storage_serde
+
+        impl Default for ComponentStorage {
+            fn default() -> Self {
+                Self {
+                    #( #component_storage_names: ::std::default::Default::default(), )*
+                    #( #resource_storage_names: #resource_defaults, )*
+                    #( #event_storage_names: ::std::default::Default::default(), )*
+                    #( #index_storage_names: ::std::default::Default::default(), )*
+                }
+            }
         }
         #(
-            impl ::engine_ecs::LocalTypeIndex<ComponentStorage> for #component_types {
+            impl ::engine_ecs::LocalTypeIndex<ComponentStorage> for #user_component_types {
                 const TYPE_INDEX: u32 = #counter;
             }
+        )*
 
+        #(
             impl ::engine_ecs::internal::ComponentStorageProvider<#component_types> for ComponentStorage {
                 fn storage(&self) -> & ::engine_ecs::internal::ComponentList<#component_types> {
                     & self.#component_storage_names
@@ -148,9 +468,125 @@ pub fn gen_storage_for_world(input: TokenStream) -> TokenStream {
             }
         )*
 
+        #(
+            impl ::engine_ecs::internal::EventStorageProvider<#event_types> for ComponentStorage {
+                const EVENTS_INDEX: u32 = #counter_events;
+
+                fn events(&self) -> & ::engine_ecs::internal::ResourceStorage<::engine_ecs::Events<#event_types>> {
+                    & self.#event_storage_names
+                }
+                fn events_mut(&mut self) -> &mut ::engine_ecs::internal::ResourceStorage<::engine_ecs::Events<#event_types>> {
+                    &mut self.#event_storage_names
+                }
+            }
+        )*
+
+        #(
+            impl ::engine_ecs::internal::IndexStorageProvider<#index_component_types, #index_key_types> for ComponentStorage {
+                const INDEX_INDEX: u32 = #counter_indexes;
+
+                fn index(&self) -> & ::engine_ecs::internal::ResourceStorage<::engine_ecs::ComponentIndex<#index_component_types, #index_key_types>> {
+                    & self.#index_storage_names
+                }
+                fn index_mut(&mut self) -> &mut ::engine_ecs::internal::ResourceStorage<::engine_ecs::ComponentIndex<#index_component_types, #index_key_types>> {
+                    &mut self.#index_storage_names
+                }
+            }
+        )*
+
+        /// Component of any type, used by commands.
+        #serialize_derives
+        pub enum AnyComponent {
+            #( #component_variant_attrs #component_variants(#component_types), )*
+        }
+
+        #(
+            impl From<#component_types> for AnyComponent {
+                fn from(component: #component_types) -> Self {
+                    Self::#component_variants(component)
+                }
+            }
+        )*
+
+        impl ::std::fmt::Debug for AnyComponent {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                match self {
+                    #( Self::#component_variants(_) => f.write_str(stringify!(#component_variants)), )*
+                }
+            }
+        }
+
+        impl ::engine_ecs::internal::DynComponent<ComponentStorage> for AnyComponent {
+            fn type_index(&self) -> ::engine_ecs::TypeIndex {
+                match self {
+                    #( Self::#component_variants(_) => <#component_types as ::engine_ecs::LocalTypeIndex<ComponentStorage>>::TYPE_INDEX, )*
+                }
+            }
+
+            fn add_to_archetype_in_storage(self, world: &mut ::engine_ecs::World<ComponentStorage>, archetype: ::engine_ecs::ArchetypeID, entity: ::engine_ecs::EntityID) {
+                match self {
+                    #( Self::#component_variants(component) => world.add_bundle_to_archetype(archetype, entity, component), )*
+                }
+            }
+        }
+
+        /// Resource of any type, used by commands.
+        #serialize_derives
+        pub enum AnyResource {
+            #( #resource_variant_attrs #resource_types(#resource_types), )*
+        }
+
+        #(
+            impl From<#resource_types> for AnyResource {
+                fn from(resource: #resource_types) -> Self {
+                    Self::#resource_types(resource)
+                }
+            }
+        )*
+
+        impl ::std::fmt::Debug for AnyResource {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                match *self {
+                    #( Self::#resource_types(_) => f.write_str(stringify!(#resource_types)), )*
+                }
+            }
+        }
+
+        impl ::engine_ecs::internal::DynResource<ComponentStorage> for AnyResource {
+            fn insert_into(self, world: &mut ::engine_ecs::World<ComponentStorage>) {
+                match self {
+                    #( Self::#resource_types(resource) => world.insert_resource(resource), )*
+                }
+            }
+        }
+
         impl ::engine_ecs::internal::DynDispath for ComponentStorage {
+            const COMPONENT_TYPES: u32 = #component_type_count;
             const RESOURCE_TYPES: u32 = #resource_type_count;
 
+            const COMPONENT_NAMES: &'static [&'static str] = &[#( #component_name_strs, )*];
+            const RESOURCE_NAMES: &'static [&'static str] = &[#( #resource_name_strs, )*];
+            const SPARSE: &'static [bool] = &[#( #component_sparse, )*];
+            const SPARSE_TYPES: &'static [::engine_ecs::TypeIndex] = &[#( #sparse_component_indices, )*];
+
+            type AnyComponent = AnyComponent;
+            type AnyResource = AnyResource;
+
+            fn dispath<F, Ret>(&self, index: ::engine_ecs::TypeIndex, f: F) -> Ret
+            where
+                F: FnOnce(&dyn ::engine_ecs::internal::DynComponentList) -> Ret
+            {
+                match index {
+                    #(
+                        <#component_types as ::engine_ecs::LocalTypeIndex<ComponentStorage>>::TYPE_INDEX => {
+                            let storage = <Self as ::engine_ecs::internal::ComponentStorageProvider<#component_types>>::storage(self);
+                            f(storage)
+                        }
+                    ,)*
+                    _ => unreachable!()
+                }
+            }
+
             fn dispath_mut<F, Ret>(&mut self, index: ::engine_ecs::TypeIndex, f: F) -> Ret
             where
                 F: FnOnce(&mut dyn ::engine_ecs::internal::DynComponentList) -> Ret
@@ -165,23 +601,43 @@ pub fn gen_storage_for_world(input: TokenStream) -> TokenStream {
                     _ => unreachable!()
                 }
             }
+
+            fn update_events(&mut self) {
+                #( ::engine_ecs::internal::update_events::<Self, #event_types>(self); )*
+            }
+
+            fn hooks() -> ::engine_ecs::Hooks<Self> {
+                #hooks
+            }
         }
 
         #(
-            impl ::engine_ecs::Bundle<ComponentStorage> for #component_types {
+            impl ::engine_ecs::Bundle<ComponentStorage> for #user_component_types {
                 fn type_ids() -> ::engine_ecs::internal::TypeIndexStorage {
                     ::engine_ecs::internal::TypeIndexStorage::from_elem(#counter2, 1)
                 }
 
-                fn add_to_archetype_in_storage(self, world: &mut ::engine_ecs::World<ComponentStorage>, archetype: ::engine_ecs::ArchetypeID) {
-                    world.add_bundle_to_archetype(archetype, self)
+                fn add_to_archetype_in_storage(self, world: &mut ::engine_ecs::World<ComponentStorage>, archetype: ::engine_ecs::ArchetypeID, entity: ::engine_ecs::EntityID) {
+                    world.add_bundle_to_archetype(archetype, entity, self)
+                }
+
+                fn add_many_to_archetype_in_storage(bundles: Vec<Self>, world: &mut ::engine_ecs::World<ComponentStorage>, archetype: ::engine_ecs::ArchetypeID, entities: &[::engine_ecs::EntityID]) {
+                    world.add_many_to_archetype(archetype, entities, bundles)
+                }
+
+                fn into_components(self, components: &mut Vec<AnyComponent>) {
+                    components.push(AnyComponent::#user_component_types(self))
                 }
             }
+        )*
 
-            unsafe impl<'wrld> ::engine_ecs::internal::QueryParameter<'wrld, ComponentStorage> for &'wrld #component_types {
+        #(
+            impl ::engine_ecs::internal::TableComponent<ComponentStorage> for #table_component_types {}
+
+            unsafe impl<'wrld> ::engine_ecs::internal::QueryParameter<'wrld, ComponentStorage> for &'wrld #table_component_types {
                 fn add_requests(req: &mut ::engine_ecs::internal::ComponentRequests) {
-                    req.request(#counter3, false);
-                    req.require(#counter3);
+                    req.request(#table_component_indices, false);
+                    req.require(#table_component_indices);
                 }
                 unsafe fn get_from_world(
                     world: &'wrld ::engine_ecs::QueryWorld<'wrld, ComponentStorage>,
@@ -189,15 +645,32 @@ pub fn gen_storage_for_world(input: TokenStream) -> TokenStream {
                     index: ::engine_ecs::internal::InArchetypeID,
                     _ent_id: ::engine_ecs::EntityID,
                 ) -> Self {
-                    let storage = world.storage_for_archetype::<#component_types>(archetype).expect("component assumed to exist, as we've asked for it");
+                    let storage = world.storage_for_archetype::<#table_component_types>(archetype).expect("component assumed to exist, as we've asked for it");
                     world.get(storage, index).expect("component assumed to exist, as it exists in the archetype")
                 }
             }
 
-            unsafe impl<'wrld> ::engine_ecs::internal::QueryParameter<'wrld, ComponentStorage> for &'wrld mut #component_types {
+            unsafe impl<'wrld> ::engine_ecs::internal::QueryParameter<'wrld, ComponentStorage> for Option<&'wrld #table_component_types> {
+                fn add_requests(req: &mut ::engine_ecs::internal::ComponentRequests) {
+                    req.request(#table_component_indices, false);
+                }
+                unsafe fn get_from_world(
+                    world: &'wrld ::engine_ecs::QueryWorld<'wrld, ComponentStorage>,
+                    archetype: ::engine_ecs::ArchetypeID,
+                    index: ::engine_ecs::internal::InArchetypeID,
+                    _ent_id: ::engine_ecs::EntityID,
+                ) -> Self {
+                    let storage = world.storage_for_archetype::<#table_component_types>(archetype)?;
+                    Some(world.get(storage, index).expect("component assumed to exist, as it exists in the archetype"))
+                }
+            }
+        )*
+
+        #(
+            unsafe impl<'wrld> ::engine_ecs::internal::QueryParameter<'wrld, ComponentStorage> for &'wrld mut #table_mutable_types {
                 fn add_requests(req: &mut ::engine_ecs::internal::ComponentRequests) {
-                    req.request(#counter3, true);
-                    req.require(#counter3);
+                    req.request(#table_mutable_indices, true);
+                    req.require(#table_mutable_indices);
                 }
                 unsafe fn get_from_world(
                     world: &'wrld ::engine_ecs::QueryWorld<'wrld, ComponentStorage>,
@@ -205,10 +678,99 @@ pub fn gen_storage_for_world(input: TokenStream) -> TokenStream {
                     index: ::engine_ecs::internal::InArchetypeID,
                     _ent_id: ::engine_ecs::EntityID,
                 ) -> Self {
-                    let storage = world.storage_for_archetype::<#component_types>(archetype).expect("component assumed to exist, as we've asked for it");
+                    let storage = world.storage_for_archetype::<#table_mutable_types>(archetype).expect("component assumed to exist, as we've asked for it");
                     world.get_mut(storage, index).expect("component assumed to exist, as it exists in the archetype")
                 }
             }
+
+            unsafe impl<'wrld> ::engine_ecs::internal::QueryParameter<'wrld, ComponentStorage> for Option<&'wrld mut #table_mutable_types> {
+                fn add_requests(req: &mut ::engine_ecs::internal::ComponentRequests) {
+                    req.request(#table_mutable_indices, true);
+                }
+                unsafe fn get_from_world(
+                    world: &'wrld ::engine_ecs::QueryWorld<'wrld, ComponentStorage>,
+                    archetype: ::engine_ecs::ArchetypeID,
+                    index: ::engine_ecs::internal::InArchetypeID,
+                    _ent_id: ::engine_ecs::EntityID,
+                ) -> Self {
+                    let storage = world.storage_for_archetype::<#table_mutable_types>(archetype)?;
+                    Some(world.get_mut(storage, index).expect("component assumed to exist, as it exists in the archetype"))
+                }
+            }
+        )*
+
+        #(
+            unsafe impl<'wrld> ::engine_ecs::internal::QueryParameter<'wrld, ComponentStorage> for &'wrld #sparse_component_types {
+                const SPARSE: bool = true;
+                fn add_requests(req: &mut ::engine_ecs::internal::ComponentRequests) {
+                    req.request_sparse(#sparse_component_indices, false);
+                }
+                fn matches(world: &::engine_ecs::QueryWorld<ComponentStorage>, ent_id: ::engine_ecs::EntityID) -> bool {
+                    world.contains_sparse(#sparse_component_indices, ent_id)
+                }
+                fn sparse_entities(world: &'wrld ::engine_ecs::QueryWorld<'wrld, ComponentStorage>) -> Option<&'wrld [::engine_ecs::EntityID]> {
+                    Some(world.sparse_entities::<#sparse_component_types>())
+                }
+                unsafe fn get_from_world(
+                    world: &'wrld ::engine_ecs::QueryWorld<'wrld, ComponentStorage>,
+                    _archetype: ::engine_ecs::ArchetypeID,
+                    _index: ::engine_ecs::internal::InArchetypeID,
+                    ent_id: ::engine_ecs::EntityID,
+                ) -> Self {
+                    world.get_sparse(ent_id).expect("component assumed to exist, as entity matches the query")
+                }
+            }
+
+            unsafe impl<'wrld> ::engine_ecs::internal::QueryParameter<'wrld, ComponentStorage> for Option<&'wrld #sparse_component_types> {
+                const SPARSE: bool = true;
+                fn add_requests(req: &mut ::engine_ecs::internal::ComponentRequests) {
+                    req.request_sparse(#sparse_component_indices, false);
+                }
+                unsafe fn get_from_world(
+                    world: &'wrld ::engine_ecs::QueryWorld<'wrld, ComponentStorage>,
+                    _archetype: ::engine_ecs::ArchetypeID,
+                    _index: ::engine_ecs::internal::InArchetypeID,
+                    ent_id: ::engine_ecs::EntityID,
+                ) -> Self {
+                    world.get_sparse(ent_id)
+                }
+            }
+
+            unsafe impl<'wrld> ::engine_ecs::internal::QueryParameter<'wrld, ComponentStorage> for &'wrld mut #sparse_component_types {
+                const SPARSE: bool = true;
+                fn add_requests(req: &mut ::engine_ecs::internal::ComponentRequests) {
+                    req.request_sparse(#sparse_component_indices, true);
+                }
+                fn matches(world: &::engine_ecs::QueryWorld<ComponentStorage>, ent_id: ::engine_ecs::EntityID) -> bool {
+                    world.contains_sparse(#sparse_component_indices, ent_id)
+                }
+                fn sparse_entities(world: &'wrld ::engine_ecs::QueryWorld<'wrld, ComponentStorage>) -> Option<&'wrld [::engine_ecs::EntityID]> {
+                    Some(world.sparse_entities::<#sparse_component_types>())
+                }
+                unsafe fn get_from_world(
+                    world: &'wrld ::engine_ecs::QueryWorld<'wrld, ComponentStorage>,
+                    _archetype: ::engine_ecs::ArchetypeID,
+                    _index: ::engine_ecs::internal::InArchetypeID,
+                    ent_id: ::engine_ecs::EntityID,
+                ) -> Self {
+                    world.get_sparse_mut(ent_id).expect("component assumed to exist, as entity matches the query")
+                }
+            }
+
+            unsafe impl<'wrld> ::engine_ecs::internal::QueryParameter<'wrld, ComponentStorage> for Option<&'wrld mut #sparse_component_types> {
+                const SPARSE: bool = true;
+                fn add_requests(req: &mut ::engine_ecs::internal::ComponentRequests) {
+                    req.request_sparse(#sparse_component_indices, true);
+                }
+                unsafe fn get_from_world(
+                    world: &'wrld ::engine_ecs::QueryWorld<'wrld, ComponentStorage>,
+                    _archetype: ::engine_ecs::ArchetypeID,
+                    _index: ::engine_ecs::internal::InArchetypeID,
+                    ent_id: ::engine_ecs::EntityID,
+                ) -> Self {
+                    world.get_sparse_mut(ent_id)
+                }
+            }
         )*
 
         #(
@@ -223,6 +785,11 @@ pub fn gen_storage_for_world(input: TokenStream) -> TokenStream {
                 unsafe fn from_world(world: &'a ::engine_ecs::QueryWorld<'a, ComponentStorage>) -> Self {
                     world.resource()
                 }
+                unsafe fn try_from_world(world: &'a ::engine_ecs::QueryWorld<'a, ComponentStorage>) -> Result<Self, ::engine_ecs::EcsError> {
+                    world.get_resource().ok_or_else(|| ::engine_ecs::EcsError::MissingResource {
+                        resource: ::std::any::type_name::<#resource_types>(),
+                    })
+                }
             }
 
             unsafe impl<'a> ::engine_ecs::internal::SystemParameter<'a, ComponentStorage> for &'a mut #resource_types {
@@ -236,18 +803,61 @@ pub fn gen_storage_for_world(input: TokenStream) -> TokenStream {
                 unsafe fn from_world(world: &'a ::engine_ecs::QueryWorld<'a, ComponentStorage>) -> Self {
                     world.resource_mut()
                 }
+                unsafe fn try_from_world(world: &'a ::engine_ecs::QueryWorld<'a, ComponentStorage>) -> Result<Self, ::engine_ecs::EcsError> {
+                    world.get_resource_mut().ok_or_else(|| ::engine_ecs::EcsError::MissingResource {
+                        resource: ::std::any::type_name::<#resource_types>(),
+                    })
+                }
+            }
+
+            unsafe impl<'a> ::engine_ecs::internal::SystemParameter<'a, ComponentStorage> for Option<&'a #resource_types> {
+                fn requests() -> ::engine_ecs::internal::SmallVec<[::engine_ecs::internal::ComponentRequests; 8]> {
+                    <&'a #resource_types as ::engine_ecs::internal::SystemParameter<'a, ComponentStorage>>::requests()
+                }
+                unsafe fn from_world(world: &'a ::engine_ecs::QueryWorld<'a, ComponentStorage>) -> Self {
+                    world.get_resource()
+                }
+            }
+
+            unsafe impl<'a> ::engine_ecs::internal::SystemParameter<'a, ComponentStorage> for Option<&'a mut #resource_types> {
+                fn requests() -> ::engine_ecs::internal::SmallVec<[::engine_ecs::internal::ComponentRequests; 8]> {
+                    <&'a mut #resource_types as ::engine_ecs::internal::SystemParameter<'a, ComponentStorage>>::requests()
+                }
+                unsafe fn from_world(world: &'a ::engine_ecs::QueryWorld<'a, ComponentStorage>) -> Self {
+                    world.get_resource_mut()
+                }
             }
         )*
 
         pub type Query<'a, T, Limits=()> = ::engine_ecs::QueryG<'a, ComponentStorage, T, Limits>;
         pub type With<T> = ::engine_ecs::WithG<ComponentStorage, T>;
         pub type Without<T> = ::engine_ecs::WithoutG<ComponentStorage, T>;
-        pub type Commands = ::engine_ecs::CommandsG<ComponentStorage>;
+        pub type Changed<T> = ::engine_ecs::ChangedG<ComponentStorage, T>;
+        pub type Or<T> = ::engine_ecs::OrG<ComponentStorage, T>;
+        pub type AnyOf<T> = ::engine_ecs::AnyOfG<ComponentStorage, T>;
+        pub type Commands<'a> = ::engine_ecs::CommandsG<'a, ComponentStorage>;
+        pub type Command = ::engine_ecs::CommandG<ComponentStorage>;
         pub type Changes<'a> = ::engine_ecs::ChangesG<'a, ComponentStorage>;
+        pub type Spawned<'a, T> = ::engine_ecs::SpawnedG<'a, ComponentStorage, T>;
+        pub type Despawned<'a, T> = ::engine_ecs::DespawnedG<'a, ComponentStorage, T>;
+        pub type EventWriter<'a, T> = ::engine_ecs::EventWriterG<'a, ComponentStorage, T>;
+        pub type EventReader<'a, T> = ::engine_ecs::EventReaderG<'a, ComponentStorage, T>;
+        pub type Local<'a, T> = ::engine_ecs::LocalG<'a, ComponentStorage, T>;
+        pub type Index<'a, C, K> = ::engine_ecs::IndexG<'a, ComponentStorage, C, K>;
     )
     .into()
 }
 
+/// Types of the storage kind given by `sparse`, along with their type indices.
+fn split_by_kind<T: Clone>(types: &[T], is_sparse: &[bool], sparse: bool) -> (Vec<T>, Vec<u32>) {
+    types
+        .iter()
+        .zip(0u32..)
+        .filter(|(_c, index)| is_sparse[*index as usize] == sparse)
+        .map(|(c, index)| (c.clone(), index))
+        .unzip()
+}
+
 /// ecs internal use
 #[proc_macro]
 pub fn gen_bundle_tuple_impls(input: TokenStream) -> TokenStream {
@@ -263,7 +873,7 @@ pub fn gen_bundle_tuple_impls(input: TokenStream) -> TokenStream {
         .map(|x| format_ident!("B{x}"))
         .collect::<Vec<_>>();
     let type_names_from_1 = type_names.iter().skip(1);
-    let counter = (0..count).map(syn::Index::from);
+    let counter = (0..count).map(syn::Index::from).collect::<Vec<_>>();
 
     quote!(
         impl<Storage, #(#type_names,)*> Bundle<Storage> for (#(#type_names,)*)
@@ -280,8 +890,30 @@ pub fn gen_bundle_tuple_impls(input: TokenStream) -> TokenStream {
                 self,
                 world: &mut World<Storage>,
                 archetype: ArchetypeID,
+                entity: EntityID,
+            ) {
+                #(self.#counter.add_to_archetype_in_storage(world, archetype, entity);)*
+            }
+
+            fn add_many_to_archetype_in_storage(
+                bundles: Vec<Self>,
+                world: &mut World<Storage>,
+                archetype: ArchetypeID,
+                entities: &[EntityID],
             ) {
-                #(self.#counter.add_to_archetype_in_storage(world, archetype);)*
+                // Every part goes to its own storages, so bundles are split into a list per part.
+                let mut parts = (#(Vec::<#type_names>::with_capacity(bundles.len()),)*);
+                for bundle in bundles {
+                    #(parts.#counter.push(bundle.#counter);)*
+                }
+                #(#type_names::add_many_to_archetype_in_storage(parts.#counter, world, archetype, entities);)*
+            }
+
+            fn into_components(self, components: &mut Vec<Storage::AnyComponent>)
+            where
+                Storage: DynDispath,
+            {
+                #(self.#counter.into_components(components);)*
             }
         }
     )
@@ -302,14 +934,25 @@ pub fn gen_query_param_tuple_impls(input: TokenStream) -> TokenStream {
     let type_names = (0..count)
         .map(|x| format_ident!("P{x}"))
         .collect::<Vec<_>>();
+    let indices = (0..count).map(syn::Index::from).collect::<Vec<_>>();
 
     quote!(
         unsafe impl<'wrld, Storage: DynDispath, #(#type_names: QueryParameter<'wrld, Storage>,)*> QueryParameter<'wrld, Storage> for (#(#type_names,)*)
         {
+            const SPARSE: bool = false #(|| #type_names::SPARSE)*;
+
             fn add_requests(req: &mut ComponentRequests) {
                 #(#type_names::add_requests(req);)*
             }
 
+            fn matches(world: &QueryWorld<Storage>, ent_id: EntityID) -> bool {
+                #(#type_names::matches(world, ent_id))&&*
+            }
+
+            fn sparse_entities(world: &'wrld QueryWorld<'wrld, Storage>) -> Option<&'wrld [EntityID]> {
+                shortest_entity_list([#(#type_names::sparse_entities(world),)*])
+            }
+
             unsafe fn get_from_world(
                 world: &'wrld QueryWorld<'wrld, Storage>,
                 archetype: ArchetypeID,
@@ -320,11 +963,138 @@ pub fn gen_query_param_tuple_impls(input: TokenStream) -> TokenStream {
             }
         }
 
-        impl<#(#type_names: QueryLimits,)*> QueryLimits for (#(#type_names,)*)
+        unsafe impl<'wrld, Storage: DynDispath, #(#type_names: ChunkParameter<'wrld, Storage>,)*> ChunkParameter<'wrld, Storage> for (#(#type_names,)*)
         {
+            type Chunk = (#(#type_names::Chunk,)*);
+            type Slices = (#(#type_names::Slices,)*);
+
+            unsafe fn get_chunk(world: &'wrld QueryWorld<'wrld, Storage>, archetype: ArchetypeID) -> Self::Chunk {
+                (#(#type_names::get_chunk(world, archetype),)*)
+            }
+            fn split_front(chunk: &mut Self::Chunk, mid: usize) -> Self::Chunk {
+                (#(#type_names::split_front(&mut chunk.#indices, mid),)*)
+            }
+            fn take_first(chunk: &mut Self::Chunk) -> Self {
+                (#(#type_names::take_first(&mut chunk.#indices),)*)
+            }
+            fn into_slices(chunk: Self::Chunk) -> Self::Slices {
+                (#(#type_names::into_slices(chunk.#indices),)*)
+            }
+        }
+
+        impl<Storage: DynDispath, #(#type_names: QueryLimits<Storage>,)*> QueryLimits<Storage> for (#(#type_names,)*)
+        {
+            const PER_ENTITY: bool = false #(|| #type_names::PER_ENTITY)*;
+            type Prepared = (#(#type_names::Prepared,)*);
+
             fn add_requests(req: &mut ComponentRequests) {
                 #(#type_names::add_requests(req);)*
             }
+
+            unsafe fn prepare(world: &QueryWorld<Storage>, archetype: ArchetypeID) -> Self::Prepared {
+                (#(#type_names::prepare(world, archetype),)*)
+            }
+
+            unsafe fn matches(
+                world: &QueryWorld<Storage>,
+                prepared: Self::Prepared,
+                archetype: ArchetypeID,
+                index: InArchetypeID,
+            ) -> bool {
+                #(#type_names::matches(world, prepared.#indices, archetype, index))&&*
+            }
+        }
+
+        impl<Storage: DynDispath, #(#type_names: QueryLimits<Storage>,)*> QueryLimits<Storage> for OrG<Storage, (#(#type_names,)*)>
+        {
+            const PER_ENTITY: bool = false #(|| #type_names::PER_ENTITY)*;
+            /// Prepared alternatives that the archetype satisfies, `None` for the rest.
+            type Prepared = (#(Option<#type_names::Prepared>,)*);
+
+            fn add_requests(req: &mut ComponentRequests) {
+                req.any_of(vec![#({
+                    let mut alternative = ComponentRequests::default();
+                    #type_names::add_requests(&mut alternative);
+                    alternative
+                }),*]);
+            }
+
+            unsafe fn prepare(world: &QueryWorld<Storage>, archetype: ArchetypeID) -> Self::Prepared {
+                if !Self::PER_ENTITY {
+                    // Archetype was already checked against alternatives.
+                    return Default::default();
+                }
+                let arche = &world.inner.archeman.archetypes[archetype.0 as usize];
+                (#({
+                    let mut alternative = ComponentRequests::default();
+                    #type_names::add_requests(&mut alternative);
+                    alternative
+                        .satisfied_by(arche)
+                        .then(|| #type_names::prepare(world, archetype))
+                },)*)
+            }
+
+            unsafe fn matches(
+                world: &QueryWorld<Storage>,
+                prepared: Self::Prepared,
+                archetype: ArchetypeID,
+                index: InArchetypeID,
+            ) -> bool {
+                if !Self::PER_ENTITY {
+                    return true;
+                }
+                #(
+                    if let Some(prepared) = prepared.#indices {
+                        if #type_names::matches(world, prepared, archetype, index) {
+                            return true;
+                        }
+                    }
+                )*
+                false
+            }
+        }
+
+        impl<Storage: DynDispath, #(#type_names: Component<Storage>,)*> QueryLimits<Storage> for AnyOfG<Storage, (#(#type_names,)*)>
+        {
+            // Entities with sparse components can be in any archetype.
+            const PER_ENTITY: bool = false #(|| Storage::SPARSE[#type_names::TYPE_INDEX as usize])*;
+
+            fn add_requests(req: &mut ComponentRequests) {
+                if Self::PER_ENTITY {
+                    return;
+                }
+                req.any_of(vec![#({
+                    let mut alternative = ComponentRequests::default();
+                    alternative.require(#type_names::TYPE_INDEX);
+                    alternative
+                }),*]);
+            }
+
+            /// True if the archetype has one of components that aren't sparse.
+            type Prepared = bool;
+
+            unsafe fn prepare(world: &QueryWorld<Storage>, archetype: ArchetypeID) -> bool {
+                false #(|| (
+                    !Storage::is_sparse(#type_names::TYPE_INDEX)
+                        && world.storage_for_archetype::<#type_names>(archetype).is_some()
+                ))*
+            }
+
+            unsafe fn matches(
+                world: &QueryWorld<Storage>,
+                prepared: bool,
+                archetype: ArchetypeID,
+                index: InArchetypeID,
+            ) -> bool {
+                if !Self::PER_ENTITY || prepared {
+                    return true;
+                }
+                let entity = world.entity_at(archetype, index);
+                false #(|| (
+                    Storage::is_sparse(#type_names::TYPE_INDEX)
+                        && world.contains_sparse(#type_names::TYPE_INDEX, entity)
+                ))*
+            }
         }
     ).into()
 }
@@ -385,9 +1155,6 @@ pub fn gen_world_run_impls(input: TokenStream) -> TokenStream {
     let var_names = (0..count)
         .map(|x| format_ident!("p{x}"))
         .collect::<Vec<_>>();
-    let rel_names = (0..count)
-        .map(|x| format_ident!("r{x}"))
-        .collect::<Vec<_>>();
 
     quote!(
         impl<'wrld, Storage: DynDispath, F, Ret, #(#type_names,)*> WorldRun<'wrld, F, Ret, (#(#type_names,)*)> for QueryWorld<'wrld, Storage>
@@ -395,12 +1162,295 @@ pub fn gen_world_run_impls(input: TokenStream) -> TokenStream {
             F: FnOnce(#(#type_names),*) -> Ret,
             #(#type_names: SystemParameter<'wrld, Storage>,)*
         {
-            fn run(&'wrld self, f: F) -> Ret {
-                #( let (#rel_names, #var_names) = self.parameter_raw(); )*
+            fn try_run(&'wrld self, f: F) -> Result<Ret, EcsError> {
+                let mut holds = Vec::with_capacity(#count);
+                #(
+                    let #var_names = match self.try_parameter_raw::<#type_names>() {
+                        Ok((param_holds, param)) => {
+                            holds.push(param_holds);
+                            param
+                        }
+                        Err(err) => {
+                            for param_holds in holds {
+                                self.release_parameter(param_holds);
+                            }
+                            return Err(err);
+                        }
+                    };
+                )*
                 let ret = f(#(#var_names),*);
-                #( self.release_parameter(#rel_names); )*
+                for param_holds in holds {
+                    self.release_parameter(param_holds);
+                }
+                Ok(ret)
+            }
+
+            fn requests() -> Vec<ComponentRequests> {
+                let mut requests = Vec::new();
+                #( requests.extend(#type_names::requests()); )*
+                requests
+            }
+        }
+    )
+    .into()
+}
+
+/// Fields of a struct with named fields, or an error to emit instead of the derived impl.
+fn named_fields<'a>(
+    input: &'a DeriveInput,
+    derived: &str,
+) -> Result<(Vec<&'a Ident>, Vec<&'a Type>), TokenStream> {
+    let error = |message: String| {
+        Err(syn::Error::new_spanned(&input.ident, message)
+            .to_compile_error()
+            .into())
+    };
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return error(format!(
+                    "{derived} can only be derived for structs with named fields"
+                ))
+            }
+        },
+        _ => return error(format!("{derived} can only be derived for structs")),
+    };
+    let field_names = fields
+        .iter()
+        .map(|field| field.ident.as_ref().expect("fields are named"))
+        .collect();
+    let field_types = fields.iter().map(|field| &field.ty).collect();
+    Ok((field_names, field_types))
+}
+
+/// Generics of the derived impl: lifetime of the world, which is the struct's own lifetime if it has one, and `Storage`.
+fn world_generics(input: &DeriveInput) -> Result<(Generics, Lifetime), TokenStream> {
+    let mut generics = input.generics.clone();
+    let lifetime = match input.generics.lifetimes().collect::<Vec<_>>().as_slice() {
+        [] => {
+            let lifetime: Lifetime = parse_quote!('wrld);
+            generics.params.insert(0, parse_quote!(#lifetime));
+            lifetime
+        }
+        [param] => param.lifetime.clone(),
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.generics,
+                "expected at most one lifetime, that is used for borrows from the world",
+            )
+            .to_compile_error()
+            .into())
+        }
+    };
+    generics
+        .params
+        .push(parse_quote!(Storage: ::engine_ecs::internal::DynDispath));
+    Ok((generics, lifetime))
+}
+
+/// Implements `Bundle` for a struct with named fields, each of which has to be a `Bundle` itself.
+#[proc_macro_derive(Bundle)]
+pub fn derive_bundle(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let (field_names, field_types) = match named_fields(&input, "Bundle") {
+        Ok(fields) => fields,
+        Err(error) => return error,
+    };
+
+    let mut generics = input.generics.clone();
+    generics.params.push(parse_quote!(Storage));
+    let where_clause = generics.make_where_clause();
+    for field_type in &field_types {
+        where_clause
+            .predicates
+            .push(parse_quote!(#field_type: ::engine_ecs::Bundle<Storage>));
+    }
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    let (_, type_generics, _) = input.generics.split_for_impl();
+    let field_counter = (0..field_names.len())
+        .map(syn::Index::from)
+        .collect::<Vec<_>>();
+
+    quote!(
+        impl #impl_generics ::engine_ecs::Bundle<Storage> for #name #type_generics #where_clause {
+            fn type_ids() -> ::engine_ecs::internal::TypeIndexStorage {
+                let mut indexes = ::engine_ecs::internal::TypeIndexStorage::new();
+                #(indexes.extend_from_slice(&<#field_types as ::engine_ecs::Bundle<Storage>>::type_ids());)*
+                indexes
+            }
+
+            fn add_to_archetype_in_storage(
+                self,
+                world: &mut ::engine_ecs::World<Storage>,
+                archetype: ::engine_ecs::ArchetypeID,
+                entity: ::engine_ecs::EntityID,
+            ) {
+                #(<#field_types as ::engine_ecs::Bundle<Storage>>::add_to_archetype_in_storage(self.#field_names, world, archetype, entity);)*
+            }
+
+            fn add_many_to_archetype_in_storage(
+                bundles: Vec<Self>,
+                world: &mut ::engine_ecs::World<Storage>,
+                archetype: ::engine_ecs::ArchetypeID,
+                entities: &[::engine_ecs::EntityID],
+            ) {
+                let mut fields = (#(Vec::<#field_types>::with_capacity(bundles.len()),)*);
+                for bundle in bundles {
+                    #(fields.#field_counter.push(bundle.#field_names);)*
+                }
+                #(<#field_types as ::engine_ecs::Bundle<Storage>>::add_many_to_archetype_in_storage(fields.#field_counter, world, archetype, entities);)*
+            }
+
+            fn into_components(self, components: &mut Vec<<Storage as ::engine_ecs::internal::DynDispath>::AnyComponent>)
+            where
+                Storage: ::engine_ecs::internal::DynDispath,
+            {
+                #(<#field_types as ::engine_ecs::Bundle<Storage>>::into_components(self.#field_names, components);)*
+            }
+        }
+    )
+    .into()
+}
+
+/// Implements `SystemParameter` for a struct with named fields, each of which has to be a `SystemParameter` itself.
+///
+/// Struct can have a single lifetime, which is used for borrows from the world.
+#[proc_macro_derive(SystemParam)]
+pub fn derive_system_param(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let (field_names, field_types) = match named_fields(&input, "SystemParam") {
+        Ok(fields) => fields,
+        Err(error) => return error,
+    };
+    let (mut generics, lifetime) = match world_generics(&input) {
+        Ok(generics) => generics,
+        Err(error) => return error,
+    };
+
+    let where_clause = generics.make_where_clause();
+    for field_type in &field_types {
+        where_clause.predicates.push(
+            parse_quote!(#field_type: ::engine_ecs::internal::SystemParameter<#lifetime, Storage>),
+        );
+    }
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    let (_, type_generics, _) = input.generics.split_for_impl();
+
+    quote!(
+        unsafe impl #impl_generics ::engine_ecs::internal::SystemParameter<#lifetime, Storage> for #name #type_generics #where_clause {
+            fn requests() -> ::engine_ecs::internal::SmallVec<[::engine_ecs::internal::ComponentRequests; 8]> {
+                let mut ret = ::engine_ecs::internal::SmallVec::new();
+                #(ret.extend(<#field_types as ::engine_ecs::internal::SystemParameter<#lifetime, Storage>>::requests());)*
                 ret
             }
+
+            unsafe fn from_world(world: &#lifetime ::engine_ecs::QueryWorld<#lifetime, Storage>) -> Self {
+                Self {
+                    #(#field_names: <#field_types as ::engine_ecs::internal::SystemParameter<#lifetime, Storage>>::from_world(world),)*
+                }
+            }
+
+            unsafe fn try_from_world(world: &#lifetime ::engine_ecs::QueryWorld<#lifetime, Storage>) -> Result<Self, ::engine_ecs::EcsError> {
+                Ok(Self {
+                    #(#field_names: <#field_types as ::engine_ecs::internal::SystemParameter<#lifetime, Storage>>::try_from_world(world)?,)*
+                })
+            }
+        }
+    )
+    .into()
+}
+
+/// Implements `QueryParameter` for a struct with named fields, each of which has to be a `QueryParameter` itself.
+///
+/// Struct can have a single lifetime, which is used for borrows from the world.
+/// If all fields support it, `ChunkParameter` is implemented as well, with chunks being tuples of fields' chunks.
+#[proc_macro_derive(QueryData)]
+pub fn derive_query_data(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let (field_names, field_types) = match named_fields(&input, "QueryData") {
+        Ok(fields) => fields,
+        Err(error) => return error,
+    };
+    let (generics, lifetime) = match world_generics(&input) {
+        Ok(generics) => generics,
+        Err(error) => return error,
+    };
+    let indices = (0..field_types.len())
+        .map(syn::Index::from)
+        .collect::<Vec<_>>();
+
+    let mut query_generics = generics.clone();
+    let where_clause = query_generics.make_where_clause();
+    for field_type in &field_types {
+        where_clause.predicates.push(
+            parse_quote!(#field_type: ::engine_ecs::internal::QueryParameter<#lifetime, Storage>),
+        );
+    }
+    let (impl_generics, _, where_clause) = query_generics.split_for_impl();
+    let (_, type_generics, _) = input.generics.split_for_impl();
+
+    let mut chunk_generics = generics.clone();
+    let chunk_where_clause = chunk_generics.make_where_clause();
+    for field_type in &field_types {
+        chunk_where_clause.predicates.push(
+            parse_quote!(#field_type: ::engine_ecs::internal::ChunkParameter<#lifetime, Storage>),
+        );
+    }
+    let (chunk_impl_generics, _, chunk_where_clause) = chunk_generics.split_for_impl();
+
+    quote!(
+        unsafe impl #impl_generics ::engine_ecs::internal::QueryParameter<#lifetime, Storage> for #name #type_generics #where_clause {
+            const SPARSE: bool = false #(|| <#field_types as ::engine_ecs::internal::QueryParameter<#lifetime, Storage>>::SPARSE)*;
+
+            fn add_requests(req: &mut ::engine_ecs::internal::ComponentRequests) {
+                #(<#field_types as ::engine_ecs::internal::QueryParameter<#lifetime, Storage>>::add_requests(req);)*
+            }
+
+            fn matches(world: &::engine_ecs::QueryWorld<Storage>, ent_id: ::engine_ecs::EntityID) -> bool {
+                true #(&& <#field_types as ::engine_ecs::internal::QueryParameter<#lifetime, Storage>>::matches(world, ent_id))*
+            }
+
+            fn sparse_entities(world: &#lifetime ::engine_ecs::QueryWorld<#lifetime, Storage>) -> Option<&#lifetime [::engine_ecs::EntityID]> {
+                ::engine_ecs::internal::shortest_entity_list([#(<#field_types as ::engine_ecs::internal::QueryParameter<#lifetime, Storage>>::sparse_entities(world),)*])
+            }
+
+            unsafe fn get_from_world(
+                world: &#lifetime ::engine_ecs::QueryWorld<#lifetime, Storage>,
+                archetype: ::engine_ecs::ArchetypeID,
+                index: ::engine_ecs::internal::InArchetypeID,
+                ent_id: ::engine_ecs::EntityID,
+            ) -> Self {
+                Self {
+                    #(#field_names: <#field_types as ::engine_ecs::internal::QueryParameter<#lifetime, Storage>>::get_from_world(world, archetype, index, ent_id),)*
+                }
+            }
+        }
+
+        unsafe impl #chunk_impl_generics ::engine_ecs::internal::ChunkParameter<#lifetime, Storage> for #name #type_generics #chunk_where_clause {
+            type Chunk = (#(<#field_types as ::engine_ecs::internal::ChunkParameter<#lifetime, Storage>>::Chunk,)*);
+            type Slices = (#(<#field_types as ::engine_ecs::internal::ChunkParameter<#lifetime, Storage>>::Slices,)*);
+
+            unsafe fn get_chunk(
+                world: &#lifetime ::engine_ecs::QueryWorld<#lifetime, Storage>,
+                archetype: ::engine_ecs::ArchetypeID,
+            ) -> Self::Chunk {
+                (#(<#field_types as ::engine_ecs::internal::ChunkParameter<#lifetime, Storage>>::get_chunk(world, archetype),)*)
+            }
+            fn split_front(chunk: &mut Self::Chunk, mid: usize) -> Self::Chunk {
+                (#(<#field_types as ::engine_ecs::internal::ChunkParameter<#lifetime, Storage>>::split_front(&mut chunk.#indices, mid),)*)
+            }
+            fn take_first(chunk: &mut Self::Chunk) -> Self {
+                Self {
+                    #(#field_names: <#field_types as ::engine_ecs::internal::ChunkParameter<#lifetime, Storage>>::take_first(&mut chunk.#indices),)*
+                }
+            }
+            fn into_slices(chunk: Self::Chunk) -> Self::Slices {
+                (#(<#field_types as ::engine_ecs::internal::ChunkParameter<#lifetime, Storage>>::into_slices(chunk.#indices),)*)
+            }
         }
     )
     .into()
@@ -1,14 +1,15 @@
 use std::{mem, time::Duration};
 
-use engine_ecs::{EntityID, World, WorldRun};
+use engine_ecs::{system, EntityID, Schedule, World};
 use engine_num::Vec3;
 use engine_registry::{BuildingKind, TileKind};
 use mcs::{
-    events::system_handle_pending_events, system_handle_actions, ComponentStorage,
+    events::system_handle_pending_events, system_handle_actions, Building, ComponentStorage,
     PendingEventsRes, Player, PlayerID, PlayerMap,
 };
 use rotations::BuildingOrientation;
 use serde::{Deserialize, Serialize};
+use tracing::error;
 
 use self::{
     tilemap::{TileIndex, TilePos},
@@ -38,8 +39,21 @@ impl Universe {
         Universe::default()
     }
 
-    pub fn update_ctx(&mut self) -> UpdateCtx {
-        UpdateCtx { universe: self }
+    /// Systems run on every step. Should be created once and kept between steps, as systems keep their locals in it.
+    pub fn schedule() -> Schedule<ComponentStorage> {
+        Schedule::new()
+            .with_system(system!(system_handle_pending_events))
+            .with_system(system!(system_handle_actions).after("system_handle_pending_events"))
+    }
+
+    pub fn update_ctx<'a>(
+        &'a mut self,
+        schedule: &'a mut Schedule<ComponentStorage>,
+    ) -> UpdateCtx<'a> {
+        UpdateCtx {
+            universe: self,
+            schedule,
+        }
     }
 
     pub fn player_ent_id(&self, player: PlayerID) -> Option<EntityID> {
@@ -56,9 +70,11 @@ impl Universe {
 
 pub struct UpdateCtx<'a> {
     universe: &'a mut Universe,
+    schedule: &'a mut Schedule<ComponentStorage>,
 }
 
 impl UpdateCtx<'_> {
+    /// Takes events for ui, accumulated over every step since the last ui update.
     #[must_use]
     pub fn evctx(self) -> UiEventCtx {
         mem::take(self.universe.world.resource_mut::<UiEventCtx>())
@@ -74,15 +90,27 @@ impl UpdateCtx<'_> {
 
     pub fn step(&mut self) {
         let world = &mut self.universe.world;
-        {
-            let query_world = &world.query_world();
-            query_world.run(system_handle_pending_events);
-            query_world.run(system_handle_actions);
+        if let Err(errors) = self.schedule.try_run_parallel(world) {
+            for (system, err) in errors {
+                error!("Universe system {system} failed: {err}");
+            }
         }
         world.resource_mut::<PendingEventsRes>().0.clear();
-        //system_handle_pending_events(universe, evctx);
 
-        //self.universe.pending_events.clear();
+        // Every step is exactly one change detection cycle, no matter how many steps a client runs per frame.
+        world.next_cycle();
+        let (spawned, despawned) = {
+            let qword = world.query_world_shared();
+            let spawned: Vec<_> = qword.parameter::<mcs::Spawned<Building>>().iter().collect();
+            let despawned: Vec<_> = qword
+                .parameter::<mcs::Despawned<Building>>()
+                .iter()
+                .collect();
+            (spawned, despawned)
+        };
+        let evctx = world.resource_mut::<UiEventCtx>();
+        evctx.buildings_spawned.extend(spawned);
+        evctx.buildings_despawned.extend(despawned);
     }
 }
 
@@ -4,16 +4,51 @@ pub(crate) mod player;
 pub(crate) mod vessel;
 
 pub use buildings::*;
+use engine_ecs::{EntityID, Hooks, Migrations};
 use engine_macro::gen_storage_for_world;
 pub(crate) use events::*;
 pub use player::*;
 pub use vessel::*;
 
-use crate::UiEventCtx;
+use crate::{tilemap::TilePos, UiEventCtx};
 
 gen_storage_for_world!(
     : components
         VesselTiles Player Building
     : resources
-        DefaultVesselRes PendingEventsRes PlayerMap UiEventCtx PendingActionsRes
+        DefaultVesselRes transient PendingEventsRes PlayerMap transient UiEventCtx PendingActionsRes
+    : migrations
+        migrations
+    : hooks
+        hooks
+    : indexes
+        Building (VesselID, TilePos)
 );
+
+fn hooks() -> Hooks<ComponentStorage> {
+    Hooks::new()
+        .on_remove::<Player>(|player, commands| {
+            let entity = player.id();
+            commands.submit(move |world| world.resource_mut::<PlayerMap>().forget(entity));
+        })
+        // Buildings are children of their vessels, so that they are despawned along with them.
+        .on_add::<Building>(|building, commands| {
+            let vessel = building.get::<Building>().expect("just added").vessel;
+            commands.set_parent(building.id(), vessel.0);
+        })
+}
+
+fn migrations() -> Migrations<ComponentStorage> {
+    // Buildings became children of their vessels, see `hooks`.
+    Migrations::new().step(|world| {
+        let buildings: Vec<(EntityID, VesselID)> = world
+            .query_world_shared()
+            .parameter::<Query<(EntityID, &Building)>>()
+            .iter()
+            .map(|(entity, building)| (entity, building.vessel))
+            .collect();
+        for (building, vessel) in buildings {
+            world.set_parent(building, vessel.0);
+        }
+    })
+}
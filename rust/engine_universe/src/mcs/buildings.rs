@@ -1,3 +1,4 @@
+use engine_ecs::IndexedBy;
 use engine_registry::BuildingKind;
 use serde::{Deserialize, Serialize};
 
@@ -10,8 +11,23 @@ pub struct Building {
     pub position: TilePos,
     pub orientation: BuildingOrientation,
     pub kind: BuildingKind,
+    /// Also the `Parent` of the building, set by a hook once it's added. Only set when the building is created,
+    /// and its parent shouldn't be changed either, so that the index and the hierarchy agree.
     #[serde(default)]
-    pub vessel: VesselID,
+    pub(crate) vessel: VesselID,
+}
+
+impl Building {
+    pub fn vessel(&self) -> VesselID {
+        self.vessel
+    }
+}
+
+/// Buildings are indexed by the tile they occupy.
+impl IndexedBy<(VesselID, TilePos)> for Building {
+    fn key(&self) -> (VesselID, TilePos) {
+        (self.vessel, self.position)
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -1,17 +1,27 @@
+use std::collections::HashSet;
+
 use engine_num::Vec3;
 use serde::{Deserialize, Serialize};
 use tracing::info;
 
 use crate::{
-    actions::Action, mcs::Player, tilemap::Tile, ui_events::UiEventCtx, OwnedUniverseEvent,
-    UniverseEvent,
+    actions::Action,
+    mcs::Player,
+    tilemap::{Tile, TilePos},
+    ui_events::UiEventCtx,
+    OwnedUniverseEvent, UniverseEvent,
 };
 
-use super::{Building, Commands, DefaultVesselRes, PlayerMap, Query, VesselTiles};
+use super::{Building, Commands, DefaultVesselRes, Index, PlayerMap, Query, VesselID, VesselTiles};
 
+/// Events received since the last step, cleared after every step.
+///
+/// Not an `Events` queue: reader cursors are kept by the schedule rather than the world,
+/// so a client that joins from a snapshot would read events from the previous step again.
 #[derive(Default, Clone, Serialize, Deserialize)]
 pub(crate) struct PendingEventsRes(pub(crate) Vec<OwnedUniverseEvent>);
 
+/// Actions produced from events during the current step, drained when they are handled.
 #[derive(Default, Clone, Serialize, Deserialize)]
 pub(crate) struct PendingActionsRes(pub(crate) Vec<Action>);
 
@@ -36,14 +46,11 @@ pub(crate) fn system_handle_pending_events<'a>(
                 let vessel = default_vessel.0;
                 if player_map.get(player_id).is_none() {
                     info!("Creating player for {player_id:?}");
-                    commands.submit(move |world| {
-                        let ent = world.spawn(Player {
-                            position: Vec3::new(0.0, 10.0, 0.0),
-                            vessel,
-                        });
-                        world.resource_mut::<PlayerMap>().create(player_id, ent);
-                        info!("Created player for {player_id:?}");
+                    let ent = commands.spawn(Player {
+                        position: Vec3::new(0.0, 10.0, 0.0),
+                        vessel,
                     });
+                    player_map.create(player_id, ent);
                 }
             }
             UniverseEvent::PlayerMoved { new_position } => {
@@ -116,9 +123,12 @@ pub(crate) fn system_handle_actions<'a>(
     evctx: &mut UiEventCtx,
     mut players: Query<'a, &'a mut Player>,
     mut vessels: Query<'a, &'a mut VesselTiles>,
+    buildings: Index<Building, (VesselID, TilePos)>,
     commands: Commands,
     actions: &mut PendingActionsRes,
 ) {
+    // Buildings are spawned by commands, so the index doesn't know about ones placed during this step.
+    let mut claimed = HashSet::new();
     for action in actions.0.drain(..) {
         match action {
             Action::MovePlayer {
@@ -159,20 +169,22 @@ pub(crate) fn system_handle_actions<'a>(
                 orientation,
                 kind,
             } => {
-                commands.submit(move |world| {
-                    world.spawn(Building {
-                        position,
-                        orientation,
-                        kind,
-                        vessel,
-                    });
+                if buildings.contains_key(&(vessel, position))
+                    || !claimed.insert((vessel, position))
+                {
+                    info!("Tile {position:?} is already occupied by a building");
+                    continue;
+                }
+                commands.spawn(Building {
+                    position,
+                    orientation,
+                    kind,
+                    vessel,
                 });
                 evctx.any_vessel_changed = true;
             }
             Action::RemoveBuilding { entity } => {
-                commands.submit(move |world| {
-                    world.despawn(entity);
-                });
+                commands.despawn(entity);
                 evctx.any_vessel_changed = true;
             }
         }
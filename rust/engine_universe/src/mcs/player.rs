@@ -25,4 +25,8 @@ impl PlayerMap {
     pub fn create(&mut self, id: PlayerID, ent: EntityID) {
         self.0.insert(id, ent);
     }
+    /// Removes the player whose entity is `ent`, called once it loses `Player` component.
+    pub fn forget(&mut self, ent: EntityID) {
+        self.0.retain(|_id, player_ent| *player_ent != ent);
+    }
 }
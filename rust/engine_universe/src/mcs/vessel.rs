@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::tilemap::{Tile, TileMap};
 
-#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub struct VesselID(pub EntityID);
 
 #[derive(Serialize, Deserialize, Clone)]
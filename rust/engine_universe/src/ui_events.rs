@@ -1,3 +1,4 @@
+use engine_ecs::EntityID;
 use serde::{Deserialize, Serialize};
 
 use super::tilemap::TilePos;
@@ -6,4 +7,8 @@ use super::tilemap::TilePos;
 pub struct UiEventCtx {
     pub tiles_changed: Vec<TilePos>,
     pub any_vessel_changed: bool,
+    /// Buildings spawned since the last ui update, possibly despawned again since.
+    pub buildings_spawned: Vec<EntityID>,
+    /// Buildings despawned since the last ui update.
+    pub buildings_despawned: Vec<EntityID>,
 }